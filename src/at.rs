@@ -0,0 +1,698 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use crate::device::{Device, DeviceContext};
+use crate::framer::{Framing, FramingSpec};
+
+/// Длина командной строки, после которой недописанная строка отбрасывается.
+const MAX_LINE: usize = 1024;
+
+/// Вид команды: выполнение (`+CSQ`), чтение (`+CREG?`), проверка (`+CREG=?`) или установка
+/// с аргументами (`+CREG=1`, `E0`, `S7=30`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum AtKind {
+    Exec,
+    Read,
+    Test,
+    /// Аргументы через запятую, без кавычек.
+    Set(Vec<String>),
+}
+
+/// Одна команда из командной строки. Имя в верхнем регистре: `+CSQ`, `E`, `&F`, `S7`, `D`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtCommand {
+    pub name: String,
+    pub kind: AtKind,
+}
+
+impl AtCommand {
+    /// Числовой аргумент базовой команды (`E1`, `S7=30`); без аргумента — 0, как в V.250.
+    pub fn number(&self) -> Option<u32> {
+        match &self.kind {
+            AtKind::Exec => Some(0),
+            AtKind::Set(args) if args.len() == 1 => args[0].parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Аргумент установки с номером `index`.
+    pub fn arg(&self, index: usize) -> Option<&str> {
+        match &self.kind {
+            AtKind::Set(args) => args.get(index).map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
+/// Итоговый код ответа.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResultCode {
+    Ok,
+    Connect,
+    Ring,
+    NoCarrier,
+    Error,
+    NoDialtone,
+    Busy,
+    NoAnswer,
+    /// `+CME ERROR: <n>`; при `AT+CMEE=0` выводится как `ERROR`.
+    CmeError(u16),
+    /// Итоговый ответ, своеобразный для модема, например `SHUT OK` у SIM800.
+    Text(String),
+    /// Без итогового ответа (например, `AT+CIFSR` у SIM800 выдаёт только адрес).
+    None,
+    /// Приглашение `> `: следующие байты передаются набору команд как данные (`AT+CIPSEND`).
+    Prompt,
+}
+
+impl ResultCode {
+    /// Код для `ATV0` и текст для `ATV1`.
+    fn numeric(&self) -> Option<u8> {
+        match self {
+            ResultCode::Ok => Some(0),
+            ResultCode::Connect => Some(1),
+            ResultCode::Ring => Some(2),
+            ResultCode::NoCarrier => Some(3),
+            ResultCode::Error | ResultCode::CmeError(_) => Some(4),
+            ResultCode::NoDialtone => Some(6),
+            ResultCode::Busy => Some(7),
+            ResultCode::NoAnswer => Some(8),
+            ResultCode::Text(_) | ResultCode::None | ResultCode::Prompt => None,
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            ResultCode::Ok => "OK",
+            ResultCode::Connect => "CONNECT",
+            ResultCode::Ring => "RING",
+            ResultCode::NoCarrier => "NO CARRIER",
+            ResultCode::Error | ResultCode::CmeError(_) => "ERROR",
+            ResultCode::NoDialtone => "NO DIALTONE",
+            ResultCode::Busy => "BUSY",
+            ResultCode::NoAnswer => "NO ANSWER",
+            ResultCode::Text(text) => text,
+            ResultCode::None => "",
+            ResultCode::Prompt => ">",
+        }
+    }
+}
+
+/// Ответ на команду: информационные строки и итоговый код.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtResponse {
+    pub lines: Vec<String>,
+    pub result: ResultCode,
+}
+
+impl AtResponse {
+    pub fn ok() -> Self {
+        AtResponse { lines: Vec::new(), result: ResultCode::Ok }
+    }
+
+    /// Информационные строки и `OK`.
+    pub fn info(lines: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        AtResponse { lines: lines.into_iter().map(Into::into).collect(), result: ResultCode::Ok }
+    }
+
+    pub fn error() -> Self {
+        AtResponse { lines: Vec::new(), result: ResultCode::Error }
+    }
+
+    pub fn cme(code: u16) -> Self {
+        AtResponse { lines: Vec::new(), result: ResultCode::CmeError(code) }
+    }
+
+    pub fn code(result: ResultCode) -> Self {
+        AtResponse { lines: Vec::new(), result }
+    }
+}
+
+/// Настройки модема, влияющие на формат ответов.
+#[derive(Clone, Debug)]
+pub struct ModemState {
+    /// `ATE`: эхо командной строки.
+    pub echo: bool,
+    /// `ATV`: текстовые (1) или числовые (0) коды ответов.
+    pub verbose: bool,
+    /// `ATQ1`: коды ответов не выводятся.
+    pub quiet: bool,
+    /// `AT+CMEE`: 0 — `ERROR`, 1 — числовой, 2 — текстовый `+CME ERROR`.
+    pub cmee: u8,
+    pub s_registers: [u8; 256],
+}
+
+impl Default for ModemState {
+    fn default() -> Self {
+        let mut s_registers = [0u8; 256];
+        // S2 escape-символ, S3/S4 окончание строки, S5 backspace, S6–S12 таймауты по V.250
+        for (index, value) in [(2, b'+'), (3, b'\r'), (4, b'\n'), (5, 8), (6, 2), (7, 60), (8, 2), (10, 15), (12, 50)] {
+            s_registers[index] = value;
+        }
+        ModemState { echo: true, verbose: true, quiet: false, cmee: 0, s_registers }
+    }
+}
+
+impl ModemState {
+    fn eol(&self) -> String {
+        format!("{}{}", self.s_registers[3] as char, self.s_registers[4] as char)
+    }
+
+    /// Информационные строки в формате `ATV1` (`\r\n<text>\r\n`) или `ATV0` (`<text>\r\n`).
+    pub fn format_lines(&self, lines: &[String]) -> String {
+        let eol = self.eol();
+        let mut out = String::new();
+        if self.verbose && !lines.is_empty() {
+            out.push_str(&eol);
+        }
+        for line in lines {
+            out.push_str(line);
+            out.push_str(&eol);
+        }
+        out
+    }
+
+    /// Итоговый код: `\r\nOK\r\n` или `0\r`; при `ATQ1` — пусто.
+    pub fn format_result(&self, result: &ResultCode) -> String {
+        // Приглашение не является кодом ответа и выводится и при `ATQ1`
+        if *result == ResultCode::Prompt {
+            return match self.verbose {
+                true => format!("{}> ", self.eol()),
+                false => "> ".to_string(),
+            };
+        }
+        if self.quiet || *result == ResultCode::None {
+            return String::new();
+        }
+        let eol = self.eol();
+        let text = match result {
+            ResultCode::CmeError(code) if self.cmee == 1 => format!("+CME ERROR: {}", code),
+            ResultCode::CmeError(code) if self.cmee == 2 => format!("+CME ERROR: {}", cme_text(*code)),
+            result => match (self.verbose, result.numeric()) {
+                (false, Some(code)) => return format!("{}{}", code, self.s_registers[3] as char),
+                _ => result.text().to_string(),
+            },
+        };
+        match self.verbose {
+            true => format!("{}{}{}", eol, text, eol),
+            false => format!("{}{}", text, eol),
+        }
+    }
+
+    /// Незапрошенное сообщение (URC), например `+CREG: 1` или `RING`.
+    pub fn format_urc(&self, text: &str) -> Vec<u8> {
+        self.format_lines(&[text.to_string()]).into_bytes()
+    }
+}
+
+fn cme_text(code: u16) -> &'static str {
+    match code {
+        3 => "operation not allowed",
+        4 => "operation not supported",
+        10 => "SIM not inserted",
+        11 => "SIM PIN required",
+        16 => "incorrect password",
+        30 => "no network service",
+        _ => "unknown",
+    }
+}
+
+/// Набор команд, подключаемый к интерпретатору. Наборы опрашиваются по порядку; первый,
+/// вернувший ответ, обрабатывает команду. Если команду не знает ни один набор, ответ — `ERROR`.
+pub trait AtCommandSet: Send {
+    /// Выполняет команду или возвращает `None`, если она не относится к набору.
+    fn execute(&mut self, command: &AtCommand, modem: &mut ModemState, ctx: &DeviceContext) -> Option<AtResponse>;
+
+    /// Принимает данные после приглашения `> `, которое вернул `execute`. Обработанные байты
+    /// удаляются из буфера; `true` завершает ввод данных. Пока ввод не завершён, набор сам
+    /// ограничивает размер буфера.
+    fn on_data(&mut self, _buffer: &mut Vec<u8>, _modem: &mut ModemState, _ctx: &DeviceContext) -> bool {
+        true
+    }
+
+    /// Вызывается потоком чтения не реже раза в 10 мс: для приёма из сокетов и сообщений
+    /// по событиям.
+    fn on_tick(&mut self, _modem: &ModemState, _ctx: &DeviceContext) {}
+
+    /// Возврат к заводским настройкам (`ATZ`, `AT&F`).
+    fn reset(&mut self) {}
+}
+
+/// Интерпретатор AT-команд (Hayes V.250 и 3GPP 27.007): эхо, коды ответов, S-регистры,
+/// цепочки команд (`AT+CSQ;+CREG?`) и `A/`. Сами команды выполняют подключённые наборы.
+pub struct AtModem {
+    name: &'static str,
+    state: ModemState,
+    sets: Vec<Box<dyn AtCommandSet>>,
+    last_line: Option<String>,
+    /// Принятые байты, ещё не сложившиеся в командную строку.
+    buffer: Vec<u8>,
+    /// Набор команд, принимающий данные после приглашения `> `.
+    data_mode: Option<usize>,
+}
+
+impl AtModem {
+    pub fn new(name: &'static str, sets: Vec<Box<dyn AtCommandSet>>) -> Self {
+        AtModem { name, state: ModemState::default(), sets, last_line: None, buffer: Vec::new(), data_mode: None }
+    }
+
+    /// Добавляет набор команд с наивысшим приоритетом.
+    pub fn push_front(&mut self, set: Box<dyn AtCommandSet>) {
+        self.sets.insert(0, set);
+    }
+
+    pub fn state(&self) -> &ModemState {
+        &self.state
+    }
+
+    /// Выделяет из буфера командную строку от `AT` до символа S3 с учётом S5 (backspace).
+    /// `A/` повторяет предыдущую строку сразу, без S3; символы вне строк отбрасываются.
+    fn next_line(&mut self) -> Option<String> {
+        loop {
+            let Some(start) = self.buffer.iter().position(|byte| byte.eq_ignore_ascii_case(&b'A')) else {
+                self.buffer.clear();
+                return None;
+            };
+            self.buffer.drain(..start);
+            match self.buffer.get(1)?.to_ascii_uppercase() {
+                b'T' => break,
+                b'/' => {
+                    self.buffer.drain(..2);
+                    if let Some(line) = self.last_line.clone() {
+                        return Some(line);
+                    }
+                }
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        let end = self.buffer.iter().position(|&byte| byte == self.state.s_registers[3])?;
+        let mut line = Vec::new();
+        for byte in self.buffer.drain(..=end).take(end) {
+            if byte == self.state.s_registers[5] {
+                line.pop();
+            } else {
+                line.push(byte);
+            }
+        }
+        // Строка, в которой стёрт сам префикс, не выполняется
+        let line = String::from_utf8_lossy(&line).into_owned();
+        match line.get(..2).is_some_and(|prefix| prefix.eq_ignore_ascii_case("AT")) {
+            true => Some(line),
+            false => self.next_line(),
+        }
+    }
+
+    /// Выполняет командную строку без префикса `AT` и возвращает текст ответа.
+    fn run_line(&mut self, body: &str, ctx: &DeviceContext) -> (String, ResultCode) {
+        let commands = match parse_command_line(body) {
+            Ok(commands) => commands,
+            Err(_) => return (self.state.format_result(&ResultCode::Error), ResultCode::Error),
+        };
+        let mut out = String::new();
+        let mut result = ResultCode::Ok;
+        for command in &commands {
+            let response = self.execute(command, ctx);
+            out.push_str(&self.state.format_lines(&response.lines));
+            result = response.result;
+            // Ошибка или особый итоговый ответ прерывают цепочку; промежуточные `OK` не выводятся
+            if result != ResultCode::Ok {
+                break;
+            }
+        }
+        out.push_str(&self.state.format_result(&result));
+        (out, result)
+    }
+
+    fn execute(&mut self, command: &AtCommand, ctx: &DeviceContext) -> AtResponse {
+        if let Some(response) = self.execute_setting(command) {
+            return response;
+        }
+        for (index, set) in self.sets.iter_mut().enumerate() {
+            if let Some(response) = set.execute(command, &mut self.state, ctx) {
+                if response.result == ResultCode::Prompt {
+                    self.data_mode = Some(index);
+                }
+                return response;
+            }
+        }
+        AtResponse::error()
+    }
+
+    /// Команды, меняющие формат ответов: `E`, `V`, `Q`, `Z`, `&F`, `S<n>`, `+CMEE`.
+    fn execute_setting(&mut self, command: &AtCommand) -> Option<AtResponse> {
+        let flag = |command: &AtCommand| match command.number() {
+            Some(value @ (0 | 1)) => Ok(value == 1),
+            _ => Err(AtResponse::error()),
+        };
+        let response = match command.name.as_str() {
+            "E" => flag(command).map(|on| self.state.echo = on),
+            "V" => flag(command).map(|on| self.state.verbose = on),
+            "Q" => flag(command).map(|on| self.state.quiet = on),
+            "Z" | "&F" => {
+                self.state = ModemState::default();
+                self.sets.iter_mut().for_each(|set| set.reset());
+                Ok(())
+            }
+            "+CMEE" => match &command.kind {
+                AtKind::Read => return Some(AtResponse::info([format!("+CMEE: {}", self.state.cmee)])),
+                AtKind::Test => return Some(AtResponse::info(["+CMEE: (0-2)"])),
+                _ => match command.number() {
+                    Some(value @ 0..=2) => {
+                        self.state.cmee = value as u8;
+                        Ok(())
+                    }
+                    _ => Err(AtResponse::error()),
+                },
+            },
+            name if name.starts_with('S') && name[1..].parse::<u8>().is_ok() => {
+                let index: usize = name[1..].parse::<u8>().unwrap() as usize;
+                match &command.kind {
+                    AtKind::Read => return Some(AtResponse::info([format!("{:03}", self.state.s_registers[index])])),
+                    AtKind::Set(args) => match args.first().and_then(|value| value.parse::<u8>().ok()) {
+                        Some(value) => {
+                            self.state.s_registers[index] = value;
+                            Ok(())
+                        }
+                        None => Err(AtResponse::error()),
+                    },
+                    // `ATS7` без `=` или `?` только выбирает регистр
+                    _ => Ok(()),
+                }
+            }
+            _ => return None,
+        };
+        Some(response.map_or_else(|error| error, |_| AtResponse::ok()))
+    }
+}
+
+impl Device for AtModem {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn framing(&self, _baud_rate: u32) -> FramingSpec {
+        // Строки выделяет сам модем по S3 и S5; пауза только отделяет порции данных
+        FramingSpec { framing: Framing::Idle(Duration::from_millis(2)), escape: None, checksum: None }
+    }
+
+    fn on_frame(&mut self, frame: &[u8], ctx: &DeviceContext) -> bool {
+        // Эхо идёт по мере приёма, до выполнения команды, как у настоящего модема
+        if self.state.echo {
+            ctx.send(frame.to_vec());
+        }
+        self.buffer.extend_from_slice(frame);
+        loop {
+            if let Some(index) = self.data_mode {
+                if !self.sets[index].on_data(&mut self.buffer, &mut self.state, ctx) {
+                    return true;
+                }
+                self.data_mode = None;
+            }
+            if self.buffer.len() > MAX_LINE {
+                self.buffer.clear();
+            }
+            let Some(line) = self.next_line() else {
+                return true;
+            };
+            let (response, result) = self.run_line(&line[2..], ctx);
+            let result = if result == ResultCode::None { "(no result code)" } else { result.text() };
+            ctx.log(&format!("[AT] {} -> {}", line, result));
+            self.last_line = Some(line);
+            ctx.send(response.into_bytes());
+        }
+    }
+
+    fn on_tick(&mut self, _now: Instant, ctx: &DeviceContext) {
+        for set in &mut self.sets {
+            set.on_tick(&self.state, ctx);
+        }
+    }
+}
+
+/// Разбирает командную строку после `AT`: базовые команды идут подряд (`E0V1`), расширенные
+/// разделяются `;` (`+CSQ;+CREG?`). Пробелы вне кавычек игнорируются.
+pub fn parse_command_line(body: &str) -> Result<Vec<AtCommand>, String> {
+    let bytes = body.as_bytes();
+    let mut commands = Vec::new();
+    let mut i = 0;
+    let number_at = |i: &mut usize| {
+        let start = *i;
+        while *i < bytes.len() && bytes[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        (start != *i).then(|| body[start..*i].to_string())
+    };
+    while i < bytes.len() {
+        let c = bytes[i].to_ascii_uppercase();
+        match c {
+            b' ' | b';' => i += 1,
+            b'+' | b'*' | b'#' | b'^' | b'$' => {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || b"!%-./:_".contains(&bytes[i])) {
+                    i += 1;
+                }
+                if i - start < 2 {
+                    return Err(format!("empty command name at '{}'", &body[start..]));
+                }
+                let name = body[start..i].to_ascii_uppercase();
+                let kind = if body[i..].starts_with("=?") {
+                    i += 2;
+                    AtKind::Test
+                } else if body[i..].starts_with('?') {
+                    i += 1;
+                    AtKind::Read
+                } else if body[i..].starts_with('=') {
+                    let end = find_unquoted(body, i + 1, b';');
+                    let args = split_args(&body[i + 1..end]);
+                    i = end;
+                    AtKind::Set(args)
+                } else {
+                    AtKind::Exec
+                };
+                commands.push(AtCommand { name, kind });
+            }
+            b'&' => {
+                let letter = bytes.get(i + 1).filter(|b| b.is_ascii_alphabetic()).ok_or("missing command after '&'")?;
+                i += 2;
+                let name = format!("&{}", letter.to_ascii_uppercase() as char);
+                let kind = number_at(&mut i).map_or(AtKind::Exec, |n| AtKind::Set(vec![n]));
+                commands.push(AtCommand { name, kind });
+            }
+            b'S' => {
+                i += 1;
+                let index = number_at(&mut i).ok_or("missing S-register number")?;
+                let kind = match bytes.get(i) {
+                    Some(b'?') => {
+                        i += 1;
+                        AtKind::Read
+                    }
+                    Some(b'=') => {
+                        i += 1;
+                        AtKind::Set(vec![number_at(&mut i).unwrap_or_default()])
+                    }
+                    _ => AtKind::Exec,
+                };
+                commands.push(AtCommand { name: format!("S{}", index), kind });
+            }
+            // Набор номера занимает остаток строки, включая `;` голосового вызова
+            b'D' => {
+                commands.push(AtCommand { name: "D".to_string(), kind: AtKind::Set(vec![body[i + 1..].trim().to_string()]) });
+                i = bytes.len();
+            }
+            c if c.is_ascii_alphabetic() => {
+                i += 1;
+                let kind = number_at(&mut i).map_or(AtKind::Exec, |n| AtKind::Set(vec![n]));
+                commands.push(AtCommand { name: (c as char).to_string(), kind });
+            }
+            _ => return Err(format!("unexpected character '{}'", c as char)),
+        }
+    }
+    Ok(commands)
+}
+
+/// Позиция символа `target` вне кавычек, начиная с `from`, или конец строки.
+fn find_unquoted(text: &str, from: usize, target: u8) -> usize {
+    let mut quoted = false;
+    for (offset, &byte) in text.as_bytes()[from..].iter().enumerate() {
+        match byte {
+            b'"' => quoted = !quoted,
+            byte if byte == target && !quoted => return from + offset,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Делит аргументы по запятым вне кавычек и снимает кавычки.
+fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = find_unquoted(text, start, b',');
+        args.push(text[start..end].trim().trim_matches('"').to_string());
+        if end >= text.len() {
+            break;
+        }
+        start = end + 1;
+    }
+    args
+}
+
+/// Набор команд с постоянными ответами из файла TOML.
+///
+/// ```toml
+/// [[at]]
+/// name = "+CBC"
+/// exec = ["+CBC: 0,85,4100"]
+/// test = ["+CBC: (0-2),(1-100),(3000-4500)"]
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct StaticCommands {
+    commands: HashMap<String, StaticCommand>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StaticFile {
+    #[serde(default)]
+    at: Vec<StaticCommand>,
+}
+
+/// Ответы на команду по видам; вид без ответа передаётся следующему набору.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StaticCommand {
+    name: String,
+    exec: Option<Vec<String>>,
+    read: Option<Vec<String>>,
+    test: Option<Vec<String>>,
+    /// Ответ на установку с любыми аргументами.
+    set: Option<Vec<String>>,
+    /// Итоговый ответ вместо `OK`, например `ERROR` или `SHUT OK`.
+    result: Option<String>,
+}
+
+impl StaticCommands {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Cannot open AT command set '{}': {}", path.display(), e)))?;
+        let file: StaticFile = toml::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+        let commands = file.at.into_iter().map(|command| (command.name.to_ascii_uppercase(), command)).collect();
+        Ok(StaticCommands { commands })
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl AtCommandSet for StaticCommands {
+    fn execute(&mut self, command: &AtCommand, _modem: &mut ModemState, _ctx: &DeviceContext) -> Option<AtResponse> {
+        let entry = self.commands.get(&command.name)?;
+        let lines = match command.kind {
+            AtKind::Exec => entry.exec.as_ref(),
+            AtKind::Read => entry.read.as_ref(),
+            AtKind::Test => entry.test.as_ref(),
+            AtKind::Set(_) => entry.set.as_ref(),
+        }?;
+        let result = match entry.result.as_deref() {
+            None | Some("OK") => ResultCode::Ok,
+            Some("ERROR") => ResultCode::Error,
+            Some("") => ResultCode::None,
+            Some(text) => ResultCode::Text(text.to_string()),
+        };
+        Some(AtResponse { lines: lines.clone(), result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(name: &str, kind: AtKind) -> AtCommand {
+        AtCommand { name: name.to_string(), kind }
+    }
+
+    fn set(args: &[&str]) -> AtKind {
+        AtKind::Set(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn parses_chained_extended_commands() {
+        let commands = parse_command_line("+CSQ;+creg?").unwrap();
+        assert_eq!(commands, vec![command("+CSQ", AtKind::Exec), command("+CREG", AtKind::Read)]);
+        let commands = parse_command_line("+CREG=?; +CMGS=\"+7900;1\",145").unwrap();
+        assert_eq!(commands, vec![command("+CREG", AtKind::Test), command("+CMGS", set(&["+7900;1", "145"]))]);
+    }
+
+    #[test]
+    fn parses_basic_commands() {
+        let commands = parse_command_line("E0V0").unwrap();
+        assert_eq!(commands, vec![command("E", set(&["0"])), command("V", set(&["0"]))]);
+        let commands = parse_command_line("&FS7=30S0?Q").unwrap();
+        assert_eq!(
+            commands,
+            vec![command("&F", AtKind::Exec), command("S7", set(&["30"])), command("S0", AtKind::Read), command("Q", AtKind::Exec)]
+        );
+        // Набор номера забирает остаток строки
+        assert_eq!(parse_command_line("E1D+7900;").unwrap()[1], command("D", set(&["+7900;"])));
+        assert_eq!(parse_command_line("").unwrap(), vec![]);
+        for bad in ["&", "+", "S", "E0!"] {
+            assert!(parse_command_line(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn splits_arguments() {
+        assert_eq!(split_args("1, \"a,b\" ,3"), vec!["1", "a,b", "3"]);
+        assert_eq!(split_args("\"internet\""), vec!["internet"]);
+        assert_eq!(split_args("1,,"), vec!["1", "", ""]);
+        assert_eq!(split_args(""), vec![""]);
+    }
+
+    #[test]
+    fn formats_result_codes() {
+        let mut state = ModemState::default();
+        assert_eq!(state.format_result(&ResultCode::Ok), "\r\nOK\r\n");
+        assert_eq!(state.format_result(&ResultCode::NoCarrier), "\r\nNO CARRIER\r\n");
+        assert_eq!(state.format_result(&ResultCode::Prompt), "\r\n> ");
+        assert_eq!(state.format_lines(&["+CSQ: 20,0".to_string()]), "\r\n+CSQ: 20,0\r\n");
+        state.verbose = false;
+        assert_eq!(state.format_result(&ResultCode::Ok), "0\r");
+        assert_eq!(state.format_result(&ResultCode::Error), "4\r");
+        assert_eq!(state.format_result(&ResultCode::CmeError(10)), "4\r");
+        assert_eq!(state.format_result(&ResultCode::Text("SHUT OK".to_string())), "SHUT OK\r\n");
+        assert_eq!(state.format_lines(&["+CSQ: 20,0".to_string()]), "+CSQ: 20,0\r\n");
+        // `ATQ1` скрывает коды ответов, но не приглашение
+        state.quiet = true;
+        assert_eq!(state.format_result(&ResultCode::Ok), "");
+        assert_eq!(state.format_result(&ResultCode::Prompt), "> ");
+    }
+
+    #[test]
+    fn cmee_selects_error_format() {
+        let mut modem = AtModem::new("test", Vec::new());
+        let mut run = |line: &str| {
+            let commands = parse_command_line(line).unwrap();
+            modem.execute_setting(&commands[0]).unwrap();
+            modem.state.format_result(&ResultCode::CmeError(10))
+        };
+        assert_eq!(run("+CMEE=0"), "\r\nERROR\r\n");
+        assert_eq!(run("+CMEE=1"), "\r\n+CME ERROR: 10\r\n");
+        assert_eq!(run("+CMEE=2"), "\r\n+CME ERROR: SIM not inserted\r\n");
+        assert_eq!(run("V0"), "+CME ERROR: SIM not inserted\r\n");
+        assert_eq!(modem.execute_setting(&command("+CMEE", set(&["3"]))), Some(AtResponse::error()));
+        assert_eq!(modem.state.cmee, 2);
+    }
+}
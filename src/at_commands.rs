@@ -0,0 +1,166 @@
+use crate::at::{AtCommand, AtCommandSet, AtKind, AtModem, AtResponse, ModemState, ResultCode};
+use crate::device::DeviceContext;
+
+/// Модем с базовыми командами V.250 и общими командами 3GPP 27.007.
+pub fn hayes_modem() -> AtModem {
+    AtModem::new(
+        "Hayes AT modem",
+        vec![
+            Box::new(GsmCommands::new("virtualport", "Virtual GSM modem", env!("CARGO_PKG_VERSION"))),
+            Box::new(BasicCommands::new(&["virtualport AT modem"])),
+        ],
+    )
+}
+
+/// Базовые команды V.250, не меняющие формат ответов: `I`, `H`, `A`, `D`, `O`, `X`, `L`, `M`,
+/// `&C`, `&D`, `&W`. Данных вызовов нет, поэтому `ATD`, `ATA` и `ATO` отвечают `NO CARRIER`,
+/// а голосовой вызов (`ATD<номер>;`) — `OK`.
+pub struct BasicCommands {
+    identification: Vec<String>,
+}
+
+impl BasicCommands {
+    /// `identification` — ответ на `ATI`.
+    pub fn new(identification: &[&str]) -> Self {
+        BasicCommands { identification: identification.iter().map(|line| line.to_string()).collect() }
+    }
+}
+
+impl AtCommandSet for BasicCommands {
+    fn execute(&mut self, command: &AtCommand, _modem: &mut ModemState, _ctx: &DeviceContext) -> Option<AtResponse> {
+        let response = match command.name.as_str() {
+            "I" => match command.number() {
+                Some(0) => AtResponse::info(self.identification.clone()),
+                _ => AtResponse::error(),
+            },
+            "D" => match command.arg(0) {
+                Some(number) if number.ends_with(';') => AtResponse::ok(),
+                Some(number) if !number.is_empty() => AtResponse::code(ResultCode::NoCarrier),
+                _ => AtResponse::error(),
+            },
+            "A" | "O" => AtResponse::code(ResultCode::NoCarrier),
+            "H" | "X" | "L" | "M" | "&C" | "&D" | "&W" => match command.number() {
+                Some(_) => AtResponse::ok(),
+                None => AtResponse::error(),
+            },
+            _ => return None,
+        };
+        Some(response)
+    }
+}
+
+/// Общие команды GSM-модема по 3GPP 27.007: идентификация, SIM, уровень сигнала,
+/// регистрация в сети и подключение к GPRS.
+pub struct GsmCommands {
+    manufacturer: &'static str,
+    model: &'static str,
+    revision: &'static str,
+    creg: u8,
+    cgreg: u8,
+    cfun: u8,
+    cgatt: u8,
+    cmgf: u8,
+}
+
+impl GsmCommands {
+    pub fn new(manufacturer: &'static str, model: &'static str, revision: &'static str) -> Self {
+        GsmCommands { manufacturer, model, revision, creg: 0, cgreg: 0, cfun: 1, cgatt: 1, cmgf: 0 }
+    }
+
+    /// Модем в сети домашнего оператора, пока не выключен радиомодуль (`AT+CFUN=0/4`).
+    fn registration(&self) -> u8 {
+        if self.cfun == 1 { 1 } else { 0 }
+    }
+}
+
+/// Код области и соты, выводимые при `AT+CREG=2`.
+fn location(mode: u8, registration: u8) -> &'static str {
+    if mode == 2 && registration == 1 { ",\"1A2B\",\"3C4D\"" } else { "" }
+}
+
+/// Чтение, проверка и установка параметра из диапазона `0..=max`.
+fn setting(command: &AtCommand, value: &mut u8, max: u8, read: String, test: &str) -> AtResponse {
+    match &command.kind {
+        AtKind::Read => AtResponse::info([read]),
+        AtKind::Test => AtResponse::info([test]),
+        AtKind::Set(_) => match command.arg(0).and_then(|arg| arg.parse::<u8>().ok()) {
+            Some(new) if new <= max => {
+                *value = new;
+                AtResponse::ok()
+            }
+            _ => AtResponse::cme(3),
+        },
+        AtKind::Exec => AtResponse::cme(4),
+    }
+}
+
+impl AtCommandSet for GsmCommands {
+    fn execute(&mut self, command: &AtCommand, _modem: &mut ModemState, _ctx: &DeviceContext) -> Option<AtResponse> {
+        let exec = |lines: &[&str]| match command.kind {
+            AtKind::Exec => AtResponse::info(lines.iter().copied()),
+            AtKind::Test => AtResponse::ok(),
+            _ => AtResponse::cme(4),
+        };
+        let registration = self.registration();
+        let response = match command.name.as_str() {
+            "+CGMI" | "+GMI" => exec(&[self.manufacturer]),
+            "+CGMM" | "+GMM" => exec(&[self.model]),
+            "+CGMR" | "+GMR" => exec(&[self.revision]),
+            "+CGSN" | "+GSN" => exec(&["867567040000000"]),
+            "+CIMI" => exec(&["250010000000000"]),
+            "+CSQ" => match command.kind {
+                AtKind::Exec if registration == 1 => AtResponse::info(["+CSQ: 23,99"]),
+                AtKind::Exec => AtResponse::info(["+CSQ: 99,99"]),
+                AtKind::Test => AtResponse::info(["+CSQ: (0-31,99),(0-7,99)"]),
+                _ => AtResponse::cme(4),
+            },
+            "+CPIN" => match &command.kind {
+                AtKind::Read => AtResponse::info(["+CPIN: READY"]),
+                AtKind::Test | AtKind::Set(_) => AtResponse::ok(),
+                AtKind::Exec => AtResponse::cme(4),
+            },
+            "+CREG" => {
+                let read = format!("+CREG: {},{}{}", self.creg, registration, location(self.creg, registration));
+                setting(command, &mut self.creg, 2, read, "+CREG: (0-2)")
+            }
+            "+CGREG" => {
+                let read = format!("+CGREG: {},{}{}", self.cgreg, registration, location(self.cgreg, registration));
+                setting(command, &mut self.cgreg, 2, read, "+CGREG: (0-2)")
+            }
+            "+CFUN" => match &command.kind {
+                AtKind::Read => AtResponse::info([format!("+CFUN: {}", self.cfun)]),
+                AtKind::Test => AtResponse::info(["+CFUN: (0,1,4),(0,1)"]),
+                // Второй аргумент (перезагрузка) принимается, но ничего не меняет
+                AtKind::Set(_) => match command.arg(0).and_then(|arg| arg.parse::<u8>().ok()) {
+                    Some(value @ (0 | 1 | 4)) => {
+                        self.cfun = value;
+                        AtResponse::ok()
+                    }
+                    _ => AtResponse::cme(3),
+                },
+                AtKind::Exec => AtResponse::cme(4),
+            },
+            "+CGATT" => {
+                let read = format!("+CGATT: {}", self.cgatt * registration);
+                setting(command, &mut self.cgatt, 1, read, "+CGATT: (0,1)")
+            }
+            "+CMGF" => {
+                let read = format!("+CMGF: {}", self.cmgf);
+                setting(command, &mut self.cmgf, 1, read, "+CMGF: (0,1)")
+            }
+            "+COPS" => match &command.kind {
+                AtKind::Read if registration == 1 => AtResponse::info(["+COPS: 0,0,\"MyOperator\""]),
+                AtKind::Read => AtResponse::info(["+COPS: 0"]),
+                AtKind::Test => AtResponse::info(["+COPS: (2,\"MyOperator\",\"MyOp\",\"25001\"),,(0-4),(0-2)"]),
+                AtKind::Set(_) => AtResponse::ok(),
+                AtKind::Exec => AtResponse::cme(4),
+            },
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    fn reset(&mut self) {
+        *self = GsmCommands::new(self.manufacturer, self.model, self.revision);
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::hex::parse_hex;
+
+/// Алгоритм контрольной суммы. Сумма дописывается в конец кадра в порядке байтов,
+/// принятом в соответствующих протоколах.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Checksum {
+    /// CRC-16/MODBUS, младший байт первым.
+    Crc16Modbus,
+    /// CRC-16/CCITT-FALSE (полином 0x1021, начальное значение 0xFFFF), старший байт первым.
+    CrcCcitt,
+    /// CRC-32 (IEEE 802.3), младший байт первым.
+    Crc32,
+    /// LRC (Modbus ASCII): дополнение суммы байтов до нуля.
+    Lrc,
+    /// Исключающее ИЛИ всех байтов.
+    Xor,
+    /// 8-битный Fletcher (u-blox UBX): байты CK_A, CK_B.
+    Fletcher8,
+}
+
+impl Checksum {
+    pub const ALL: [Checksum; 6] = [
+        Checksum::Crc16Modbus,
+        Checksum::CrcCcitt,
+        Checksum::Crc32,
+        Checksum::Lrc,
+        Checksum::Xor,
+        Checksum::Fletcher8,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Checksum::Crc16Modbus => "crc16_modbus",
+            Checksum::CrcCcitt => "crc_ccitt",
+            Checksum::Crc32 => "crc32",
+            Checksum::Lrc => "lrc",
+            Checksum::Xor => "xor",
+            Checksum::Fletcher8 => "fletcher8",
+        }
+    }
+
+    /// Размер контрольной суммы в байтах.
+    pub fn size(&self) -> usize {
+        match self {
+            Checksum::Crc16Modbus | Checksum::CrcCcitt | Checksum::Fletcher8 => 2,
+            Checksum::Crc32 => 4,
+            Checksum::Lrc | Checksum::Xor => 1,
+        }
+    }
+
+    /// Вычисляет контрольную сумму данных в том виде, в котором она передаётся в кадре.
+    pub fn compute(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Checksum::Crc16Modbus => crc16_modbus(data).to_le_bytes().to_vec(),
+            Checksum::CrcCcitt => crc_ccitt(data).to_be_bytes().to_vec(),
+            Checksum::Crc32 => crc32(data).to_le_bytes().to_vec(),
+            Checksum::Lrc => vec![lrc(data)],
+            Checksum::Xor => vec![data.iter().fold(0, |acc, b| acc ^ b)],
+            Checksum::Fletcher8 => fletcher8(data).to_vec(),
+        }
+    }
+
+    /// Дописывает контрольную сумму к данным.
+    pub fn seal(&self, data: &mut Vec<u8>) {
+        let sum = self.compute(data);
+        data.extend_from_slice(&sum);
+    }
+
+    /// Проверяет, что кадр заканчивается верной контрольной суммой.
+    pub fn verify(&self, frame: &[u8]) -> bool {
+        match frame.len().checked_sub(self.size()) {
+            Some(split) => self.compute(&frame[..split]) == frame[split..],
+            None => false,
+        }
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Checksum::ALL.into_iter().find(|checksum| checksum.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = Checksum::ALL.iter().map(Checksum::name).collect();
+            format!("Unknown checksum '{}' (expected one of {})", name, names.join(", "))
+        })
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub fn crc16_modbus(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+pub fn crc_ccitt(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn lrc(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)).wrapping_neg()
+}
+
+pub fn fletcher8(data: &[u8]) -> [u8; 2] {
+    let (mut a, mut b) = (0u8, 0u8);
+    for &byte in data {
+        a = a.wrapping_add(byte);
+        b = b.wrapping_add(a);
+    }
+    [a, b]
+}
+
+/// Разбирает шестнадцатеричные байты с подстановками контрольных сумм:
+/// `"01 03 02 00 2A {crc16_modbus}"` — сумма вычисляется по всем предшествующим байтам.
+pub fn parse_sealed_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        data.extend(parse_hex(&rest[..open])?);
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("Unterminated placeholder in '{}'", text))?;
+        let checksum: Checksum = rest[open + 1..open + close].trim().parse()?;
+        checksum.seal(&mut data);
+        rest = &rest[open + close + 1..];
+    }
+    data.extend(parse_hex(rest)?);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(crc16_modbus(CHECK), 0x4B37);
+        assert_eq!(crc_ccitt(CHECK), 0x29B1);
+        assert_eq!(crc32(CHECK), 0xCBF4_3926);
+        assert_eq!(lrc(CHECK), 0x23);
+        assert_eq!(Checksum::Xor.compute(CHECK), vec![0x31]);
+        // UBX-CFG-PRT (poll): B5 62 06 00 00 00 06 18
+        assert_eq!(fletcher8(&[0x06, 0x00, 0x00, 0x00]), [0x06, 0x18]);
+    }
+
+    #[test]
+    fn byte_order_in_frame() {
+        // Modbus RTU: младший байт CRC первым
+        assert_eq!(Checksum::Crc16Modbus.compute(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]), vec![0xC5, 0xCD]);
+        assert_eq!(Checksum::CrcCcitt.compute(CHECK), vec![0x29, 0xB1]);
+        assert_eq!(Checksum::Crc32.compute(CHECK), vec![0x26, 0x39, 0xF4, 0xCB]);
+    }
+
+    #[test]
+    fn seal_and_verify() {
+        for checksum in Checksum::ALL {
+            let mut frame = CHECK.to_vec();
+            checksum.seal(&mut frame);
+            assert_eq!(frame.len(), CHECK.len() + checksum.size());
+            assert!(checksum.verify(&frame), "{}", checksum);
+            *frame.last_mut().unwrap() ^= 0xFF;
+            assert!(!checksum.verify(&frame), "{}", checksum);
+        }
+        assert!(!Checksum::Crc32.verify(&[0x01, 0x02]));
+    }
+
+    #[test]
+    fn parses_names_and_placeholders() {
+        assert_eq!("crc16_modbus".parse(), Ok(Checksum::Crc16Modbus));
+        assert!("crc8".parse::<Checksum>().is_err());
+        assert_eq!(parse_sealed_hex("01 03 00 00 00 0A {crc16_modbus}"), Ok(vec![0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD]));
+        assert!(parse_sealed_hex("01 {crc16_modbus").is_err());
+    }
+}
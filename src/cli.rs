@@ -1,4 +1,7 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use virtualport::framer::FramingSpec;
+use virtualport::tcp_bridge::ClientPolicy;
+use virtualport::track::TrackSource;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -8,16 +11,19 @@ use clap::Parser;
     long_about = "This program creates a virtual serial port using pseudoterminal (PTY) with configurable baud rate, parity, and logging options. It also supports sending heartbeat messages and managing serial communication through the command-line interface."
 )]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path for the symbolic link to the virtual port (Unix only)
     #[arg(short = 'l', long, default_value = "/tmp/my_virtual_port", help = "Specify the symbolic link path for the virtual serial port.")]
     pub link: String,
 
     /// Enable verbose logging to stdout
-    #[arg(short = 'v', long, default_value_t = false, help = "Enable verbose logging for additional information in the terminal.")]
+    #[arg(short = 'v', long, global = true, default_value_t = false, help = "Enable verbose logging for additional information in the terminal.")]
     pub verbose: bool,
 
     /// Do not disable echo (echo is disabled by default)
-    #[arg(short = 'e', long, default_value_t = false, help = "If enabled, the echo feature will remain active on the slave device.")]
+    #[arg(short = 'e', long, global = true, default_value_t = false, help = "If enabled, the echo feature will remain active on the slave device.")]
     pub enable_echo: bool,
 
     /// Initial message that will be sent to the virtual port upon startup
@@ -25,7 +31,7 @@ pub struct Args {
     pub init_msg: Option<String>,
 
     /// Path to a file for logging communication
-    #[arg(short = 'f', long, help = "Specify a path for logging communication to a file.")]
+    #[arg(short = 'f', long, global = true, help = "Specify a path for logging communication to a file.")]
     pub log_file: Option<String>,
 
     /// Heartbeat interval in seconds
@@ -33,14 +39,155 @@ pub struct Args {
     pub heartbeat: u64,
 
     /// Text for the heartbeat message
-    #[arg(short = 'm', long, default_value = "HEARTBEAT\n", help = "Set the text for the heartbeat message.")]
+    #[arg(short = 'm', long, global = true, default_value = "HEARTBEAT\n", help = "Set the text for the heartbeat message.")]
     pub hb_msg: String,
 
     /// Set the baud rate for the virtual serial port
-    #[arg(short = 'r', long, default_value = "9600", help = "Set the baud rate for the virtual serial port.")]
+    #[arg(short = 'r', long, global = true, default_value = "9600", help = "Set the baud rate for the virtual serial port.")]
     pub baud_rate: String,
 
     /// Set the parity for the serial connection (none, even, odd)
-    #[arg(short = 'p', long, default_value = "none", value_parser = ["none", "even", "odd"], help = "Set the parity for the virtual serial port")]
+    #[arg(short = 'p', long, global = true, default_value = "none", value_parser = ["none", "even", "odd"], help = "Set the parity for the virtual serial port")]
     pub parity: String,
+
+    /// Command files with command/response pairs (repeatable, later files override earlier ones)
+    #[arg(short = 'c', long = "commands", value_name = "PATH", help = "Load commands from a TOML profile or a legacy command file. Can be repeated; later files override earlier ones. Defaults to ./commands.txt if present.")]
+    pub commands: Vec<String>,
+
+    /// Rhai script that computes responses to received lines
+    #[arg(short = 's', long, value_name = "PATH", help = "Load a Rhai script that handles received lines before the command table. The script is reloaded when the file changes.")]
+    pub script: Option<String>,
+
+    /// How received data is split into frames
+    #[arg(long, global = true, value_name = "SPEC", help = "Split received data into frames: line:cr|lf|crlf, idle:<ms>, fixed:<n>, delim:<start>..<end> or length:<offset>,<size>,<le|be>[,<adjust>]. Append ,esc=<byte> for an escape byte (line and delim). Commands are then matched against whole frames.")]
+    pub framing: Option<FramingSpec>,
+
+    /// Built-in device model that answers received frames
+    #[arg(long, value_enum, value_name = "KIND", help = "Emulate a built-in device instead of (or in addition to) the command table.")]
+    pub device: Option<DeviceKind>,
+
+    /// Register map for the Modbus device
+    #[arg(long, value_name = "PATH", help = "Load coils and registers for the Modbus device from a TOML or CSV file. Defaults to slave 1 with 100 zeroed cells in each table.")]
+    pub register_map: Option<String>,
+
+    /// Poll list for the Modbus master
+    #[arg(long, value_name = "PATH", required_if_eq("device", "modbus-master"), help = "Requests the Modbus master sends to the device on the slave end, with their schedule, timeouts and retries (TOML).")]
+    pub poll_list: Option<String>,
+
+    /// Extra commands for the AT modem
+    #[arg(long, value_name = "PATH", help = "Add or override AT modem commands with fixed responses from a TOML file. Checked before the built-in command set.")]
+    pub at_commands: Option<String>,
+
+    /// Serve the virtual port to TCP clients
+    #[arg(long, value_name = "ADDR:PORT", conflicts_with_all = ["framing", "device", "script"], help = "Accept TCP clients on this address and bridge them to the virtual port in raw mode: client bytes are written to the port, data the application writes is sent to the clients. commands.txt is not loaded; explicit --commands still answer lines. The console keeps working.")]
+    pub tcp_listen: Option<String>,
+
+    /// What to do when a second TCP client connects
+    #[arg(long, value_name = "POLICY", default_value = "reject", requires = "tcp_listen", help = "Policy for additional TCP clients: reject (keep the first client), share (serve all clients) or last-wins (disconnect the previous client).")]
+    pub tcp_policy: ClientPolicy,
+
+    /// Position source for the GPS receivers
+    #[arg(long, value_name = "SOURCE", default_value = "static:55.7539,37.6208,150", help = "Position for the NMEA and UBX receivers: static:<lat>,<lon>[,<alt>], line:<lat>,<lon>,<km/h>,<course>, circle:<lat>,<lon>,<radius m>,<km/h>, or a GPX/CSV track file replayed in a loop.")]
+    pub track: TrackSource,
+
+    /// GPS fix rate
+    #[arg(long, alias = "nmea-rate", value_name = "HZ", default_value_t = 1.0, help = "Number of GPS fixes per second (the UBX receiver starts with it until CFG-RATE changes it).")]
+    pub fix_rate: f64,
+
+    /// Track playback speed
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, help = "Replay the track faster (e.g. 10) or slower (e.g. 0.5) than real time.")]
+    pub time_scale: f64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum DeviceKind {
+    /// Modbus RTU slave
+    ModbusRtu,
+    /// Modbus ASCII slave
+    ModbusAscii,
+    /// Modbus RTU master polling the device on the slave end
+    ModbusMaster,
+    /// NMEA 0183 GPS receiver
+    Nmea,
+    /// u-blox receiver speaking the UBX binary protocol
+    Ubx,
+    /// Hayes/3GPP AT command modem
+    At,
+    /// SIM800 GSM modem
+    Sim800,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Convert a legacy command file (alternating command/response lines) to a TOML profile
+    Convert {
+        /// Legacy command file to convert
+        #[arg(help = "Path to the legacy command file.")]
+        input: String,
+
+        /// Output path for the TOML profile (stdout if omitted)
+        #[arg(short = 'o', long, help = "Write the TOML profile to this file instead of stdout.")]
+        output: Option<String>,
+    },
+
+    /// Create two virtual ports wired back-to-back like a null-modem cable
+    Pair {
+        /// Symbolic link for the first port
+        #[arg(long, default_value = "/tmp/ttyV0", help = "Symbolic link path for port A.")]
+        link_a: String,
+
+        /// Symbolic link for the second port
+        #[arg(long, default_value = "/tmp/ttyV1", help = "Symbolic link path for port B.")]
+        link_b: String,
+
+        /// Command files answered on port A
+        #[arg(long, value_name = "PATH", help = "Answer lines written to port A from a TOML profile or legacy command file instead of passing them to B. Can be repeated.")]
+        commands_a: Vec<String>,
+
+        /// Command files answered on port B
+        #[arg(long, value_name = "PATH", help = "Answer lines written to port B from a TOML profile or legacy command file instead of passing them to A. Can be repeated.")]
+        commands_b: Vec<String>,
+
+        /// Heartbeat interval for port A
+        #[arg(long, value_name = "SECS", default_value_t = 0, help = "Send the heartbeat message (--hb-msg) to port A every SECS seconds.")]
+        heartbeat_a: u64,
+
+        /// Heartbeat interval for port B
+        #[arg(long, value_name = "SECS", default_value_t = 0, help = "Send the heartbeat message (--hb-msg) to port B every SECS seconds.")]
+        heartbeat_b: u64,
+
+        /// Hold every frame until it is forwarded, edited or dropped in the console
+        #[arg(long, default_value_t = false, help = "Pause each frame in transit and forward, edit, drop or inject frames from the console. Use --framing to choose what counts as a frame.")]
+        intercept: bool,
+    },
+
+    /// Create several virtual ports on a shared bus, like an RS-485 multidrop line
+    Hub {
+        /// Number of ports on the bus
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u16).range(2..=32), help = "Number of virtual ports connected to the bus (2-32).")]
+        ports: u16,
+
+        /// Symlink prefix for the ports
+        #[arg(long, value_name = "PATH", default_value = "/tmp/ttyBus", help = "Symbolic links are created as <PATH>0, <PATH>1, ...")]
+        link_prefix: String,
+
+        /// Simulate collisions between overlapping transmissions
+        #[arg(long, default_value_t = false, help = "Hold each transmission on the bus for its character time at the configured baud rate and garble data from ports that transmit at the same time.")]
+        collisions: bool,
+    },
+
+    /// Sit between an application and a real serial device and log the traffic both ways
+    Sniff {
+        /// Serial device to forward to
+        #[arg(value_name = "DEVICE", help = "Path to the real serial device (e.g. /dev/ttyUSB0) or any other tty.")]
+        device: String,
+
+        /// Symbolic link the application opens instead of the device
+        #[arg(short = 'l', long, default_value = "/tmp/my_virtual_port", help = "Symbolic link path for the virtual port the application opens.")]
+        link: String,
+
+        /// Hold every frame until it is forwarded, edited or dropped in the console
+        #[arg(long, default_value_t = false, help = "Pause each frame in transit and forward, edit, drop or inject frames from the console. Frames from the application follow --framing; data from the device is held as it arrives.")]
+        intercept: bool,
+    },
 }
@@ -1,27 +1,624 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{self, BufRead};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use rand::Rng;
+use regex::bytes::{Captures, Regex};
 
-/// Загружает команды из файла, где каждая команда и её ответ задаются в соседних строках.
-pub fn load_commands_from_file(filename: &str) -> HashMap<String, String> {
-    let mut commands = HashMap::new();
-    let path = Path::new(filename);
+use crate::hex::{escape_bytes, HexPattern, PatternMatch};
+use crate::profile::load_profile_into;
+use crate::scheduler::Scheduler;
 
-    if !path.exists() {
-        eprintln!("[Warning] Command file '{}' not found. Running without predefined commands.", filename);
-        return commands;
+/// Файл команд, используемый по умолчанию, если пути не заданы явно.
+pub const DEFAULT_COMMANDS_FILE: &str = "commands.txt";
+
+/// Директива подключения другого файла команд.
+const INCLUDE_DIRECTIVE: &str = "@include";
+
+/// Префикс команды, задающей регулярное выражение вместо точного совпадения.
+const REGEX_PREFIX: char = '~';
+
+/// Часть ответа: текст (допускает подстановку групп регулярного выражения) или сырые байты.
+#[derive(Clone, Debug)]
+pub enum ResponsePart {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+/// Задержка ответа: фиксированная или случайная из диапазона.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delay {
+    Fixed(Duration),
+    Range(Duration, Duration),
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Delay::Fixed(Duration::ZERO)
+    }
+}
+
+impl Delay {
+    /// Возвращает конкретное значение задержки.
+    pub fn sample(&self) -> Duration {
+        match *self {
+            Delay::Fixed(delay) => delay,
+            Delay::Range(min, max) if min < max => rand::thread_rng().gen_range(min..=max),
+            Delay::Range(min, _) => min,
+        }
+    }
+}
+
+/// Отдельное сообщение ответа с задержкой относительно предыдущего сообщения.
+#[derive(Clone, Debug)]
+pub struct ResponseMessage {
+    pub part: ResponsePart,
+    pub delay: Delay,
+}
+
+/// Ответ на команду вместе с параметрами отправки.
+#[derive(Clone, Debug, Default)]
+pub struct CommandEntry {
+    pub responses: Vec<ResponseMessage>,
+    pub delay: Delay,
+    /// Размер фрагмента при поблочной отправке (0 — сообщение отправляется целиком).
+    pub chunk_size: usize,
+    /// Пауза между фрагментами.
+    pub chunk_gap: Duration,
+    pub quiet: bool,
+    /// Состояние, в которое эмулятор переходит после команды.
+    pub goto: Option<String>,
+}
+
+impl CommandEntry {
+    /// Ответ из одной текстовой строки, завершённой `\n`.
+    pub fn text(response: &str) -> Self {
+        CommandEntry {
+            responses: vec![ResponseMessage {
+                part: ResponsePart::Text(format!("{}\n", response)),
+                delay: Delay::default(),
+            }],
+            ..CommandEntry::default()
+        }
+    }
+}
+
+/// Сообщение найденного ответа; задержка отсчитывается от отправки предыдущего сообщения.
+#[derive(Clone, Debug)]
+pub struct ReplyMessage {
+    pub delay: Duration,
+    pub data: Vec<u8>,
+}
+
+/// Найденный ответ, готовый к отправке.
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub messages: Vec<ReplyMessage>,
+    pub delay: Duration,
+    pub chunk_size: usize,
+    pub chunk_gap: Duration,
+    pub quiet: bool,
+    pub goto: Option<String>,
+}
+
+impl Reply {
+    /// Ставит сообщения ответа в очередь отправки с учётом задержек и разбиения на фрагменты.
+    pub fn schedule(&self, scheduler: &Scheduler, tag: &'static str) {
+        let mut at = self.delay;
+        for message in &self.messages {
+            at += message.delay;
+            if self.chunk_size == 0 || message.data.len() <= self.chunk_size {
+                scheduler.send_after(at, message.data.clone(), tag);
+                continue;
+            }
+            for chunk in message.data.chunks(self.chunk_size) {
+                scheduler.send_after(at, chunk.to_vec(), tag);
+                at += self.chunk_gap;
+            }
+            at -= self.chunk_gap;
+        }
+    }
+
+    /// Текстовое описание ответа для консоли: сообщения и их задержки.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        for (index, message) in self.messages.iter().enumerate() {
+            let delay = if index == 0 { self.delay + message.delay } else { message.delay };
+            let text = format!("'{}'", escape_bytes(message.data.trim_ascii_end()));
+            if delay.is_zero() {
+                parts.push(text);
+            } else {
+                parts.push(format!("{} after {} ms", text, delay.as_millis()));
+            }
+        }
+        if parts.is_empty() {
+            "nothing".to_string()
+        } else {
+            parts.join(", then ")
+        }
+    }
+}
+
+/// Результат поиска hex-шаблона в потоке принятых байтов.
+#[derive(Debug)]
+pub enum StreamMatch {
+    /// Кадр `start..start + len` совпал с шаблоном.
+    Complete { start: usize, len: usize, reply: Reply },
+    /// С позиции `start` может начинаться кадр, для которого ещё не хватает байтов.
+    Partial(usize),
+    None,
+}
+
+/// Набор команд: точные совпадения и регулярные выражения с подстановкой групп в ответ,
+/// а также hex-шаблоны, которые ищутся в потоке байтов без деления на строки.
+/// Команды сопоставляются с принятыми байтами без преобразования в текст.
+#[derive(Clone, Debug, Default)]
+pub struct CommandSet {
+    exact: HashMap<Vec<u8>, CommandEntry>,
+    /// Точные совпадения без учёта регистра, по команде в нижнем регистре.
+    exact_ignore_case: HashMap<Vec<u8>, CommandEntry>,
+    patterns: Vec<(Regex, CommandEntry)>,
+    hex: Vec<(HexPattern, CommandEntry)>,
+}
+
+impl CommandSet {
+    /// Добавляет команду с точным совпадением.
+    pub fn insert(&mut self, command: impl Into<Vec<u8>>, entry: CommandEntry) {
+        self.exact.insert(command.into(), entry);
+    }
+
+    /// Добавляет команду с точным совпадением без учёта регистра. Ответ, как и у обычных
+    /// точных совпадений, отправляется без подстановки групп.
+    pub fn insert_ignore_case(&mut self, command: &str, entry: CommandEntry) {
+        self.exact_ignore_case.insert(command.to_lowercase().into_bytes(), entry);
     }
 
-    let file = File::open(filename).expect("Failed to open commands file");
-    let reader = io::BufReader::new(file);
-    let mut lines = reader.lines();
+    /// Добавляет регулярное выражение. Выражение должно совпадать с командой целиком,
+    /// в ответе допускаются ссылки на группы (`$1`, `${name}`). Повторное добавление того же
+    /// выражения заменяет ответ, сохраняя исходный порядок.
+    pub fn insert_pattern(&mut self, pattern: &str, ignore_case: bool, entry: CommandEntry) -> Result<(), regex::Error> {
+        let flags = if ignore_case { "i" } else { "" };
+        let regex = Regex::new(&format!("^(?{}:{})$", flags, pattern))?;
+        match self.patterns.iter_mut().find(|(existing, _)| existing.as_str() == regex.as_str()) {
+            Some(slot) => slot.1 = entry,
+            None => self.patterns.push((regex, entry)),
+        }
+        Ok(())
+    }
 
-    while let Some(Ok(command)) = lines.next() {
-        if let Some(Ok(response)) = lines.next() {
-            commands.insert(command, response);
+    /// Добавляет hex-шаблон (`01 03 ?? ?? *`). Повторное добавление того же шаблона заменяет ответ.
+    pub fn insert_hex(&mut self, pattern: HexPattern, entry: CommandEntry) {
+        match self.hex.iter_mut().find(|(existing, _)| *existing == pattern) {
+            Some(slot) => slot.1 = entry,
+            None => self.hex.push((pattern, entry)),
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.exact.len() + self.exact_ignore_case.len() + self.patterns.len() + self.hex.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ищет ответ на команду: сначала точные совпадения, затем выражения в порядке файла.
+    pub fn lookup(&self, command: &[u8]) -> Option<Reply> {
+        if let Some(entry) = self.exact.get(command) {
+            return Some(entry.render(None));
+        }
+        if !self.exact_ignore_case.is_empty() {
+            let folded = match std::str::from_utf8(command) {
+                Ok(text) => text.to_lowercase().into_bytes(),
+                Err(_) => command.to_ascii_lowercase(),
+            };
+            if let Some(entry) = self.exact_ignore_case.get(&folded) {
+                return Some(entry.render(None));
+            }
+        }
+        self.patterns
+            .iter()
+            .find_map(|(regex, entry)| regex.captures(command).map(|caps| entry.render(Some(&caps))))
+    }
+
+    /// Ищет ответ на целый кадр: как `lookup`, а также среди hex-шаблонов, совпадающих с кадром полностью.
+    pub fn lookup_frame(&self, frame: &[u8]) -> Option<Reply> {
+        self.lookup(frame).or_else(|| {
+            self.hex
+                .iter()
+                .find(|(pattern, _)| pattern.match_prefix(frame) == PatternMatch::Complete(frame.len()))
+                .map(|(_, entry)| entry.render(None))
+        })
+    }
+
+    /// Сопоставляет hex-шаблоны с началом данных в порядке файла.
+    fn match_prefix(&self, data: &[u8]) -> (Option<(usize, &CommandEntry)>, bool) {
+        let mut partial = false;
+        for (pattern, entry) in &self.hex {
+            match pattern.match_prefix(data) {
+                PatternMatch::Complete(len) => return (Some((len, entry)), partial),
+                PatternMatch::Partial => partial = true,
+                PatternMatch::Mismatch => {}
+            }
+        }
+        (None, partial)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &CommandEntry> {
+        self.exact
+            .values()
+            .chain(self.exact_ignore_case.values())
+            .chain(self.patterns.iter().map(|(_, entry)| entry))
+            .chain(self.hex.iter().map(|(_, entry)| entry))
+    }
+}
+
+/// Таймер состояния: срабатывает через заданное время после входа в состояние.
+#[derive(Clone, Debug)]
+pub struct StateTimer {
+    pub after: Delay,
+    /// Ответ и переход, выполняемые при срабатывании.
+    pub entry: CommandEntry,
+}
+
+/// Состояние эмулируемого устройства: собственные команды и таймеры.
+#[derive(Clone, Debug, Default)]
+pub struct StateDef {
+    pub commands: CommandSet,
+    pub timers: Vec<StateTimer>,
+}
+
+/// Таблица команд: общие команды, действующие в любом состоянии, и именованные состояния
+/// с собственными командами, переходами и таймерами.
+#[derive(Clone, Debug, Default)]
+pub struct CommandTable {
+    global: CommandSet,
+    states: BTreeMap<String, StateDef>,
+    initial_state: Option<String>,
+}
+
+impl CommandTable {
+    pub fn new() -> Self {
+        CommandTable::default()
+    }
+
+    /// Добавляет общую команду с точным совпадением.
+    pub fn insert(&mut self, command: impl Into<Vec<u8>>, entry: CommandEntry) {
+        self.global.insert(command, entry);
+    }
+
+    /// Добавляет общую команду с точным совпадением без учёта регистра.
+    pub fn insert_ignore_case(&mut self, command: &str, entry: CommandEntry) {
+        self.global.insert_ignore_case(command, entry)
+    }
+
+    /// Добавляет общую команду в виде регулярного выражения.
+    pub fn insert_pattern(&mut self, pattern: &str, ignore_case: bool, entry: CommandEntry) -> Result<(), regex::Error> {
+        self.global.insert_pattern(pattern, ignore_case, entry)
+    }
+
+    /// Добавляет общую команду в виде hex-шаблона.
+    pub fn insert_hex(&mut self, pattern: HexPattern, entry: CommandEntry) {
+        self.global.insert_hex(pattern, entry);
+    }
+
+    /// Общие команды, действующие в любом состоянии.
+    pub fn global_mut(&mut self) -> &mut CommandSet {
+        &mut self.global
+    }
+
+    /// Возвращает состояние для изменения, создавая его при необходимости.
+    pub fn state_mut(&mut self, name: &str) -> &mut StateDef {
+        self.states.entry(name.to_string()).or_default()
+    }
+
+    pub fn state(&self, name: &str) -> Option<&StateDef> {
+        self.states.get(name)
+    }
+
+    pub fn state_names(&self) -> impl Iterator<Item = &str> {
+        self.states.keys().map(String::as_str)
+    }
+
+    pub fn set_initial_state(&mut self, name: impl Into<String>) {
+        self.initial_state = Some(name.into());
+    }
+
+    /// Начальное состояние эмулятора.
+    pub fn initial_state(&self) -> Option<&str> {
+        self.initial_state.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.global.len() + self.states.values().map(|state| state.commands.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ищет ответ на команду: сначала среди команд текущего состояния, затем среди общих.
+    pub fn lookup(&self, command: &[u8], state: Option<&str>) -> Option<Reply> {
+        state
+            .and_then(|name| self.states.get(name))
+            .and_then(|state| state.commands.lookup(command))
+            .or_else(|| self.global.lookup(command))
+    }
+
+    /// Есть ли hex-шаблоны, которые нужно искать в потоке байтов.
+    pub fn has_hex_patterns(&self) -> bool {
+        !self.global.hex.is_empty() || self.states.values().any(|state| !state.commands.hex.is_empty())
+    }
+
+    /// Ищет самый ранний кадр, совпадающий с hex-шаблоном текущего состояния или общим.
+    /// Если с какой-то позиции кадр может завершиться после приёма следующих байтов,
+    /// поиск останавливается на ней, чтобы не отвечать на середину кадра.
+    pub fn match_stream(&self, data: &[u8], state: Option<&str>) -> StreamMatch {
+        let sets: Vec<&CommandSet> = state
+            .and_then(|name| self.states.get(name))
+            .map(|state| &state.commands)
+            .into_iter()
+            .chain(std::iter::once(&self.global))
+            .collect();
+        for start in 0..data.len() {
+            let mut partial = false;
+            for set in &sets {
+                let (complete, maybe) = set.match_prefix(&data[start..]);
+                if let Some((len, entry)) = complete {
+                    return StreamMatch::Complete { start, len, reply: entry.render(None) };
+                }
+                partial |= maybe;
+            }
+            if partial {
+                return StreamMatch::Partial(start);
+            }
+        }
+        StreamMatch::None
+    }
+
+    /// Ищет ответ на кадр, выделенный из потока: сначала в текущем состоянии, затем среди общих команд.
+    pub fn lookup_frame(&self, frame: &[u8], state: Option<&str>) -> Option<Reply> {
+        state
+            .and_then(|name| self.states.get(name))
+            .and_then(|state| state.commands.lookup_frame(frame))
+            .or_else(|| self.global.lookup_frame(frame))
+    }
+
+    /// Проверяет, что все переходы ведут в описанные состояния.
+    pub fn validate(&self) -> Result<(), String> {
+        let check = |target: &str| {
+            if self.states.contains_key(target) {
+                Ok(())
+            } else {
+                Err(format!("transition to undefined state '{}'", target))
+            }
+        };
+        if let Some(initial) = &self.initial_state {
+            if !self.states.contains_key(initial) {
+                return Err(format!("initial state '{}' is not defined", initial));
+            }
+        }
+        let entries = self.global.entries().chain(
+            self.states
+                .values()
+                .flat_map(|state| state.commands.entries().chain(state.timers.iter().map(|timer| &timer.entry))),
+        );
+        for entry in entries {
+            if let Some(target) = &entry.goto {
+                check(target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CommandEntry {
+    /// Формирует ответ, подставляя группы регулярного выражения в текстовые сообщения.
+    pub fn render(&self, caps: Option<&Captures>) -> Reply {
+        let messages = self
+            .responses
+            .iter()
+            .map(|message| {
+                let data = match (&message.part, caps) {
+                    (ResponsePart::Text(template), Some(caps)) => {
+                        let mut data = Vec::new();
+                        caps.expand(template.as_bytes(), &mut data);
+                        data
+                    }
+                    (ResponsePart::Text(text), None) => text.clone().into_bytes(),
+                    (ResponsePart::Bytes(bytes), _) => bytes.clone(),
+                };
+                ReplyMessage { delay: message.delay.sample(), data }
+            })
+            .collect();
+        Reply {
+            messages,
+            delay: self.delay.sample(),
+            chunk_size: self.chunk_size,
+            chunk_gap: self.chunk_gap,
+            quiet: self.quiet,
+            goto: self.goto.clone(),
+        }
+    }
+}
+
+/// Элемент файла команд в старом формате (чередующиеся строки команда/ответ).
+#[derive(Debug)]
+pub enum LegacyItem {
+    Include(String),
+    Command { line: usize, command: String, response: String },
+}
+
+/// Разбирает файл команд в старом формате без подключения вложенных файлов.
+pub fn parse_legacy_file(path: &Path) -> io::Result<Vec<LegacyItem>> {
+    let file = File::open(path).map_err(|e| {
+        io::Error::new(e.kind(), format!("Cannot open command file '{}': {}", path.display(), e))
+    })?;
+
+    let mut items = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (index, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        let trimmed = line.trim();
+        // Пустые строки и комментарии пропускаются только на месте команды: строка ответа
+        // берётся как есть, иначе пустой ответ или ответ с `#` сдвинул бы все следующие пары
+        if pending.is_none() && (trimmed.is_empty() || trimmed.starts_with('#')) {
+            continue;
+        }
+
+        match pending.take() {
+            Some((line_number, command)) => items.push(LegacyItem::Command {
+                line: line_number,
+                command,
+                response: line.to_string(),
+            }),
+            None => {
+                if let Some(include) = trimmed.strip_prefix(INCLUDE_DIRECTIVE) {
+                    let include = include.trim();
+                    if include.is_empty() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{}:{}: {} requires a path", path.display(), index + 1, INCLUDE_DIRECTIVE),
+                        ));
+                    }
+                    items.push(LegacyItem::Include(include.to_string()));
+                } else {
+                    pending = Some((index + 1, trimmed.to_string()));
+                }
+            }
+        }
+    }
+
+    if let Some((line, command)) = pending {
+        eprintln!(
+            "[Warning] {}:{}: command '{}' has no response and is ignored.",
+            path.display(),
+            line,
+            command
+        );
+    }
+    Ok(items)
+}
+
+/// Возвращает выражение, если команда старого формата задана с префиксом `~`.
+pub fn legacy_pattern(command: &str) -> Option<&str> {
+    command.strip_prefix(REGEX_PREFIX)
+}
+
+/// Загружает команды из нескольких файлов по порядку; команды из более поздних файлов
+/// переопределяют ранее загруженные. Отсутствие любого из файлов является ошибкой.
+pub fn load_commands<P: AsRef<Path>>(paths: &[P]) -> io::Result<CommandTable> {
+    let mut commands = CommandTable::new();
+    for path in paths {
+        load_into(path.as_ref(), &mut commands, &mut Vec::new())?;
+    }
     commands
+        .validate()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(commands)
+}
+
+/// Загружает команды из файла. Файлы `.toml` разбираются как профиль устройства, остальные —
+/// в старом формате, где каждая команда и её ответ задаются в соседних строках.
+/// Пустые строки и строки, начинающиеся с `#`, пропускаются; `@include <путь>` подключает
+/// другой файл (относительно каталога текущего файла). Команда с префиксом `~` задаёт
+/// регулярное выражение, группы которого можно использовать в ответе (`$1`).
+pub fn load_commands_from_file<P: AsRef<Path>>(filename: P) -> io::Result<CommandTable> {
+    load_commands(&[filename])
+}
+
+/// Загружает файл команд по умолчанию, если он существует. Отсутствие файла не является ошибкой.
+pub fn load_default_commands() -> io::Result<CommandTable> {
+    if !Path::new(DEFAULT_COMMANDS_FILE).exists() {
+        eprintln!("[Warning] Command file '{}' not found. Running without predefined commands.", DEFAULT_COMMANDS_FILE);
+        return Ok(CommandTable::new());
+    }
+    load_commands_from_file(DEFAULT_COMMANDS_FILE)
+}
+
+/// Загружает файл любого поддерживаемого формата, отслеживая цепочку подключений.
+pub(crate) fn load_into(path: &Path, commands: &mut CommandTable, stack: &mut Vec<PathBuf>) -> io::Result<()> {
+    let canonical = path.canonicalize().map_err(|e| {
+        io::Error::new(e.kind(), format!("Cannot open command file '{}': {}", path.display(), e))
+    })?;
+    if stack.contains(&canonical) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Recursive include of command file '{}'", path.display()),
+        ));
+    }
+    stack.push(canonical);
+
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        load_profile_into(path, commands, stack)?;
+    } else {
+        load_legacy_into(path, commands, stack)?;
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+fn load_legacy_into(path: &Path, commands: &mut CommandTable, stack: &mut Vec<PathBuf>) -> io::Result<()> {
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for item in parse_legacy_file(path)? {
+        match item {
+            LegacyItem::Include(include) => load_into(&base.join(include), commands, stack)?,
+            LegacyItem::Command { line, command, response } => match legacy_pattern(&command) {
+                Some(pattern) => commands
+                    .insert_pattern(pattern, false, CommandEntry::text(&response))
+                    .map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{}:{}: invalid regular expression: {}", path.display(), line, e),
+                        )
+                    })?,
+                None => commands.insert(command, CommandEntry::text(&response)),
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(set: &CommandSet, command: &[u8]) -> Option<Vec<u8>> {
+        set.lookup(command).map(|reply| reply.messages[0].data.clone())
+    }
+
+    #[test]
+    fn legacy_response_may_be_empty_or_start_with_hash() {
+        let path = std::env::temp_dir().join(format!("virtualport-{}-responses.txt", std::process::id()));
+        std::fs::write(&path, "# modem\nAT+X\n#OK\n\nAT+EMPTY\n\nAT\nOK\n").unwrap();
+        let items = parse_legacy_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let pairs: Vec<(&str, &str)> = items
+            .iter()
+            .map(|item| match item {
+                LegacyItem::Command { command, response, .. } => (command.as_str(), response.as_str()),
+                LegacyItem::Include(include) => panic!("unexpected include {}", include),
+            })
+            .collect();
+        assert_eq!(pairs, vec![("AT+X", "#OK"), ("AT+EMPTY", ""), ("AT", "OK")]);
+    }
+
+    #[test]
+    fn ignore_case_exact_match_keeps_dollar_signs() {
+        let mut set = CommandSet::default();
+        set.insert_ignore_case("AT+Price", CommandEntry::text("costs $1 or ${2}"));
+        assert_eq!(response(&set, b"at+PRICE"), Some(b"costs $1 or ${2}\n".to_vec()));
+        assert_eq!(response(&set, b"AT+PRICES"), None);
+    }
+
+    #[test]
+    fn pattern_substitutes_groups() {
+        let mut set = CommandSet::default();
+        set.insert_pattern(r"AT\+CSQ=(\d+)", false, CommandEntry::text("+CSQ: $1")).unwrap();
+        assert_eq!(response(&set, b"AT+CSQ=17"), Some(b"+CSQ: 17\n".to_vec()));
+        assert_eq!(response(&set, b"AT+CSQ=17x"), None);
+    }
 }
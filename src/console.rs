@@ -0,0 +1,93 @@
+use std::fs::File;
+use crate::emulator::Emulator;
+use crate::pty::{describe_termios, set_baud_rate, set_parity, speed_to_baud};
+
+/// Команда консоли, введённая пользователем в виде строки с префиксом `/`.
+#[derive(Debug, PartialEq)]
+pub enum ConsoleCommand {
+    Baud(u32),
+    Parity(String),
+    Status,
+    State(Option<String>),
+    Help,
+}
+
+/// Разбирает строку консоли. Возвращает `None`, если строка не является командой.
+pub fn parse_console_command(line: &str) -> Option<Result<ConsoleCommand, String>> {
+    let body = line.strip_prefix('/')?;
+    // `//текст` отправляется в порт как `/текст`
+    if body.starts_with('/') {
+        return None;
+    }
+    let mut parts = body.split_whitespace();
+    let name = parts.next().unwrap_or("");
+    let arg = parts.next();
+    if parts.next().is_some() {
+        return Some(Err(format!("Too many arguments for /{}", name)));
+    }
+    Some(match (name, arg) {
+        ("baud", Some(value)) => match value.parse::<u32>() {
+            Ok(speed) if speed_to_baud(speed).is_some() => Ok(ConsoleCommand::Baud(speed)),
+            Ok(_) => Err(format!("Unsupported baud rate: {}", value)),
+            Err(_) => Err(format!("Invalid baud rate: {}", value)),
+        },
+        ("baud", None) => Err("Usage: /baud <rate>".to_string()),
+        ("parity", Some(value)) => match value {
+            "none" | "even" | "odd" => Ok(ConsoleCommand::Parity(value.to_string())),
+            _ => Err(format!("Invalid parity: {} (expected none, even or odd)", value)),
+        },
+        ("parity", None) => Err("Usage: /parity <none|even|odd>".to_string()),
+        ("status", None) => Ok(ConsoleCommand::Status),
+        ("state", name) => Ok(ConsoleCommand::State(name.map(str::to_string))),
+        ("help", None) => Ok(ConsoleCommand::Help),
+        _ => Err(format!("Unknown command: /{} (type /help for a list)", name)),
+    })
+}
+
+/// Выполняет команду консоли над slave-устройством и выводит новое состояние termios.
+pub fn execute_console_command(command: &ConsoleCommand, slave: &File, emulator: &Emulator) {
+    match command {
+        ConsoleCommand::Baud(speed) => {
+            // Скорость уже проверена при разборе
+            if let Some(baud) = speed_to_baud(*speed) {
+                match set_baud_rate(slave, baud) {
+                    Ok(()) => println!("[Info] Baud rate set to {}", speed),
+                    Err(e) => eprintln!("[Console] Cannot set baud rate {}: {}", speed, e),
+                }
+            }
+        }
+        ConsoleCommand::Parity(parity) => {
+            if let Err(e) = set_parity(slave, parity) {
+                eprintln!("[Console] {}", e);
+            }
+        }
+        ConsoleCommand::Status => {}
+        ConsoleCommand::State(None) => {
+            match emulator.current_state() {
+                Some(state) => {
+                    let states: Vec<&str> = emulator.table().state_names().collect();
+                    println!("[Console] Current state: {} (states: {})", state, states.join(", "));
+                }
+                None => println!("[Console] The loaded profile defines no states."),
+            }
+            return;
+        }
+        ConsoleCommand::State(Some(name)) => {
+            if let Err(e) = emulator.set_state(name, "console") {
+                eprintln!("[Console] {}", e);
+            }
+            return;
+        }
+        ConsoleCommand::Help => {
+            println!("[Console] Available commands:");
+            println!("  /baud <rate>              change the baud rate of the virtual port");
+            println!("  /parity <none|even|odd>   change the parity of the virtual port");
+            println!("  /status                   show the current port settings");
+            println!("  /state [name]             show or change the emulator state");
+            println!("  /help                     show this help");
+            println!("  //text                    send '/text' to the port");
+            return;
+        }
+    }
+    println!("[Console] Port settings: {}", describe_termios(slave));
+}
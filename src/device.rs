@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::framer::FramingSpec;
+use crate::logger::log_message;
+use crate::scheduler::Scheduler;
+
+/// Встроенная модель устройства, отвечающая на кадры вместо таблицы команд.
+pub trait Device: Send {
+    /// Название для вывода в консоль.
+    fn name(&self) -> &'static str;
+
+    /// Способ выделения кадров, используемый, если `framing` не задан явно.
+    fn framing(&self, baud_rate: u32) -> FramingSpec;
+
+    /// Обрабатывает принятый кадр. Возвращает `false`, если кадр не относится к устройству
+    /// и должен быть передан таблице команд.
+    fn on_frame(&mut self, frame: &[u8], ctx: &DeviceContext) -> bool;
+
+    /// Вызывается потоком чтения не реже раза в 10 мс: для запросов по расписанию и таймаутов.
+    fn on_tick(&mut self, _now: Instant, _ctx: &DeviceContext) {}
+
+    /// Вызывается при остановке порта, например для вывода статистики.
+    fn finish(&mut self, _ctx: &DeviceContext) {}
+}
+
+/// Вывод устройства: очередь отправки и лог обмена.
+pub struct DeviceContext<'a> {
+    pub scheduler: &'a Scheduler,
+    pub logger: &'a Option<Arc<Mutex<File>>>,
+}
+
+impl DeviceContext<'_> {
+    /// Ставит ответ в очередь на немедленную отправку.
+    pub fn send(&self, data: Vec<u8>) {
+        self.scheduler.send_now(data, "Response");
+    }
+
+    /// Ставит в очередь запрос, который устройство отправляет по своей инициативе.
+    pub fn send_request(&self, data: Vec<u8>) {
+        self.scheduler.send_now(data, "Request");
+    }
+
+    /// Выводит сообщение в консоль и в лог.
+    pub fn log(&self, msg: &str) {
+        println!("{}", msg);
+        log_message(self.logger, msg);
+    }
+}
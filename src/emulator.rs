@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use crate::commands::{CommandTable, Reply, StreamMatch};
+use crate::hex::escape_bytes;
+use crate::logger::log_message;
+use crate::scheduler::Scheduler;
+
+/// Наибольший объём байтов, ожидающих завершения кадра; при превышении поиск сдвигается.
+const MAX_PENDING: usize = 4096;
+
+/// Текущее состояние и номер перехода; таймеры, взведённые до последнего перехода, игнорируются.
+#[derive(Default)]
+struct Machine {
+    current: Option<String>,
+    epoch: u64,
+}
+
+/// Эмулятор устройства: сопоставляет команды с таблицей с учётом текущего состояния,
+/// выполняет переходы и таймеры. Клонируется для использования из нескольких потоков.
+#[derive(Clone)]
+pub struct Emulator {
+    table: Arc<CommandTable>,
+    machine: Arc<Mutex<Machine>>,
+    scheduler: Scheduler,
+    logger: Option<Arc<Mutex<File>>>,
+    verbose: bool,
+}
+
+impl Emulator {
+    pub fn new(table: CommandTable, scheduler: Scheduler, logger: Option<Arc<Mutex<File>>>, verbose: bool) -> Self {
+        Emulator {
+            table: Arc::new(table),
+            machine: Arc::new(Mutex::new(Machine::default())),
+            scheduler,
+            logger,
+            verbose,
+        }
+    }
+
+    /// Переводит эмулятор в начальное состояние и взводит его таймеры.
+    pub fn start(&self) {
+        if let Some(initial) = self.table.initial_state() {
+            self.enter(initial, "initial state");
+        }
+    }
+
+    pub fn table(&self) -> &CommandTable {
+        &self.table
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub fn current_state(&self) -> Option<String> {
+        self.machine.lock().unwrap().current.clone()
+    }
+
+    /// Отвечает на команду, если она есть в таблице. Возвращает `true`, если команда распознана.
+    pub fn respond(&self, command: &[u8], tag: &'static str) -> bool {
+        let state = self.current_state();
+        match self.table.lookup(command, state.as_deref()) {
+            Some(reply) => {
+                self.dispatch(command, state.as_deref(), reply, tag);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Отвечает на кадр, выделенный из потока; hex-шаблоны должны совпасть с кадром целиком.
+    pub fn respond_frame(&self, frame: &[u8], tag: &'static str) -> bool {
+        let state = self.current_state();
+        match self.table.lookup_frame(frame, state.as_deref()) {
+            Some(reply) => {
+                self.dispatch(frame, state.as_deref(), reply, tag);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Отвечает на кадры, совпавшие с hex-шаблонами. Обработанные и заведомо лишние байты
+    /// удаляются из буфера; незавершённый кадр остаётся до приёма следующих байтов.
+    pub fn respond_stream(&self, buffer: &mut Vec<u8>, tag: &'static str) {
+        loop {
+            let state = self.current_state();
+            match self.table.match_stream(buffer, state.as_deref()) {
+                StreamMatch::Complete { start, len, reply } => {
+                    let frame: Vec<u8> = buffer.drain(..start + len).skip(start).collect();
+                    self.dispatch(&frame, state.as_deref(), reply, tag);
+                }
+                StreamMatch::Partial(start) => {
+                    buffer.drain(..start);
+                    if buffer.len() <= MAX_PENDING {
+                        return;
+                    }
+                    buffer.remove(0);
+                }
+                StreamMatch::None => {
+                    buffer.clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    fn dispatch(&self, command: &[u8], state: Option<&str>, reply: Reply, tag: &'static str) {
+        if !reply.quiet {
+            self.print_recognized(command, state, &reply);
+        }
+        reply.schedule(&self.scheduler, tag);
+        if let Some(target) = &reply.goto {
+            self.enter(target, &format!("command '{}'", escape_bytes(command)));
+        }
+    }
+
+    /// Принудительно переводит эмулятор в состояние (например, из консоли или скрипта).
+    pub fn set_state(&self, name: &str, reason: &str) -> Result<(), String> {
+        if self.table.state(name).is_none() {
+            return Err(format!("Unknown state: {}", name));
+        }
+        self.enter(name, reason);
+        Ok(())
+    }
+
+    fn print_recognized(&self, command: &[u8], state: Option<&str>, reply: &Reply) {
+        let command = escape_bytes(command);
+        match state {
+            Some(state) if self.verbose => println!(
+                "[Command] Recognized: '{}' in state '{}', responding with {}",
+                command,
+                state,
+                reply.describe()
+            ),
+            _ => println!("[Command] Recognized: '{}', responding with {}", command, reply.describe()),
+        }
+    }
+
+    fn enter(&self, name: &str, reason: &str) {
+        let epoch = {
+            let mut machine = self.machine.lock().unwrap();
+            let previous = machine.current.replace(name.to_string());
+            machine.epoch += 1;
+            let msg = match previous {
+                Some(previous) => format!("[State] {} -> {} ({})", previous, name, reason),
+                None => format!("[State] {} ({})", name, reason),
+            };
+            println!("{}", msg);
+            log_message(&self.logger, &msg);
+            machine.epoch
+        };
+
+        let state = match self.table.state(name) {
+            Some(state) => state,
+            None => return,
+        };
+        for (index, timer) in state.timers.iter().enumerate() {
+            let delay = timer.after.sample();
+            let emulator = self.clone();
+            let state = name.to_string();
+            self.scheduler.call_after(delay, move || emulator.fire_timer(&state, epoch, index));
+        }
+    }
+
+    fn fire_timer(&self, state: &str, epoch: u64, index: usize) {
+        if self.machine.lock().unwrap().epoch != epoch {
+            return;
+        }
+        let timer = &self.table.state(state).unwrap().timers[index];
+        let reply = timer.entry.render(None);
+        if !reply.messages.is_empty() && !reply.quiet {
+            println!("[Timer] State '{}' timer fired, sending {}", state, reply.describe());
+        }
+        reply.schedule(&self.scheduler, "Timer");
+        if let Some(target) = &reply.goto {
+            self.enter(target, &format!("timer in state '{}'", state));
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use crate::checksum::Checksum;
+
+/// Интервал опроса порта, когда ожидание кадра не ограничено паузой.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Размер незавершённого кадра, после которого буфер передаётся дальше (`line`, `idle`)
+/// или отбрасывается (`delim`, `length`), чтобы поток без разделителей не занимал память.
+pub const MAX_FRAME: usize = 4096;
+
+/// Окончание строки в режиме `line`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Terminator {
+    Cr,
+    #[default]
+    Lf,
+    CrLf,
+}
+
+/// Порядок байтов поля длины.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// Способ выделения кадров из потока байтов.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Framing {
+    /// Строки с заданным окончанием (`line:cr|lf|crlf`).
+    Line(Terminator),
+    /// Кадр заканчивается паузой в приёме (`idle:<ms>`), как в Modbus RTU.
+    Idle(Duration),
+    /// Кадры фиксированной длины (`fixed:<n>`).
+    Fixed(usize),
+    /// Кадр от начального до конечного байта включительно (`delim:02..03`).
+    Delimited { start: u8, end: u8 },
+    /// Длина кадра задана полем `size` байтов по смещению `offset` и считает байты после
+    /// поля; `adjust` добавляется к ней, например для контрольной суммы
+    /// (`length:<offset>,<size>,<le|be>[,<adjust>]`).
+    Length { offset: usize, size: usize, endian: Endian, adjust: i64 },
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing::Line(Terminator::default())
+    }
+}
+
+/// Режим выделения кадров вместе с необязательным escape-байтом: байт после него
+/// не считается разделителем, а сам escape-байт удаляется из кадра.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FramingSpec {
+    pub framing: Framing,
+    pub escape: Option<u8>,
+    /// Контрольная сумма в конце каждого кадра; кадры с неверной суммой отбрасываются.
+    pub checksum: Option<Checksum>,
+}
+
+impl FromStr for FramingSpec {
+    type Err = String;
+
+    /// Разбирает описание вида `line:crlf`, `idle:5,check=crc16_modbus`, `delim:02..03,esc=10`,
+    /// `length:1,2,be,+2`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (mode, args) = text.split_once(':').unwrap_or((text, ""));
+        let mut args: Vec<&str> = args.split(',').map(str::trim).filter(|arg| !arg.is_empty()).collect();
+        let escape = match args.iter().position(|arg| arg.starts_with("esc=")) {
+            Some(index) => Some(parse_byte(&args.remove(index)["esc=".len()..])?),
+            None => None,
+        };
+        let checksum = match args.iter().position(|arg| arg.starts_with("check=")) {
+            Some(index) => Some(args.remove(index)["check=".len()..].parse()?),
+            None => None,
+        };
+
+        let framing = match (mode, args.as_slice()) {
+            ("line", []) => Framing::Line(Terminator::Lf),
+            ("line", ["cr"]) => Framing::Line(Terminator::Cr),
+            ("line", ["lf"]) => Framing::Line(Terminator::Lf),
+            ("line", ["crlf"]) => Framing::Line(Terminator::CrLf),
+            ("idle", [ms]) => Framing::Idle(Duration::from_millis(parse_number(ms)?)),
+            ("fixed", [len]) => match parse_number(len)? {
+                0 => return Err("Fixed frame length must be positive".to_string()),
+                len => Framing::Fixed(len as usize),
+            },
+            ("delim", [range]) => {
+                let (start, end) = range
+                    .split_once("..")
+                    .ok_or_else(|| format!("Expected delim:<start>..<end>, got '{}'", text))?;
+                Framing::Delimited { start: parse_byte(start)?, end: parse_byte(end)? }
+            }
+            ("length", [offset, size, endian, adjust @ ..]) if adjust.len() <= 1 => Framing::Length {
+                offset: parse_number(offset)? as usize,
+                size: match parse_number(size)? {
+                    size @ (1 | 2 | 4) => size as usize,
+                    size => return Err(format!("Length field size must be 1, 2 or 4 bytes, got {}", size)),
+                },
+                endian: match *endian {
+                    "le" => Endian::Little,
+                    "be" => Endian::Big,
+                    other => return Err(format!("Invalid endianness '{}' (expected le or be)", other)),
+                },
+                adjust: match adjust.first() {
+                    Some(adjust) => adjust
+                        .trim_start_matches('+')
+                        .parse()
+                        .map_err(|_| format!("Invalid length adjustment '{}'", adjust))?,
+                    None => 0,
+                },
+            },
+            _ => {
+                return Err(format!(
+                    "Invalid framing '{}' (expected line:cr|lf|crlf, idle:<ms>, fixed:<n>, delim:<start>..<end> \
+                     or length:<offset>,<size>,<le|be>[,<adjust>], optionally followed by ,esc=<byte> \
+                     and ,check=<checksum>)",
+                    text
+                ))
+            }
+        };
+        if escape.is_some() && !matches!(framing, Framing::Line(_) | Framing::Delimited { .. }) {
+            return Err("An escape byte is only supported for line and delim framing".to_string());
+        }
+        Ok(FramingSpec { framing, escape, checksum })
+    }
+}
+
+impl fmt::Display for FramingSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.framing {
+            Framing::Line(Terminator::Cr) => write!(f, "line:cr")?,
+            Framing::Line(Terminator::Lf) => write!(f, "line:lf")?,
+            Framing::Line(Terminator::CrLf) => write!(f, "line:crlf")?,
+            Framing::Idle(gap) => write!(f, "idle:{}", gap.as_millis())?,
+            Framing::Fixed(len) => write!(f, "fixed:{}", len)?,
+            Framing::Delimited { start, end } => write!(f, "delim:{:02X}..{:02X}", start, end)?,
+            Framing::Length { offset, size, endian, adjust } => {
+                let endian = if *endian == Endian::Little { "le" } else { "be" };
+                write!(f, "length:{},{},{}", offset, size, endian)?;
+                if *adjust != 0 {
+                    write!(f, ",{:+}", adjust)?;
+                }
+            }
+        }
+        if let Some(escape) = self.escape {
+            write!(f, ",esc={:02X}", escape)?;
+        }
+        if let Some(checksum) = self.checksum {
+            write!(f, ",check={}", checksum)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    text.parse().map_err(|_| format!("Invalid number '{}'", text))
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    u8::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|_| format!("Invalid hex byte '{}'", text))
+}
+
+/// Выделяет кадры из принятых байтов согласно `FramingSpec`.
+pub struct Framer {
+    spec: FramingSpec,
+    buffer: Vec<u8>,
+    /// Предыдущий байт был escape-байтом.
+    escaped: bool,
+    /// Принят начальный байт кадра в режиме `delim`.
+    in_frame: bool,
+    last_received: Instant,
+}
+
+impl Framer {
+    pub fn new(spec: FramingSpec) -> Self {
+        Framer { spec, buffer: Vec::new(), escaped: false, in_frame: false, last_received: Instant::now() }
+    }
+
+    pub fn checksum(&self) -> Option<Checksum> {
+        self.spec.checksum
+    }
+
+    pub fn is_line(&self) -> bool {
+        matches!(self.spec.framing, Framing::Line(_))
+    }
+
+    /// Окончание строки, которым заканчиваются кадры в режиме `line`.
+    pub fn terminator(&self) -> Option<&'static [u8]> {
+        match self.spec.framing {
+            Framing::Line(Terminator::Cr) => Some(b"\r"),
+            Framing::Line(Terminator::Lf) => Some(b"\n"),
+            Framing::Line(Terminator::CrLf) => Some(b"\r\n"),
+            _ => None,
+        }
+    }
+
+    /// Добавляет принятые байты и возвращает завершённые кадры.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        self.last_received = Instant::now();
+        let mut frames = Vec::new();
+        for &byte in data {
+            if self.buffer.len() >= MAX_FRAME {
+                frames.extend(self.overflow());
+            }
+            if self.escaped {
+                self.escaped = false;
+                if self.in_frame || self.is_line() {
+                    self.buffer.push(byte);
+                }
+                continue;
+            }
+            if Some(byte) == self.spec.escape {
+                self.escaped = true;
+                continue;
+            }
+            if let Some(frame) = self.push_byte(byte) {
+                frames.push(frame);
+                // После ресинхронизации в буфере может остаться ещё один целый кадр
+                frames.extend(std::iter::from_fn(|| self.length_frame()));
+            }
+        }
+        frames
+    }
+
+    /// Освобождает буфер, достигший `MAX_FRAME`.
+    fn overflow(&mut self) -> Option<Vec<u8>> {
+        match self.spec.framing {
+            Framing::Line(_) | Framing::Idle(_) => {
+                eprintln!("[Framer] No end of frame within {} bytes, passing the data on as a frame", MAX_FRAME);
+                Some(self.take())
+            }
+            Framing::Fixed(_) => None,
+            Framing::Delimited { .. } | Framing::Length { .. } => {
+                eprintln!("[Framer] No end of frame within {} bytes, discarding the data", MAX_FRAME);
+                self.buffer.clear();
+                self.in_frame = false;
+                None
+            }
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) -> Option<Vec<u8>> {
+        match self.spec.framing {
+            Framing::Line(terminator) => {
+                self.buffer.push(byte);
+                let complete = match terminator {
+                    Terminator::Cr => byte == b'\r',
+                    Terminator::Lf => byte == b'\n',
+                    Terminator::CrLf => self.buffer.ends_with(b"\r\n"),
+                };
+                complete.then(|| self.take())
+            }
+            Framing::Idle(_) => {
+                self.buffer.push(byte);
+                None
+            }
+            Framing::Fixed(len) => {
+                self.buffer.push(byte);
+                (self.buffer.len() == len).then(|| self.take())
+            }
+            Framing::Delimited { start, end } => {
+                if !self.in_frame {
+                    // Байты вне кадра отбрасываются до начального байта
+                    if byte == start {
+                        self.in_frame = true;
+                        self.buffer.push(byte);
+                    }
+                    return None;
+                }
+                if byte == end && self.buffer.len() == 1 && start == end {
+                    // Два разделителя подряд (`7E 7E`): второй начинает новый кадр
+                    return None;
+                }
+                self.buffer.push(byte);
+                if byte == end {
+                    self.in_frame = false;
+                    return Some(self.take());
+                }
+                None
+            }
+            Framing::Length { .. } => {
+                self.buffer.push(byte);
+                self.length_frame()
+            }
+        }
+    }
+
+    /// Выделяет из начала буфера кадр по полю длины. При некорректной длине сдвигается на байт
+    /// и проверяет оставшиеся байты, пока заголовок не разберётся или не станет неполным.
+    fn length_frame(&mut self) -> Option<Vec<u8>> {
+        let Framing::Length { offset, size, endian, adjust } = self.spec.framing else {
+            return None;
+        };
+        let header = offset + size;
+        while self.buffer.len() >= header {
+            let field = &self.buffer[offset..header];
+            let value = match endian {
+                Endian::Little => field.iter().rev().fold(0u64, |acc, &b| acc << 8 | b as u64),
+                Endian::Big => field.iter().fold(0u64, |acc, &b| acc << 8 | b as u64),
+            };
+            let total = header as i64 + value as i64 + adjust;
+            if total < header as i64 || total > MAX_FRAME as i64 {
+                // Некорректная длина: сдвигаемся на байт, чтобы найти следующий кадр
+                self.buffer.remove(0);
+                continue;
+            }
+            let total = total as usize;
+            return (self.buffer.len() >= total).then(|| self.buffer.drain(..total).collect());
+        }
+        None
+    }
+
+    /// Возвращает накопленный кадр, если в режиме `idle` истекла пауза после последнего байта.
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<u8>> {
+        match self.spec.framing {
+            Framing::Idle(gap) if !self.buffer.is_empty() && now - self.last_received >= gap => Some(self.take()),
+            _ => None,
+        }
+    }
+
+    /// Как часто нужно вызывать `poll`, чтобы пауза определялась достаточно точно.
+    pub fn poll_interval(&self) -> Duration {
+        match self.spec.framing {
+            Framing::Idle(gap) => (gap / 2).clamp(Duration::from_millis(1), POLL_INTERVAL),
+            _ => POLL_INTERVAL,
+        }
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framer(spec: &str) -> Framer {
+        Framer::new(spec.parse().unwrap())
+    }
+
+    #[test]
+    fn long_line_is_passed_on_at_max_frame() {
+        let mut framer = framer("line:lf");
+        let frames = framer.push(&vec![b'a'; MAX_FRAME + 10]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].len(), MAX_FRAME);
+        assert_eq!(framer.push(b"\n"), vec![[vec![b'a'; 10], b"\n".to_vec()].concat()]);
+    }
+
+    #[test]
+    fn unterminated_delim_frame_is_discarded() {
+        let mut framer = framer("delim:02..03");
+        let mut data = vec![0x02];
+        data.extend(vec![b'x'; MAX_FRAME + 10]);
+        assert!(framer.push(&data).is_empty());
+        assert!(framer.buffer.len() < MAX_FRAME);
+        assert_eq!(framer.push(&[0x02, b'A', 0x03]), vec![vec![0x02, b'A', 0x03]]);
+    }
+
+    #[test]
+    fn oversized_length_field_resynchronizes() {
+        let mut framer = framer("length:0,2,be");
+        assert!(framer.push(&[0xFF, 0xFF, 0x00]).is_empty());
+        assert_eq!(framer.push(&[0x02, 0xAA, 0xBB]), vec![vec![0x00, 0x02, 0xAA, 0xBB]]);
+    }
+
+    #[test]
+    fn resync_recovers_consecutive_frames() {
+        let mut framer = framer("length:0,2,be");
+        let frames = framer.push(&[0xFF, 0xFF, 0x00, 0x02, 0xAA, 0xBB, 0x00, 0x00]);
+        assert_eq!(frames, vec![vec![0x00, 0x02, 0xAA, 0xBB], vec![0x00, 0x00]]);
+        assert!(framer.buffer.is_empty());
+    }
+
+    #[test]
+    fn spec_round_trip() {
+        for text in [
+            "line:cr",
+            "line:crlf",
+            "idle:5,check=crc16_modbus",
+            "fixed:8",
+            "delim:02..03,esc=10",
+            "length:1,2,le,+2",
+            "length:0,4,be,-1,check=xor",
+        ] {
+            assert_eq!(text.parse::<FramingSpec>().unwrap().to_string(), text);
+        }
+        assert_eq!("line".parse::<FramingSpec>().unwrap(), FramingSpec::default());
+        let spec: FramingSpec = "delim:0x7E..7e".parse().unwrap();
+        assert_eq!(spec.framing, Framing::Delimited { start: 0x7E, end: 0x7E });
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        for text in [
+            "line:tab",
+            "idle:fast",
+            "fixed:0",
+            "delim:02",
+            "length:0,3,be",
+            "length:0,2,middle",
+            "fixed:4,esc=10",
+            "line:lf,check=crc64",
+            "slip",
+        ] {
+            assert!(text.parse::<FramingSpec>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn splits_frames() {
+        assert_eq!(framer("line:crlf").push(b"AT\rX\r\nOK"), vec![b"AT\rX\r\n".to_vec()]);
+        assert_eq!(framer("fixed:2").push(&[1, 2, 3, 4, 5]), vec![vec![1, 2], vec![3, 4]]);
+        // Байты вне кадра отбрасываются, экранированный конечный байт остаётся в кадре
+        let frames = framer("delim:02..03,esc=10").push(&[0xFF, 0x02, 0x10, 0x03, 0x41, 0x03]);
+        assert_eq!(frames, vec![vec![0x02, 0x03, 0x41, 0x03]]);
+        // Поле длины считает байты после себя, `+2` добавляет CRC
+        let frames = framer("length:1,1,le,+2").push(&[0xAA, 0x01, 0x55, 0xC1, 0xC2, 0xAA]);
+        assert_eq!(frames, vec![vec![0xAA, 0x01, 0x55, 0xC1, 0xC2]]);
+    }
+
+    #[test]
+    fn idle_frame_ends_after_gap() {
+        let mut framer = framer("idle:5");
+        assert!(framer.push(b"abc").is_empty());
+        assert_eq!(framer.poll(framer.last_received), None);
+        assert_eq!(framer.poll(framer.last_received + Duration::from_millis(5)), Some(b"abc".to_vec()));
+    }
+}
@@ -4,28 +4,38 @@ use std::sync::{
     Mutex,
 };
 use std::fs::File;
-use std::io::Write;
 use std::thread;
-use std::time::Duration;
-use crate::logger::log_message;
+use std::time::{Duration, Instant};
+use crate::hex::escape_bytes;
+use crate::logger::log_data;
+use crate::pty::write_all;
 
 pub fn start_heartbeat(
     running: Arc<AtomicBool>,
     heartbeat_interval: u64,
-    hb_msg: String,
-    mut master: Arc<File>,
+    hb_msg: Vec<u8>,
+    master: Arc<File>,
     logger: Option<Arc<Mutex<File>>>,
-) {
+) -> thread::JoinHandle<()> {
     thread::spawn(move || {
+        let interval = Duration::from_secs(heartbeat_interval);
         while running.load(Ordering::SeqCst) {
-            thread::sleep(Duration::from_secs(heartbeat_interval));
-            if let Err(e) = master.write_all(hb_msg.as_bytes()) {
-                eprintln!("[Heartbeat] Error sending heartbeat: {}", e);
+            // Ожидание короткими шагами, чтобы поток быстро реагировал на остановку
+            let started = Instant::now();
+            while running.load(Ordering::SeqCst) && started.elapsed() < interval {
+                thread::sleep(Duration::from_millis(100));
+            }
+            if !running.load(Ordering::SeqCst) {
                 break;
             }
-            println!("[Heartbeat] Sent: {}", hb_msg.trim_end());
-            log_message(&logger, &format!("[Heartbeat] Sent: {}", hb_msg.trim_end()));
+            // Если программа не читает порт, сообщение пропускается, а поток продолжает работу
+            if let Err(e) = write_all(&master, &hb_msg) {
+                eprintln!("[Heartbeat] Heartbeat dropped: {}", e);
+                continue;
+            }
+            println!("[Heartbeat] Sent: {}", escape_bytes(hb_msg.trim_ascii_end()));
+            log_data(&logger, "Heartbeat", &hb_msg);
         }
         println!("[Heartbeat] Thread exiting.");
-    });
+    })
 }
@@ -0,0 +1,226 @@
+/// Разбирает строку шестнадцатеричных байтов (`"01 03 0A"` или `"01030A"`).
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.is_ascii() {
+        return Err(format!("Invalid hex string '{}'", text));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("Odd number of hex digits in '{}'", text));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hex byte '{}' in '{}'", &digits[i..i + 2], text))
+        })
+        .collect()
+}
+
+/// Форматирует байты в виде `01 03 0A`.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Представляет байты для вывода: печатаемый текст выводится как есть, управляющие символы
+/// и байты, не образующие UTF-8, экранируются (`\r`, `\n`, `\x02`). Преобразование обратимо
+/// через `unescape_bytes`.
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\r' => out.push_str("\\r"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                c if c.is_control() => {
+                    let mut buf = [0u8; 4];
+                    for b in c.encode_utf8(&mut buf).bytes() {
+                        out.push_str(&format!("\\x{:02X}", b));
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\x{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Преобразует текст с экранированием (`\r`, `\n`, `\t`, `\\`, `\xNN`) в байты.
+pub fn unescape_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => out.push(b'\r'),
+            Some('n') => out.push(b'\n'),
+            Some('t') => out.push(b'\t'),
+            Some('\\') => out.push(b'\\'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&digits, 16)
+                    .ok()
+                    .filter(|_| digits.len() == 2)
+                    .ok_or_else(|| format!("Invalid escape '\\x{}' in '{}'", digits, text))?;
+                out.push(byte);
+            }
+            Some(other) => return Err(format!("Unknown escape '\\{}' in '{}'", other, text)),
+            None => return Err(format!("Trailing backslash in '{}'", text)),
+        }
+    }
+    Ok(out)
+}
+
+/// Элемент шаблона байтов.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HexToken {
+    Byte(u8),
+    /// `??` — любой один байт.
+    Any,
+    /// `*` — любое число байтов; в конце шаблона поглощает все принятые байты.
+    Rest,
+}
+
+/// Результат сопоставления шаблона с началом принятых байтов.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatternMatch {
+    /// Шаблон совпал с первыми `n` байтами.
+    Complete(usize),
+    /// Байтов пока недостаточно, но они могут совпасть после приёма следующих.
+    Partial,
+    Mismatch,
+}
+
+/// Шаблон двоичного кадра вида `01 03 ?? ?? 00 02 *`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HexPattern {
+    source: String,
+    tokens: Vec<HexToken>,
+}
+
+impl HexPattern {
+    /// Разбирает шаблон: пары шестнадцатеричных цифр, `??` и `*`; пробелы игнорируются.
+    pub fn parse(text: &str) -> Result<HexPattern, String> {
+        let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '*' {
+                tokens.push(HexToken::Rest);
+                i += 1;
+                continue;
+            }
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let token = match pair.as_str() {
+                "??" => HexToken::Any,
+                _ if pair.len() == 2 && pair.is_ascii() => u8::from_str_radix(&pair, 16)
+                    .map(HexToken::Byte)
+                    .map_err(|_| format!("Invalid hex byte '{}' in pattern '{}'", pair, text))?,
+                _ => return Err(format!("Incomplete hex byte '{}' in pattern '{}'", pair, text)),
+            };
+            tokens.push(token);
+            i += 2;
+        }
+        if tokens.is_empty() {
+            return Err("Empty hex pattern".to_string());
+        }
+        Ok(HexPattern { source: text.trim().to_string(), tokens })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Сопоставляет шаблон с началом данных. `*` в середине шаблона совпадает с наименьшим
+    /// возможным числом байтов.
+    pub fn match_prefix(&self, data: &[u8]) -> PatternMatch {
+        match_tokens(&self.tokens, data, 0)
+    }
+}
+
+fn match_tokens(tokens: &[HexToken], data: &[u8], pos: usize) -> PatternMatch {
+    let Some((token, rest)) = tokens.split_first() else {
+        return PatternMatch::Complete(pos);
+    };
+    match token {
+        HexToken::Rest if rest.is_empty() => PatternMatch::Complete(data.len()),
+        HexToken::Rest => {
+            for start in pos..=data.len() {
+                match match_tokens(rest, data, start) {
+                    PatternMatch::Mismatch => continue,
+                    result => return result,
+                }
+            }
+            PatternMatch::Partial
+        }
+        _ if pos == data.len() => PatternMatch::Partial,
+        HexToken::Byte(byte) if data[pos] != *byte => PatternMatch::Mismatch,
+        _ => match_tokens(rest, data, pos + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(text: &str) -> HexPattern {
+        HexPattern::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_hex_strings() {
+        assert_eq!(parse_hex("01 03 0a").unwrap(), vec![0x01, 0x03, 0x0A]);
+        assert_eq!(parse_hex("01030A").unwrap(), vec![0x01, 0x03, 0x0A]);
+        assert!(parse_hex("01 0").is_err());
+        assert!(parse_hex("0G").is_err());
+        assert_eq!(to_hex(&[0x01, 0xAB]), "01 AB");
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let data = b"OK\r\n\x02\\\xFF\xD0\x9F";
+        assert_eq!(escape_bytes(data), "OK\\r\\n\\x02\\\\\\xFFП");
+        assert_eq!(unescape_bytes(&escape_bytes(data)).unwrap(), data.to_vec());
+        assert!(unescape_bytes("\\x4").is_err());
+        assert!(unescape_bytes("\\q").is_err());
+        assert!(unescape_bytes("tail\\").is_err());
+    }
+
+    #[test]
+    fn parses_patterns() {
+        assert_eq!(pattern(" 01 03 ?? ?? * ").as_str(), "01 03 ?? ?? *");
+        assert_eq!(pattern("0103??*").tokens, pattern("01 03 ?? *").tokens);
+        assert!(HexPattern::parse("").is_err());
+        assert!(HexPattern::parse("01 0").is_err());
+        assert!(HexPattern::parse("01 ?G").is_err());
+        assert!(HexPattern::parse("П1").is_err());
+    }
+
+    #[test]
+    fn matches_prefix_with_wildcards() {
+        let read = pattern("01 03 ?? ?? 00 02");
+        assert_eq!(read.match_prefix(&[0x01, 0x03, 0x00, 0x10, 0x00, 0x02, 0xC4]), PatternMatch::Complete(6));
+        assert_eq!(read.match_prefix(&[0x01, 0x03, 0x00]), PatternMatch::Partial);
+        assert_eq!(read.match_prefix(&[0x01, 0x04]), PatternMatch::Mismatch);
+        assert_eq!(read.match_prefix(&[]), PatternMatch::Partial);
+    }
+
+    #[test]
+    fn rest_wildcard() {
+        // `*` в конце забирает всё принятое
+        assert_eq!(pattern("AA *").match_prefix(&[0xAA, 0x01, 0x02]), PatternMatch::Complete(3));
+        // В середине — наименьшее число байтов до совпадения остатка
+        let framed = pattern("02 * 03");
+        assert_eq!(framed.match_prefix(&[0x02, 0x41, 0x03, 0x42, 0x03]), PatternMatch::Complete(3));
+        assert_eq!(framed.match_prefix(&[0x02, 0x41]), PatternMatch::Partial);
+        assert_eq!(framed.match_prefix(&[0x03]), PatternMatch::Mismatch);
+    }
+}
@@ -0,0 +1,270 @@
+use std::fs::File;
+use std::io;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::hex::escape_bytes;
+use crate::logger::{log_data, log_message, open_log_file};
+use crate::port::{VirtualPort, VirtualPortBuilder};
+use crate::pty::write_all;
+
+/// Время передачи одного символа при 8N1: десять битов.
+pub fn character_time(baud_rate: u32) -> Duration {
+    Duration::from_secs_f64(10.0 / baud_rate.max(1) as f64)
+}
+
+/// Общая шина из нескольких виртуальных портов, как RS-485 с несколькими устройствами: данные,
+/// записанные в любой порт, получают все остальные.
+///
+/// С моделью коллизий каждая передача занимает шину на время своих символов и доставляется
+/// после окончания. Если передачи двух портов перекрываются, перекрывающиеся байты обеих
+/// искажаются (побитовое И, как на линии с доминантным нулём).
+pub struct PortHub {
+    ports: Vec<VirtualPort>,
+    bus: JoinHandle<()>,
+}
+
+impl PortHub {
+    /// Создаёт порты и запускает поток шины. `collisions` — время символа для модели коллизий;
+    /// без него данные доставляются сразу.
+    pub fn start(builders: Vec<VirtualPortBuilder>, collisions: Option<Duration>, log_file: Option<&str>) -> io::Result<PortHub> {
+        let logger = match log_file {
+            Some(path) => {
+                println!("[Info] Logging bus traffic to file: {}", path);
+                Some(open_log_file(path)?)
+            }
+            None => None,
+        };
+        let mut ports = Vec::new();
+        let mut receivers = Vec::new();
+        for builder in builders {
+            let (tx, rx) = mpsc::channel();
+            ports.push(builder.forward(tx).build()?);
+            receivers.push(rx);
+        }
+        let masters = ports.iter().map(|port| Arc::clone(port.master())).collect();
+        let bus = Bus { masters, logger, character_time: collisions, active: Vec::new(), collisions: 0 };
+        Ok(PortHub { ports, bus: start_bus(bus, receivers) })
+    }
+
+    pub fn ports(&self) -> &[VirtualPort] {
+        &self.ports
+    }
+
+    /// Блокирует до остановки всех портов.
+    pub fn wait(self) {
+        for port in self.ports {
+            port.wait();
+        }
+        // Шина останавливается, когда все потоки чтения закрывают каналы
+        let _ = self.bus.join();
+    }
+}
+
+/// Передача, ещё занимающая шину.
+struct Transmission {
+    from: usize,
+    start: Instant,
+    data: Vec<u8>,
+    collided: bool,
+}
+
+impl Transmission {
+    fn end(&self, character_time: Duration) -> Instant {
+        self.start + character_time * self.data.len() as u32
+    }
+}
+
+struct Bus {
+    masters: Vec<Arc<File>>,
+    logger: Option<Arc<Mutex<File>>>,
+    character_time: Option<Duration>,
+    active: Vec<Transmission>,
+    collisions: u64,
+}
+
+impl Bus {
+    fn transmit(&mut self, from: usize, data: Vec<u8>, now: Instant) {
+        let Some(character_time) = self.character_time else {
+            self.deliver(from, &data, false);
+            return;
+        };
+        // Порт передаёт последовательно: новая порция начинается после его предыдущей
+        let start = self
+            .active
+            .iter()
+            .filter(|other| other.from == from)
+            .map(|other| other.end(character_time))
+            .fold(now, Instant::max);
+        let mut transmission = Transmission { from, start, data, collided: false };
+        for other in self.active.iter_mut().filter(|other| other.from != from) {
+            if transmission.start >= other.end(character_time) || other.start >= transmission.end(character_time) {
+                continue;
+            }
+            transmission.collided = true;
+            other.collided = true;
+            let msg = format!("[Bus] Collision between port {} and port {}", other.from, from);
+            println!("{}", msg);
+            log_message(&self.logger, &msg);
+            self.collisions += 1;
+            // Смещение в символах между началами передач
+            let (first, second) = match other.start <= transmission.start {
+                true => (&mut other.data, &mut transmission.data),
+                false => (&mut transmission.data, &mut other.data),
+            };
+            let offset = (transmission.start.max(other.start) - transmission.start.min(other.start)).as_nanos()
+                / character_time.as_nanos().max(1);
+            for (a, b) in first.iter_mut().skip(offset as usize).zip(second.iter_mut()) {
+                let bus = *a & *b;
+                *a = bus;
+                *b = bus;
+            }
+        }
+        self.active.push(transmission);
+    }
+
+    /// Доставляет передачи, закончившиеся к `now`.
+    fn flush(&mut self, now: Instant) {
+        let Some(character_time) = self.character_time else {
+            return;
+        };
+        let (mut done, active): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.active).into_iter().partition(|transmission| transmission.end(character_time) <= now);
+        self.active = active;
+        // Короткая передача, начатая позже, может закончиться раньше длинной
+        done.sort_by_key(|transmission| transmission.end(character_time));
+        for transmission in done {
+            self.deliver(transmission.from, &transmission.data, transmission.collided);
+        }
+    }
+
+    fn deliver(&self, from: usize, data: &[u8], collided: bool) {
+        let tag = format!("{}->*", from);
+        let note = if collided { " (garbled by collision)" } else { "" };
+        println!("[{}] {}{}", tag, escape_bytes(data), note);
+        log_data(&self.logger, &tag, data);
+        for (index, master) in self.masters.iter().enumerate().filter(|(index, _)| *index != from) {
+            if let Err(e) = write_all(master, data) {
+                eprintln!("[Bus] Dropped {} byte(s) for port {}: {}", data.len(), index, e);
+            }
+        }
+    }
+}
+
+/// Собирает данные всех портов и раздаёт их остальным.
+fn start_bus(mut bus: Bus, receivers: Vec<Receiver<Vec<u8>>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut open = vec![true; receivers.len()];
+        while open.iter().any(|&open| open) || !bus.active.is_empty() {
+            let mut idle = true;
+            for (index, rx) in receivers.iter().enumerate() {
+                if !open[index] {
+                    continue;
+                }
+                match rx.try_recv() {
+                    Ok(data) => {
+                        idle = false;
+                        bus.transmit(index, data, Instant::now());
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => open[index] = false,
+                }
+            }
+            bus.flush(Instant::now());
+            if idle {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        if bus.collisions > 0 {
+            println!("[Bus] {} collision(s) in total", bus.collisions);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const CHARACTER: Duration = Duration::from_millis(1);
+
+    /// Шина с моделью коллизий, порты которой записывают полученное в файлы.
+    fn bus(name: &str, ports: usize) -> (Bus, Vec<PathBuf>) {
+        let paths: Vec<PathBuf> = (0..ports)
+            .map(|index| std::env::temp_dir().join(format!("virtualport-{}-{}-{}", std::process::id(), name, index)))
+            .collect();
+        let masters = paths.iter().map(|path| Arc::new(File::create(path).unwrap())).collect();
+        let bus = Bus { masters, logger: None, character_time: Some(CHARACTER), active: Vec::new(), collisions: 0 };
+        (bus, paths)
+    }
+
+    /// Полученные портами данные; файлы удаляются.
+    fn received(paths: Vec<PathBuf>) -> Vec<Vec<u8>> {
+        paths
+            .into_iter()
+            .map(|path| {
+                let data = fs::read(&path).unwrap();
+                let _ = fs::remove_file(&path);
+                data
+            })
+            .collect()
+    }
+
+    #[test]
+    fn back_to_back_transmissions_do_not_collide() {
+        let (mut bus, paths) = bus("hub-back-to-back", 3);
+        let t0 = Instant::now();
+        bus.transmit(0, vec![0xF0, 0xF0], t0);
+        bus.transmit(1, vec![0x0F], t0 + CHARACTER * 2);
+        bus.flush(t0 + CHARACTER * 3);
+        assert!(bus.active.is_empty());
+        assert_eq!(bus.collisions, 0);
+        assert_eq!(received(paths), vec![vec![0x0F], vec![0xF0, 0xF0], vec![0xF0, 0xF0, 0x0F]]);
+    }
+
+    #[test]
+    fn overlapping_transmissions_are_garbled_from_the_offset() {
+        let (mut bus, paths) = bus("hub-overlap", 3);
+        let t0 = Instant::now();
+        bus.transmit(0, vec![0xFF, 0xF0, 0xFF], t0);
+        // Начало через полтора символа: смещение округляется до целого символа
+        bus.transmit(1, vec![0x0F, 0x3C], t0 + CHARACTER * 3 / 2);
+        assert_eq!(bus.collisions, 1);
+        assert!(bus.active.iter().all(|transmission| transmission.collided));
+        bus.flush(t0 + CHARACTER * 4);
+        let garbled = vec![0xFF, 0x00, 0x3C];
+        assert_eq!(received(paths), vec![vec![0x00, 0x3C], garbled.clone(), [garbled, vec![0x00, 0x3C]].concat()]);
+    }
+
+    #[test]
+    fn chunks_of_one_port_are_serialized() {
+        let (mut bus, paths) = bus("hub-serialized", 3);
+        let t0 = Instant::now();
+        bus.transmit(0, vec![0x01, 0x02], t0);
+        bus.transmit(0, vec![0x03], t0);
+        assert_eq!(bus.active[1].start, t0 + CHARACTER * 2);
+        assert_eq!(bus.collisions, 0);
+        // Вторая порция занимает шину после первой, и коллизия задевает только её
+        bus.transmit(1, vec![0xFE], t0 + CHARACTER * 2);
+        assert_eq!(bus.collisions, 1);
+        assert!(!bus.active[0].collided);
+        bus.flush(t0 + CHARACTER * 2);
+        assert_eq!(bus.active.len(), 2);
+        bus.flush(t0 + CHARACTER * 3);
+        assert!(bus.active.is_empty());
+        assert_eq!(received(paths), vec![vec![0x02], vec![0x01, 0x02, 0x02], vec![0x01, 0x02, 0x02, 0x02]]);
+    }
+
+    #[test]
+    fn flush_delivers_in_order_of_completion() {
+        let (mut bus, paths) = bus("hub-order", 3);
+        let t0 = Instant::now();
+        bus.transmit(0, vec![0x01, 0x03, 0x03, 0x03, 0x03], t0);
+        bus.transmit(1, vec![0x03], t0 + CHARACTER);
+        bus.flush(t0 + CHARACTER * 10);
+        let received = received(paths);
+        assert_eq!(received[2], vec![0x03, 0x01, 0x03, 0x03, 0x03, 0x03]);
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use crate::hex::{escape_bytes, parse_hex, to_hex, unescape_bytes};
+use crate::io_handler::read_console_lines;
+use crate::pty::set_nonblocking;
+
+/// Команда консоли перехвата.
+#[derive(Debug, PartialEq)]
+pub enum InterceptCommand {
+    /// Отпустить первый задержанный кадр.
+    Forward,
+    /// Отбросить первый задержанный кадр.
+    Drop,
+    /// Заменить первый задержанный кадр и отпустить его.
+    Edit(Vec<u8>),
+    /// Отправить новый кадр в направлении раньше задержанных.
+    Inject(String, Vec<u8>),
+    List,
+    /// Отпустить все кадры и перестать задерживать новые.
+    Pass,
+    /// Снова задерживать кадры.
+    Hold,
+    Help,
+}
+
+/// Разбирает строку консоли перехвата. Пустая строка отпускает кадр.
+pub fn parse_intercept_command(line: &str) -> Result<InterceptCommand, String> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (name, rest) = match line.trim_start().split_once(' ') {
+        // Текст после команды берётся как есть, с пробелами
+        Some((name, rest)) if !rest.trim().is_empty() => (name, Some(rest)),
+        _ => (line.trim(), None),
+    };
+    match (name, rest) {
+        ("" | "f", None) => Ok(InterceptCommand::Forward),
+        ("d", None) => Ok(InterceptCommand::Drop),
+        ("e", Some(rest)) => unescape_bytes(rest).map(InterceptCommand::Edit),
+        ("x", Some(rest)) => parse_hex(rest).map(InterceptCommand::Edit),
+        ("e", None) => Err("Usage: e <text>".to_string()),
+        ("x", None) => Err("Usage: x <hex>".to_string()),
+        ("i" | "ix", Some(rest)) => {
+            let (direction, data) = rest.trim_start().split_once(' ').unwrap_or((rest.trim(), ""));
+            let data = match name {
+                "i" => unescape_bytes(data)?,
+                _ => parse_hex(data)?,
+            };
+            if direction.is_empty() || data.is_empty() {
+                return Err(format!("Usage: {} <direction> <{}>", name, if name == "i" { "text" } else { "hex" }));
+            }
+            Ok(InterceptCommand::Inject(direction.to_string(), data))
+        }
+        ("i", None) => Err("Usage: i <direction> <text>".to_string()),
+        ("ix", None) => Err("Usage: ix <direction> <hex>".to_string()),
+        ("l", None) => Ok(InterceptCommand::List),
+        ("pass", None) => Ok(InterceptCommand::Pass),
+        ("hold", None) => Ok(InterceptCommand::Hold),
+        ("help" | "?", None) => Ok(InterceptCommand::Help),
+        _ => Err(format!("Unknown command: {} (type help for a list)", line.trim())),
+    }
+}
+
+/// Кадр, ожидающий решения в консоли.
+struct Held {
+    id: u64,
+    direction: &'static str,
+    data: Vec<u8>,
+}
+
+/// Направление пересылки. Канал закрывается, когда источник кадров останавливается.
+struct Route {
+    direction: &'static str,
+    output: Option<Sender<Vec<u8>>>,
+}
+
+struct State {
+    queue: VecDeque<Held>,
+    routes: Vec<Route>,
+    next_id: u64,
+    holding: bool,
+}
+
+impl State {
+    fn send(&self, direction: &str, data: Vec<u8>) {
+        let output = self.routes.iter().find(|route| route.direction == direction).and_then(|route| route.output.as_ref());
+        if let Some(output) = output {
+            let _ = output.send(data);
+        }
+    }
+
+    /// Отпускает все задержанные кадры и перестаёт задерживать новые.
+    fn pass(&mut self) {
+        self.holding = false;
+        for held in std::mem::take(&mut self.queue) {
+            self.send(held.direction, held.data);
+        }
+    }
+
+    fn show_next(&self) {
+        let Some(held) = self.queue.front() else {
+            return;
+        };
+        let more = match self.queue.len() - 1 {
+            0 => String::new(),
+            n => format!(", {} more held", n),
+        };
+        println!("[Intercept] #{} {} {} byte(s){}: {}", held.id, held.direction, held.data.len(), more, escape_bytes(&held.data));
+        println!("[Intercept]   hex: {}", to_hex(&held.data));
+    }
+}
+
+/// Очередь перехвата между потоками чтения и записи: кадры каждого направления задерживаются,
+/// пока пользователь не отпустит, не изменит или не отбросит их в консоли. Кадры отпускаются
+/// по порядку поступления.
+#[derive(Clone)]
+pub struct Interceptor {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Interceptor {
+    fn default() -> Self {
+        Interceptor {
+            state: Arc::new(Mutex::new(State { queue: VecDeque::new(), routes: Vec::new(), next_id: 1, holding: true })),
+        }
+    }
+}
+
+impl Interceptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ставит перехват на направление: кадры из `input` задерживаются, а отпущенные и
+    /// добавленные появляются в возвращаемом канале.
+    pub fn route(&self, direction: &'static str, input: Receiver<Vec<u8>>) -> Receiver<Vec<u8>> {
+        let (tx, rx) = mpsc::channel();
+        self.state.lock().unwrap().routes.push(Route { direction, output: Some(tx) });
+        let interceptor = self.clone();
+        thread::spawn(move || {
+            for data in input {
+                interceptor.hold(direction, data);
+            }
+            interceptor.close(direction);
+        });
+        rx
+    }
+
+    /// Есть ли направления, источник которых ещё работает.
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().routes.iter().any(|route| route.output.is_some())
+    }
+
+    fn hold(&self, direction: &'static str, data: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        if !state.holding {
+            state.send(direction, data);
+            return;
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queue.push_back(Held { id, direction, data });
+        if state.queue.len() == 1 {
+            state.show_next();
+        }
+    }
+
+    fn close(&self, direction: &'static str) {
+        let mut state = self.state.lock().unwrap();
+        let before = state.queue.len();
+        state.queue.retain(|held| held.direction != direction);
+        if state.queue.len() < before {
+            println!("[Intercept] Discarded {} held {} frame(s)", before - state.queue.len(), direction);
+        }
+        if let Some(route) = state.routes.iter_mut().find(|route| route.direction == direction) {
+            route.output = None;
+        }
+    }
+
+    /// Выполняет команду консоли над очередью.
+    pub fn execute(&self, command: InterceptCommand) {
+        let mut state = self.state.lock().unwrap();
+        match command {
+            InterceptCommand::Forward | InterceptCommand::Drop | InterceptCommand::Edit(_) => {
+                let Some(held) = state.queue.pop_front() else {
+                    println!("[Intercept] No held frames.");
+                    return;
+                };
+                match command {
+                    InterceptCommand::Drop => println!("[Intercept] Dropped #{}", held.id),
+                    InterceptCommand::Edit(data) => {
+                        println!("[Intercept] Edited #{}", held.id);
+                        state.send(held.direction, data);
+                    }
+                    _ => state.send(held.direction, held.data),
+                }
+                state.show_next();
+            }
+            InterceptCommand::Inject(direction, data) => {
+                let route = state.routes.iter().find(|route| route.direction.eq_ignore_ascii_case(&direction));
+                match route {
+                    Some(route) => {
+                        println!("[Intercept] Injected {} byte(s) {}", data.len(), route.direction);
+                        state.send(route.direction, data);
+                    }
+                    None => {
+                        let directions: Vec<&str> = state.routes.iter().map(|route| route.direction).collect();
+                        eprintln!("[Intercept] Unknown direction: {} (expected {})", direction, directions.join(" or "));
+                    }
+                }
+            }
+            InterceptCommand::List => {
+                if state.queue.is_empty() {
+                    println!("[Intercept] No held frames.");
+                }
+                for held in &state.queue {
+                    println!("[Intercept] #{} {} {}", held.id, held.direction, escape_bytes(&held.data));
+                }
+            }
+            InterceptCommand::Pass => {
+                state.pass();
+                println!("[Intercept] Passing frames through; type 'hold' to intercept again.");
+            }
+            InterceptCommand::Hold => {
+                state.holding = true;
+                println!("[Intercept] Holding frames.");
+            }
+            InterceptCommand::Help => print_help(&state.routes),
+        }
+    }
+}
+
+fn print_help(routes: &[Route]) {
+    let directions: Vec<&str> = routes.iter().map(|route| route.direction).collect();
+    println!("[Intercept] Commands for the first held frame ({}):", directions.join(", "));
+    println!("  f or Enter              forward it unchanged");
+    println!("  d                       drop it");
+    println!("  e <text>                replace it with text (\\r, \\n, \\xNN escapes) and forward");
+    println!("  x <hex>                 replace it with hex bytes and forward");
+    println!("  i <direction> <text>    send a new frame ahead of the held ones");
+    println!("  ix <direction> <hex>    the same with hex bytes");
+    println!("  l                       list held frames");
+    println!("  pass / hold             stop or resume holding frames");
+}
+
+/// Читает команды перехвата из консоли, пока работает хотя бы одно направление. Если ввод
+/// консоли закончился раньше, задержанные кадры отпускаются и перехват выключается.
+pub fn start_intercept_console(interceptor: Interceptor) -> JoinHandle<()> {
+    if let Err(e) = set_nonblocking(0) {
+        eprintln!("[Intercept] Cannot switch stdin to non-blocking mode: {}", e);
+    }
+    interceptor.execute(InterceptCommand::Help);
+    thread::spawn(move || {
+        read_console_lines(
+            || interceptor.is_open(),
+            |line| {
+                match std::str::from_utf8(&line).map_err(|e| e.to_string()).and_then(parse_intercept_command) {
+                    Ok(command) => interceptor.execute(command),
+                    Err(e) => eprintln!("[Intercept] {}", e),
+                }
+                true
+            },
+        );
+        // Консоль закрыта (например, stdin перенаправлен из файла): решать о кадрах больше некому
+        let mut state = interceptor.state.lock().unwrap();
+        if state.routes.iter().any(|route| route.output.is_some()) {
+            state.pass();
+            println!("[Intercept] Console input closed, passing all frames through.");
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::TryRecvError;
+
+    type Frames = Receiver<Vec<u8>>;
+
+    /// Перехват с направлениями `A->B` и `B->A`. Кадры задерживаются вызовом `hold`, поэтому
+    /// отправители входных каналов возвращаются, чтобы потоки маршрутов не закрыли направления.
+    fn interceptor() -> (Interceptor, [Frames; 2], [Sender<Vec<u8>>; 2]) {
+        let interceptor = Interceptor::new();
+        let (a_tx, a_rx) = mpsc::channel();
+        let (b_tx, b_rx) = mpsc::channel();
+        let outputs = [interceptor.route("A->B", a_rx), interceptor.route("B->A", b_rx)];
+        (interceptor, outputs, [a_tx, b_tx])
+    }
+
+    fn drain(rx: &Frames) -> Vec<Vec<u8>> {
+        rx.try_iter().collect()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse_intercept_command(""), Ok(InterceptCommand::Forward));
+        assert_eq!(parse_intercept_command("f\r\n"), Ok(InterceptCommand::Forward));
+        assert_eq!(parse_intercept_command(" d "), Ok(InterceptCommand::Drop));
+        assert_eq!(parse_intercept_command("e AT a\\r"), Ok(InterceptCommand::Edit(b"AT a\r".to_vec())));
+        assert_eq!(parse_intercept_command("x 41 0d"), Ok(InterceptCommand::Edit(vec![0x41, 0x0D])));
+        assert_eq!(parse_intercept_command("i a->b hi there"), Ok(InterceptCommand::Inject("a->b".to_string(), b"hi there".to_vec())));
+        assert_eq!(parse_intercept_command("ix B->A 0102"), Ok(InterceptCommand::Inject("B->A".to_string(), vec![1, 2])));
+        assert_eq!(parse_intercept_command("pass"), Ok(InterceptCommand::Pass));
+        assert_eq!(parse_intercept_command("?"), Ok(InterceptCommand::Help));
+    }
+
+    #[test]
+    fn reports_usage_errors() {
+        for (line, error) in [
+            ("e", "Usage: e <text>"),
+            ("x  ", "Usage: x <hex>"),
+            ("i", "Usage: i <direction> <text>"),
+            ("i A->B", "Usage: i <direction> <text>"),
+            ("ix A->B", "Usage: ix <direction> <hex>"),
+            ("d 1", "Unknown command: d 1 (type help for a list)"),
+            ("q", "Unknown command: q (type help for a list)"),
+        ] {
+            assert_eq!(parse_intercept_command(line), Err(error.to_string()), "{:?}", line);
+        }
+        assert!(parse_intercept_command("x 4").is_err());
+        assert!(parse_intercept_command("ix A->B zz").is_err());
+    }
+
+    #[test]
+    fn forwards_drops_and_edits_the_first_held_frame() {
+        let (interceptor, [a_to_b, b_to_a], _inputs) = interceptor();
+        interceptor.hold("A->B", b"one".to_vec());
+        interceptor.hold("B->A", b"two".to_vec());
+        interceptor.hold("A->B", b"three".to_vec());
+        assert!(drain(&a_to_b).is_empty());
+        interceptor.execute(InterceptCommand::Forward);
+        assert_eq!(drain(&a_to_b), vec![b"one".to_vec()]);
+        interceptor.execute(InterceptCommand::Edit(b"TWO".to_vec()));
+        assert_eq!(drain(&b_to_a), vec![b"TWO".to_vec()]);
+        interceptor.execute(InterceptCommand::Drop);
+        interceptor.execute(InterceptCommand::Forward);
+        assert!(drain(&a_to_b).is_empty());
+        assert!(interceptor.state.lock().unwrap().queue.is_empty());
+    }
+
+    #[test]
+    fn injects_ahead_of_held_frames() {
+        let (interceptor, [a_to_b, b_to_a], _inputs) = interceptor();
+        interceptor.hold("B->A", b"held".to_vec());
+        // Направление сравнивается без учёта регистра
+        interceptor.execute(InterceptCommand::Inject("b->a".to_string(), b"new".to_vec()));
+        interceptor.execute(InterceptCommand::Inject("A->C".to_string(), b"lost".to_vec()));
+        interceptor.execute(InterceptCommand::Forward);
+        assert_eq!(drain(&b_to_a), vec![b"new".to_vec(), b"held".to_vec()]);
+        assert!(drain(&a_to_b).is_empty());
+    }
+
+    #[test]
+    fn pass_releases_held_frames_in_arrival_order() {
+        let (interceptor, [a_to_b, b_to_a], _inputs) = interceptor();
+        for (direction, data) in [("A->B", "1"), ("B->A", "2"), ("A->B", "3"), ("B->A", "4")] {
+            interceptor.hold(direction, data.as_bytes().to_vec());
+        }
+        interceptor.execute(InterceptCommand::Pass);
+        assert_eq!(drain(&a_to_b), vec![b"1".to_vec(), b"3".to_vec()]);
+        assert_eq!(drain(&b_to_a), vec![b"2".to_vec(), b"4".to_vec()]);
+        // Без задержки кадры проходят сразу, после `hold` снова задерживаются
+        interceptor.hold("A->B", b"5".to_vec());
+        assert_eq!(drain(&a_to_b), vec![b"5".to_vec()]);
+        interceptor.execute(InterceptCommand::Hold);
+        interceptor.hold("A->B", b"6".to_vec());
+        assert!(drain(&a_to_b).is_empty());
+        interceptor.execute(InterceptCommand::Forward);
+        assert_eq!(drain(&a_to_b), vec![b"6".to_vec()]);
+    }
+
+    #[test]
+    fn close_discards_frames_of_the_closed_direction() {
+        let (interceptor, [a_to_b, b_to_a], _inputs) = interceptor();
+        interceptor.hold("A->B", b"lost".to_vec());
+        interceptor.hold("B->A", b"kept".to_vec());
+        interceptor.close("A->B");
+        assert!(interceptor.is_open());
+        interceptor.execute(InterceptCommand::Forward);
+        assert_eq!(drain(&b_to_a), vec![b"kept".to_vec()]);
+        assert_eq!(a_to_b.try_recv(), Err(TryRecvError::Disconnected));
+        interceptor.close("B->A");
+        assert!(!interceptor.is_open());
+    }
+}
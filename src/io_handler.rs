@@ -1,21 +1,60 @@
 use std::sync::{Arc, Mutex};
 use std::fs::File;
 use std::io::{self, Read, Write};
+use std::sync::mpsc::Sender;
 use std::thread;
-use std::time::Duration;
-use std::collections::HashMap;
-use crate::logger::log_message;
+use std::time::{Duration, Instant};
+use crate::checksum::Checksum;
+use crate::device::{Device, DeviceContext};
+use crate::console::{execute_console_command, parse_console_command};
+use crate::emulator::Emulator;
+use crate::framer::{Framer, FramingSpec};
+use crate::hex::escape_bytes;
+use crate::logger::{log_data, log_message};
+use crate::script::Script;
 
+/// Запускает поток чтения master-устройства, работающий, пока `active` возвращает `true`.
+/// Без явного `framing` принятые данные делятся на строки по `\n`, а hex-шаблоны ищутся
+/// в потоке байтов; с `framing` команды сопоставляются с целыми кадрами, и в лог
+/// записываются кадры.
+///
+/// С `forward` необработанные данные передаются дальше, например второму порту пары: без
+/// скрипта, устройства и таблицы команд — сразу, иначе — кадрами, на которые нет ответа.
+#[allow(clippy::too_many_arguments)]
 pub fn start_reader(
-    running: Arc<std::sync::atomic::AtomicBool>,
+    active: impl Fn() -> bool + Send + 'static,
     master: Arc<File>,
     logger: Option<Arc<Mutex<File>>>,
-    commands: HashMap<String, String>,
+    emulator: Emulator,
+    script: Option<Script>,
+    device: Option<Box<dyn Device>>,
+    framing: Option<FramingSpec>,
+    forward: Option<Sender<Vec<u8>>>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
         let mut buf = [0u8; 1024];
-        let mut received_data = String::new();
-        while running.load(std::sync::atomic::Ordering::SeqCst) {
+        let passthrough = forward.is_some()
+            && script.is_none()
+            && device.is_none()
+            && framing.is_none()
+            && emulator.table().is_empty();
+        let whole_frames = framing.is_some();
+        let mut framer = Framer::new(framing.unwrap_or_default());
+        // Байты, в которых ищутся hex-шаблоны, независимо от деления на строки
+        let mut stream: Vec<u8> = Vec::new();
+        let match_stream = !whole_frames && emulator.table().has_hex_patterns();
+        let mut handler = FrameHandler {
+            logger: &logger,
+            emulator: &emulator,
+            script: script.as_ref(),
+            device,
+            whole_frames,
+            checksum: framer.checksum(),
+            checksum_failures: 0,
+            forward,
+        };
+        while active() {
+            handler.tick();
             match master.as_ref().read(&mut buf) {
                 Ok(0) => {
                     if cfg!(debug_assertions) {
@@ -24,26 +63,34 @@ pub fn start_reader(
                     break;
                 }
                 Ok(n) => {
-                    let text = String::from_utf8_lossy(&buf[..n]);
-                    received_data.push_str(&text);
-                    print!("[Received] {}", text);
-                    io::stdout().flush().unwrap();
-                    log_message(&logger, &format!("[Received] {}", text.trim_end()));
-
-                    while let Some(pos) = received_data.find('\n') {
-                        let command = received_data.drain(..=pos).collect::<String>().trim().to_string();
-                        if let Some(response) = commands.get(&command) {
-                            println!("[Command] Recognized: '{}', responding with '{}'", command, response);
-                            if let Err(e) = master.as_ref().write_all(format!("{}\n", response).as_bytes()) {
-                                eprintln!("[Reader] Error writing response: {}", e);
-                            }
-                            log_message(&logger, &format!("[Response] {}", response.trim_end()));
-                        }
+                    // Данные обрабатываются как байты; текстовое представление только для вывода
+                    let data = &buf[..n];
+                    if passthrough {
+                        handler.forward(data);
+                        continue;
+                    }
+                    // Пересылаемые данные выводит получатель
+                    if handler.forward.is_none() {
+                        println!("[Received] {}", escape_bytes(data));
+                    }
+                    if !whole_frames {
+                        log_data(&logger, "Received", data);
+                    }
+
+                    if match_stream {
+                        stream.extend_from_slice(data);
+                        emulator.respond_stream(&mut stream, "Response");
+                    }
+
+                    for frame in framer.push(data) {
+                        handler.handle(&frame, framer.terminator());
                     }
-                    master.as_ref().flush().expect("[Reader] Failed to flush response");
                 }
                 Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                    thread::sleep(Duration::from_millis(10));
+                    if let Some(frame) = framer.poll(Instant::now()) {
+                        handler.handle(&frame, None);
+                    }
+                    thread::sleep(framer.poll_interval());
                     continue;
                 }
                 Err(e) => {
@@ -52,56 +99,202 @@ pub fn start_reader(
                 }
             }
         }
+        handler.finish();
+        if handler.checksum_failures > 0 {
+            println!("[Reader] {} frame(s) failed checksum verification.", handler.checksum_failures);
+        }
         println!("[Reader] Thread exiting.");
     })
 }
 
+/// Передаёт выделенные кадры скрипту, встроенному устройству и таблице команд.
+struct FrameHandler<'a> {
+    logger: &'a Option<Arc<Mutex<File>>>,
+    emulator: &'a Emulator,
+    script: Option<&'a Script>,
+    device: Option<Box<dyn Device>>,
+    whole_frames: bool,
+    /// Контрольная сумма принимаемых кадров; кадры с неверной суммой не обрабатываются.
+    checksum: Option<Checksum>,
+    checksum_failures: u64,
+    /// Получатель кадров, на которые нет ответа.
+    forward: Option<Sender<Vec<u8>>>,
+}
+
+impl<'a> FrameHandler<'a> {
+    fn device_context(&self) -> DeviceContext<'a> {
+        DeviceContext { scheduler: self.emulator.scheduler(), logger: self.logger }
+    }
+
+    fn tick(&mut self) {
+        let ctx = self.device_context();
+        if let Some(device) = &mut self.device {
+            device.on_tick(Instant::now(), &ctx);
+        }
+    }
+
+    fn finish(&mut self) {
+        let ctx = self.device_context();
+        if let Some(device) = &mut self.device {
+            device.finish(&ctx);
+        }
+    }
+
+    /// `terminator` — окончание строки, если кадры выделяются построчно.
+    fn handle(&mut self, frame: &[u8], terminator: Option<&[u8]>) {
+        if self.whole_frames {
+            log_data(self.logger, "Received", frame);
+        }
+        // Сумма проверяется по кадру без одного окончания строки: её байты могут совпадать
+        // с пробелами или `\r`, поэтому края кадра не обрезаются
+        let body = terminator.and_then(|terminator| frame.strip_suffix(terminator)).unwrap_or(frame);
+        if let Some(checksum) = self.checksum.filter(|checksum| !checksum.verify(body)) {
+            self.checksum_failures += 1;
+            let msg = format!(
+                "[Checksum] Bad {} in frame '{}' ({} failure(s) so far), ignoring it",
+                checksum,
+                escape_bytes(body),
+                self.checksum_failures
+            );
+            eprintln!("{}", msg);
+            log_message(self.logger, &msg);
+            return;
+        }
+        // Строки сравниваются без окончания и пробелов по краям, остальные кадры — целиком
+        let command = if terminator.is_some() { frame.trim_ascii() } else { frame };
+        // Скрипт обрабатывает кадр первым; необработанные кадры ищутся в таблице
+        if self.script.is_some_and(|script| script.on_line(command)) {
+            return;
+        }
+        let ctx = self.device_context();
+        if let Some(device) = &mut self.device {
+            if device.on_frame(command, &ctx) {
+                return;
+            }
+        }
+        // Ответ отправляется потоком планировщика, чтение не блокируется
+        let responded = if self.whole_frames {
+            self.emulator.respond_frame(command, "Response")
+        } else {
+            self.emulator.respond(command, "Response")
+        };
+        if !responded {
+            self.forward(frame);
+        }
+    }
+
+    fn forward(&self, data: &[u8]) {
+        if let Some(forward) = &self.forward {
+            let _ = forward.send(data.to_vec());
+        }
+    }
+}
+
 pub fn start_writer(
-    running: Arc<std::sync::atomic::AtomicBool>,
+    active: impl Fn() -> bool + Send + 'static,
     master: Arc<File>,
+    slave: Arc<File>,
     logger: Option<Arc<Mutex<File>>>,
-    commands: HashMap<String, String>,
+    emulator: Emulator,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let stdin = io::stdin();
-        let mut stdin_lock = stdin.lock();
-        let mut input_buf = [0u8; 1024];
-        let mut current_line = String::new();
-
-        while running.load(std::sync::atomic::Ordering::SeqCst) {
-            match stdin_lock.read(&mut input_buf) {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    let bytes = &input_buf[..n];
-                    current_line.push_str(&String::from_utf8_lossy(bytes));
-
-                    while let Some(pos) = current_line.find('\n') {
-                        let line = current_line.drain(..=pos).collect::<String>();
-                        let trimmed = line.trim().to_string();
-                        let response = if let Some(resp) = commands.get(&trimmed) {
-                            println!("[Command] Recognized: '{}', responding with '{}'", trimmed, resp);
-                            format!("{}\n", resp)
-                        } else {
-                            line
-                        };
-
-                        if let Err(e) = master.as_ref().write_all(response.as_bytes()) {
-                            eprintln!("[Writer] Error writing to master: {}", e);
-                            break;
-                        }
-                        log_message(&logger, &format!("[Sent] {}", response.trim_end()));
+        read_console_lines(
+            &active,
+            |mut line| {
+                // Команды консоли обрабатываются локально и не попадают в порт
+                let console = std::str::from_utf8(line.trim_ascii()).ok().and_then(parse_console_command);
+                match console {
+                    Some(Ok(command)) => {
+                        execute_console_command(&command, &slave, &emulator);
+                        return true;
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("[Console] {}", e);
+                        return true;
                     }
+                    None => {}
                 }
-                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                    thread::sleep(Duration::from_millis(10));
-                    continue;
+                if line.trim_ascii().starts_with(b"//") {
+                    line.remove(line.iter().position(|&b| b == b'/').unwrap());
                 }
-                Err(e) => {
-                    eprintln!("[Writer] Error reading from stdin: {}", e);
-                    break;
+
+                if emulator.respond(line.trim_ascii(), "Sent") {
+                    return true;
                 }
-            }
-        }
+
+                if let Err(e) = master.as_ref().write_all(&line) {
+                    eprintln!("[Writer] Error writing to master: {}", e);
+                    return false;
+                }
+                log_data(&logger, "Sent", &line);
+                true
+            },
+        );
         println!("[Writer] Thread exiting.");
     })
 }
+
+/// Читает неблокирующий stdin построчно, пока `active` возвращает `true`. Строка передаётся
+/// в `on_line` вместе с `\n`; `false` из `on_line` прекращает чтение.
+pub(crate) fn read_console_lines(active: impl Fn() -> bool, mut on_line: impl FnMut(Vec<u8>) -> bool) {
+    let stdin = io::stdin();
+    let mut stdin_lock = stdin.lock();
+    let mut input_buf = [0u8; 1024];
+    let mut current_line: Vec<u8> = Vec::new();
+
+    while active() {
+        match stdin_lock.read(&mut input_buf) {
+            Ok(0) => break, // EOF
+            Ok(n) => {
+                current_line.extend_from_slice(&input_buf[..n]);
+                while let Some(pos) = current_line.iter().position(|&b| b == b'\n') {
+                    if !on_line(current_line.drain(..=pos).collect()) {
+                        return;
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => {
+                eprintln!("[Writer] Error reading from stdin: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use crate::commands::CommandTable;
+    use crate::scheduler::start_scheduler;
+
+    fn emulator() -> Emulator {
+        let running = Arc::new(AtomicBool::new(false));
+        let sink = Arc::new(File::create("/dev/null").unwrap());
+        let (scheduler, _) = start_scheduler(running, sink, None);
+        Emulator::new(CommandTable::new(), scheduler, None, false)
+    }
+
+    #[test]
+    fn checksum_bytes_that_look_like_whitespace_are_kept() {
+        let emulator = emulator();
+        let mut handler = FrameHandler {
+            logger: &None,
+            emulator: &emulator,
+            script: None,
+            device: None,
+            whole_frames: true,
+            checksum: Some(Checksum::Crc16Modbus),
+            checksum_failures: 0,
+            forward: None,
+        };
+        // CRC-16/MODBUS("T4409") = 0x208E, в кадре: 8E 20
+        handler.handle(b"T4409\x8E\x20\r\n", Some(b"\r\n"));
+        handler.handle(b"\tT4409\x8E\x20\r\n", Some(b"\r\n"));
+        assert_eq!(handler.checksum_failures, 1);
+    }
+}
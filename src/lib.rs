@@ -0,0 +1,46 @@
+//! Виртуальный последовательный порт на основе PTY.
+//!
+//! ```no_run
+//! use virtualport::VirtualPort;
+//!
+//! let port = VirtualPort::builder()
+//!     .link("/tmp/my_virtual_port")
+//!     .baud_rate(115200)
+//!     .build()
+//!     .expect("failed to create virtual port");
+//! println!("slave: {}", port.slave_path());
+//! port.shutdown();
+//! ```
+
+pub mod at;
+pub mod at_commands;
+pub mod calendar;
+pub mod checksum;
+pub mod cleanup;
+pub mod commands;
+pub mod console;
+pub mod device;
+pub mod emulator;
+pub mod framer;
+pub mod heartbeat;
+pub mod hex;
+pub mod hub;
+pub mod intercept;
+pub mod io_handler;
+pub mod logger;
+pub mod modbus;
+pub mod modbus_master;
+pub mod nmea;
+pub mod pair;
+pub mod port;
+pub mod profile;
+pub mod pty;
+pub mod scheduler;
+pub mod sim800;
+pub mod sniffer;
+pub mod script;
+pub mod tcp_bridge;
+pub mod track;
+pub mod ubx;
+
+pub use port::{VirtualPort, VirtualPortBuilder};
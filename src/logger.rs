@@ -1,6 +1,9 @@
-use std::fs::File;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::hex::escape_bytes;
+use crate::calendar::civil_from_days;
 
 /// Функция для логирования сообщения (если логгер активен).
 pub fn log_message(logger: &Option<Arc<Mutex<File>>>, msg: &str) {
@@ -10,3 +13,34 @@ pub fn log_message(logger: &Option<Arc<Mutex<File>>>, msg: &str) {
         }
     }
 }
+
+/// Логирует переданные или принятые байты без потерь: непечатаемые байты экранируются.
+pub fn log_data(logger: &Option<Arc<Mutex<File>>>, tag: &str, data: &[u8]) {
+    if logger.is_some() {
+        log_message(logger, &format!("[{}] {}", tag, escape_bytes(data)));
+    }
+}
+
+/// Текущее время UTC с миллисекундами: `2024-05-01 12:30:45.123`.
+pub fn timestamp() -> String {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as i64);
+    let (days, day_millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    let seconds = day_millis / 1000;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        day_millis % 1000
+    )
+}
+
+/// Открывает файл лога для дозаписи.
+pub fn open_log_file(path: &str) -> io::Result<Arc<Mutex<File>>> {
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    Ok(Arc::new(Mutex::new(file)))
+}
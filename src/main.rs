@@ -1,144 +1,293 @@
 mod cli;
-mod commands;
-mod cleanup;
-mod logger;
-mod pty;
 mod signal_handler;
-mod heartbeat;
-mod io_handler;
 
 use clap::Parser;
-use std::fs::{remove_file, OpenOptions, File};
-use std::io::{self, Write};
-use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd};
-#[cfg(unix)]
-use std::os::unix::fs::symlink;
-use std::sync::{Arc, Mutex};
-use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
-
-use cli::Args;
-use commands::load_commands_from_file;
-use cleanup::Cleanup;
-use pty::{create_virtual_serial_port, get_slave_name, set_baud_rate, set_nonblocking, set_parity, speed_to_baud};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+use cli::{Args, Command, DeviceKind};
 use signal_handler::setup_signal_handler;
-use heartbeat::start_heartbeat;
-use io_handler::{start_reader, start_writer};
+use virtualport::at::StaticCommands;
+use virtualport::at_commands::hayes_modem;
+use virtualport::commands::{load_commands, load_default_commands, CommandTable};
+use virtualport::device::Device;
+use virtualport::hex::unescape_bytes;
+use virtualport::modbus::{ModbusAscii, ModbusRtu, RegisterMap};
+use virtualport::modbus_master::{ModbusMaster, PollList};
+use virtualport::hub::{character_time, PortHub};
+use virtualport::nmea::NmeaSource;
+use virtualport::pair::PortPair;
+use virtualport::profile::convert_legacy_file;
+use virtualport::sim800::Sim800Commands;
+use virtualport::sniffer::Sniffer;
+use virtualport::tcp_bridge::TcpBridge;
+use virtualport::track::{Playback, Track};
+use virtualport::ubx::UbxReceiver;
+use virtualport::VirtualPort;
 
 fn main() -> io::Result<()> {
     // Разбор аргументов командной строки
     let args = Args::parse();
+    if let Some(command) = &args.command {
+        return run_command(&args, command);
+    }
     if args.verbose {
         println!("[Info] Starting virtual port with arguments: {:?}", args);
     }
 
-    // Загрузка команд из файла
-    let commands = load_commands_from_file("commands.txt");
+    // Загрузка команд: явно заданные файлы обязательны, commands.txt — только если существует.
+    // TCP-мост работает в сыром режиме, поэтому commands.txt для него не загружается
+    let commands = if !args.commands.is_empty() {
+        load_commands(&args.commands)
+    } else if args.tcp_listen.is_some() {
+        Ok(CommandTable::new())
+    } else {
+        load_default_commands()
+    };
+    let commands = match commands {
+        Ok(commands) => commands,
+        Err(e) => {
+            eprintln!("[Error] {}", e);
+            process::exit(1);
+        }
+    };
     if !commands.is_empty() {
         println!("[Info] Loaded {} command(s).", commands.len());
     }
 
-    // Автоматическая очистка символической ссылки при завершении работы
-    let _cleanup = Cleanup::new(args.link.clone());
+    let baud_rate = parse_baud_rate(&args);
+
+    let device = match create_device(&args, baud_rate) {
+        Ok(device) => device,
+        Err(e) => {
+            eprintln!("[Error] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // Сообщения из командной строки допускают экранирование (`\r`, `\n`, `\xNN`)
+    let hb_msg = unescape_arg(&args.hb_msg);
+    let init_msg = args.init_msg.as_deref().map(unescape_arg);
 
     // Настройка обработчика сигналов
     let running = setup_signal_handler();
 
-    // Создание виртуального последовательного порта
-    let pty_result = create_virtual_serial_port(5);
-    let (master, slave) = (pty_result.master, pty_result.slave);
-
-    // Установка неблокирующего режима для master и stdin
-    set_nonblocking(master.as_raw_fd());
-    set_nonblocking(0); // stdin
+    let mut builder = VirtualPort::builder()
+        .link(args.link.clone())
+        .verbose(args.verbose)
+        .enable_echo(args.enable_echo)
+        .heartbeat(args.heartbeat, hb_msg)
+        .baud_rate(baud_rate)
+        .parity(args.parity.clone())
+        .commands(commands)
+        .console(true)
+        .running(Arc::clone(&running));
+    if let Some(msg) = init_msg {
+        builder = builder.init_msg(msg);
+    }
+    if let Some(path) = &args.log_file {
+        builder = builder.log_file(path.clone());
+    }
+    if let Some(path) = &args.script {
+        builder = builder.script(path.clone());
+    }
+    if let Some(framing) = &args.framing {
+        builder = builder.framing(framing.clone());
+    }
+    if let Some(device) = device {
+        builder = builder.device(device);
+    }
 
-    // Получение имени slave-устройства и создание символической ссылки
-    let slave_name = get_slave_name(slave.as_raw_fd());
-    println!("[Info] Virtual serial port created: {}", slave_name);
-    let _ = remove_file(&args.link);
-    if let Err(e) = symlink(&slave_name, &args.link) {
-        eprintln!("[Error] Failed to create symbolic link: {}", e);
-    } else {
-        println!("[Info] Symbolic link created: {} <-> {}", &args.link, slave_name);
+    // С TCP-сервером данные программы уходят клиентам, а консоль продолжает работать
+    let result = match &args.tcp_listen {
+        Some(addr) => TcpBridge::start(builder, addr, args.tcp_policy, running, args.log_file.as_deref()).map(TcpBridge::wait),
+        None => builder.build().map(VirtualPort::wait),
+    };
+    if let Err(e) = result {
+        eprintln!("[Error] {}", e);
+        process::exit(1);
     }
 
-    // Настройка slave-устройства (отключение эха, если требуется)
-    let slave_fd = slave.into_raw_fd();
-    let slave_file = unsafe { File::from_raw_fd(slave_fd) };
-    {
-        let mut termios = tcgetattr(&slave_file).expect("[Error] tcgetattr failed");
-        if !args.enable_echo {
-            termios.local_flags.remove(LocalFlags::ECHO);
-            if args.verbose {
-                println!("[Info] Disabling local echo on slave device.");
+    println!("[Info] Exiting main.");
+    Ok(())
+}
+
+/// Выполняет подкоманду вместо запуска порта.
+fn run_command(args: &Args, command: &Command) -> io::Result<()> {
+    match command {
+        Command::Convert { input, output } => {
+            let profile = match convert_legacy_file(Path::new(input)) {
+                Ok(profile) => profile,
+                Err(e) => {
+                    eprintln!("[Error] {}", e);
+                    process::exit(1);
+                }
+            };
+            match output {
+                Some(path) => {
+                    fs::write(path, profile)?;
+                    println!("[Info] Profile written to {}", path);
+                }
+                None => print!("{}", profile),
+            }
+            Ok(())
+        }
+        Command::Pair { link_a, link_b, commands_a, commands_b, heartbeat_a, heartbeat_b, intercept } => {
+            let baud_rate = parse_baud_rate(args);
+            let hb_msg = unescape_arg(&args.hb_msg);
+            let running = setup_signal_handler();
+            // Порты пары отвечают только на явно заданные команды, commands.txt не загружается
+            let side = |link: &str, commands: &[String], heartbeat: u64| {
+                let commands = match commands.is_empty() {
+                    true => CommandTable::new(),
+                    false => load_commands(commands).unwrap_or_else(|e| {
+                        eprintln!("[Error] {}", e);
+                        process::exit(1);
+                    }),
+                };
+                let builder = VirtualPort::builder()
+                    .link(link)
+                    .verbose(args.verbose)
+                    .enable_echo(args.enable_echo)
+                    .heartbeat(heartbeat, hb_msg.clone())
+                    .baud_rate(baud_rate)
+                    .parity(args.parity.clone())
+                    .commands(commands)
+                    .running(Arc::clone(&running));
+                match &args.framing {
+                    Some(framing) => builder.framing(framing.clone()),
+                    None => builder,
+                }
+            };
+            let pair = PortPair::start(
+                side(link_a, commands_a, *heartbeat_a),
+                side(link_b, commands_b, *heartbeat_b),
+                args.log_file.as_deref(),
+                *intercept,
+            );
+            match pair {
+                Ok(pair) => pair.wait(),
+                Err(e) => {
+                    eprintln!("[Error] {}", e);
+                    process::exit(1);
+                }
             }
-        } else if args.verbose {
-            println!("[Info] Echo enabled on slave device.");
+            println!("[Info] Exiting main.");
+            Ok(())
+        }
+        Command::Hub { ports, link_prefix, collisions } => {
+            let baud_rate = parse_baud_rate(args);
+            let running = setup_signal_handler();
+            let builders = (0..*ports)
+                .map(|index| {
+                    VirtualPort::builder()
+                        .link(format!("{}{}", link_prefix, index))
+                        .verbose(args.verbose)
+                        .enable_echo(args.enable_echo)
+                        .baud_rate(baud_rate)
+                        .parity(args.parity.clone())
+                        .running(Arc::clone(&running))
+                })
+                .collect();
+            let collisions = collisions.then(|| character_time(baud_rate));
+            match PortHub::start(builders, collisions, args.log_file.as_deref()) {
+                Ok(hub) => hub.wait(),
+                Err(e) => {
+                    eprintln!("[Error] {}", e);
+                    process::exit(1);
+                }
+            }
+            println!("[Info] Exiting main.");
+            Ok(())
+        }
+        Command::Sniff { device, link, intercept } => {
+            let baud_rate = parse_baud_rate(args);
+            let running = setup_signal_handler();
+            // Эхо на slave исказило бы обмен, поэтому --enable-echo здесь не применяется;
+            // паритет задаётся только устройству
+            let mut builder = VirtualPort::builder().link(link.clone()).verbose(args.verbose).baud_rate(baud_rate);
+            if let Some(framing) = &args.framing {
+                builder = builder.framing(framing.clone());
+            }
+            match Sniffer::start(builder, device, &args.parity, running, args.log_file.as_deref(), *intercept) {
+                Ok(sniffer) => sniffer.wait(),
+                Err(e) => {
+                    eprintln!("[Error] {}", e);
+                    process::exit(1);
+                }
+            }
+            println!("[Info] Exiting main.");
+            Ok(())
         }
-        tcsetattr(&slave_file, SetArg::TCSANOW, &termios).expect("[Error] tcsetattr failed");
     }
+}
 
-    // Настройка скорости и паритета
-    let baud_rate = match args.baud_rate.parse::<u32>() {
-        Ok(baud) => match speed_to_baud(baud) {
-            Some(br) => br,
-            None => {
-                eprintln!("[Error] Unsupported baud rate: {}", args.baud_rate);
-                return Ok(());
-            }
-        },
+/// Скорость из `--baud-rate`; неверное значение завершает программу с ошибкой.
+fn parse_baud_rate(args: &Args) -> u32 {
+    match args.baud_rate.parse::<u32>() {
+        Ok(baud) => baud,
         Err(_) => {
             eprintln!("[Error] Invalid baud rate: {}", args.baud_rate);
-            return Ok(());
-        }
-    };
-    set_baud_rate(&slave_file, baud_rate);
-    set_parity(&slave_file, &args.parity);
-
-    // Оборачивание master-устройства в Arc для потокобезопасного доступа
-    let master_fd = master.into_raw_fd();
-    let master_file = unsafe { File::from_raw_fd(master_fd) };
-    let mut master_file = Arc::new(master_file);
-
-    // Отправка начального сообщения, если задано
-    if let Some(msg) = &args.init_msg {
-        if args.verbose {
-            println!("[Info] Sending init message: {}", msg);
+            process::exit(1);
         }
-        master_file.write_all(msg.as_bytes()).expect("[Error] Failed to write init message");
     }
+}
 
-    // Инициализация логгера, если задан файл для логирования
-    let logger: Option<Arc<Mutex<File>>> = args.log_file.as_ref().and_then(|path| {
-        OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(path)
-            .map(|file| {
-                println!("[Info] Logging communications to file: {}", path);
-                Arc::new(Mutex::new(file))
-            })
-            .map_err(|e| eprintln!("[Error] Cannot open log file {}: {}", path, e))
-            .ok()
-    });
-
-    // Запуск heartbeat-потока, если задан интервал
-    if args.heartbeat > 0 {
-        start_heartbeat(
-            running.clone(),
-            args.heartbeat,
-            args.hb_msg.clone(),
-            Arc::clone(&master_file),
-            logger.clone(),
-        );
+/// Загружает трек для приёмников GPS.
+fn create_playback(args: &Args) -> io::Result<Playback> {
+    if args.time_scale.is_nan() || args.time_scale <= 0.0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Time scale must be positive"));
     }
+    let track = Track::load(&args.track)?;
+    println!("[Info] Track: {}", track.summary());
+    Ok(Playback::new(track, args.time_scale))
+}
 
-    // Запуск потоков для чтения и записи
-    let reader_handle = start_reader(running.clone(), Arc::clone(&master_file), logger.clone(), commands.clone());
-    let writer_handle = start_writer(running.clone(), Arc::clone(&master_file), logger.clone(), commands.clone());
-
-    let _ = reader_handle.join();
-    let _ = writer_handle.join();
+/// Создаёт встроенную модель устройства, выбранную параметром `--device`.
+fn create_device(args: &Args, baud_rate: u32) -> io::Result<Option<Box<dyn Device>>> {
+    let Some(kind) = args.device else {
+        return Ok(None);
+    };
+    let register_map = || match &args.register_map {
+        Some(path) => RegisterMap::load(Path::new(path)),
+        None => Ok(RegisterMap::default_map()),
+    };
+    let device: Box<dyn Device> = match kind {
+        DeviceKind::ModbusRtu => Box::new(ModbusRtu::new(register_map()?)),
+        DeviceKind::ModbusAscii => Box::new(ModbusAscii::new(register_map()?)),
+        DeviceKind::ModbusMaster => {
+            // Наличие --poll-list для этого режима проверяет clap
+            let path = args.poll_list.as_deref().unwrap_or_default();
+            Box::new(ModbusMaster::new(PollList::load(Path::new(path))?))
+        }
+        DeviceKind::Nmea => Box::new(NmeaSource::new(create_playback(args)?, args.fix_rate, baud_rate)?),
+        DeviceKind::Ubx => Box::new(UbxReceiver::new(create_playback(args)?, args.fix_rate)?),
+        DeviceKind::At | DeviceKind::Sim800 => {
+            let mut modem = match kind {
+                DeviceKind::Sim800 => Sim800Commands::modem(),
+                _ => hayes_modem(),
+            };
+            if let Some(path) = &args.at_commands {
+                let commands = StaticCommands::load(Path::new(path))?;
+                println!("[Info] Loaded {} AT command(s) from {}", commands.len(), path);
+                modem.push_front(Box::new(commands));
+            }
+            Box::new(modem)
+        }
+    };
+    Ok(Some(device))
+}
 
-    println!("[Info] Exiting main.");
-    Ok(())
+/// Разбирает экранированное сообщение из аргумента командной строки.
+fn unescape_arg(text: &str) -> Vec<u8> {
+    match unescape_bytes(text) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("[Error] {}", e);
+            process::exit(1);
+        }
+    }
 }
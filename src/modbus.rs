@@ -0,0 +1,666 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use serde::Deserialize;
+use crate::checksum::Checksum;
+use crate::device::{Device, DeviceContext};
+use crate::framer::{Framing, FramingSpec};
+use crate::hex::parse_hex;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// Адрес широковещательного запроса: записи выполняются всеми ведомыми без ответа.
+const BROADCAST: u8 = 0;
+
+/// Число ячеек каждой таблицы ведомого по умолчанию, если карта регистров не задана.
+const DEFAULT_SIZE: u16 = 100;
+
+/// Таблица данных ведомого Modbus.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Table {
+    Coils,
+    DiscreteInputs,
+    HoldingRegisters,
+    InputRegisters,
+}
+
+impl Table {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Table::Coils => "coil",
+            Table::DiscreteInputs => "discrete input",
+            Table::HoldingRegisters => "holding register",
+            Table::InputRegisters => "input register",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Table, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "coil" | "coils" => Ok(Table::Coils),
+            "discrete" | "discrete_input" | "discrete_inputs" => Ok(Table::DiscreteInputs),
+            "holding" | "holding_register" | "holding_registers" => Ok(Table::HoldingRegisters),
+            "input" | "input_register" | "input_registers" => Ok(Table::InputRegisters),
+            other => Err(format!("unknown table '{}'", other)),
+        }
+    }
+}
+
+/// Данные одного ведомого. Обращение к адресам, которых нет в карте, вызывает исключение 02.
+#[derive(Clone, Debug, Default)]
+pub struct SlaveData {
+    coils: BTreeMap<u16, bool>,
+    discrete_inputs: BTreeMap<u16, bool>,
+    holding_registers: BTreeMap<u16, u16>,
+    input_registers: BTreeMap<u16, u16>,
+}
+
+impl SlaveData {
+    /// Ведомый, у которого все таблицы содержат `size` нулевых ячеек начиная с адреса 0.
+    pub fn zeroed(size: u16) -> Self {
+        let mut data = SlaveData::default();
+        for address in 0..size {
+            data.coils.insert(address, false);
+            data.discrete_inputs.insert(address, false);
+            data.holding_registers.insert(address, 0);
+            data.input_registers.insert(address, 0);
+        }
+        data
+    }
+
+    pub fn set(&mut self, table: Table, address: u16, value: u16) {
+        match table {
+            Table::Coils => {
+                self.coils.insert(address, value != 0);
+            }
+            Table::DiscreteInputs => {
+                self.discrete_inputs.insert(address, value != 0);
+            }
+            Table::HoldingRegisters => {
+                self.holding_registers.insert(address, value);
+            }
+            Table::InputRegisters => {
+                self.input_registers.insert(address, value);
+            }
+        }
+    }
+
+    pub fn get(&self, table: Table, address: u16) -> Option<u16> {
+        match table {
+            Table::Coils => self.coils.get(&address).map(|&v| v as u16),
+            Table::DiscreteInputs => self.discrete_inputs.get(&address).map(|&v| v as u16),
+            Table::HoldingRegisters => self.holding_registers.get(&address).copied(),
+            Table::InputRegisters => self.input_registers.get(&address).copied(),
+        }
+    }
+
+    /// Проверяет, что все адреса `start..start + count` есть в таблице.
+    fn check_range(&self, table: Table, start: u16, count: u16) -> Result<(), u8> {
+        for offset in 0..count {
+            let address = start.checked_add(offset).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            self.get(table, address).ok_or(ILLEGAL_DATA_ADDRESS)?;
+        }
+        Ok(())
+    }
+
+    fn read_bits(&self, table: Table, start: u16, count: u16) -> Result<Vec<u8>, u8> {
+        self.check_range(table, start, count)?;
+        let mut bytes = vec![0u8; (count as usize).div_ceil(8)];
+        for offset in 0..count {
+            if self.get(table, start + offset) == Some(1) {
+                bytes[offset as usize / 8] |= 1 << (offset % 8);
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn read_registers(&self, table: Table, start: u16, count: u16) -> Result<Vec<u8>, u8> {
+        self.check_range(table, start, count)?;
+        Ok((0..count).flat_map(|offset| self.get(table, start + offset).unwrap().to_be_bytes()).collect())
+    }
+}
+
+/// Карта регистров: данные ведомых по их адресам.
+#[derive(Clone, Debug, Default)]
+pub struct RegisterMap {
+    slaves: BTreeMap<u8, SlaveData>,
+}
+
+impl RegisterMap {
+    /// Карта по умолчанию: ведомый 1 с ячейками 0..100 в каждой таблице.
+    pub fn default_map() -> Self {
+        let mut map = RegisterMap::default();
+        map.slaves.insert(1, SlaveData::zeroed(DEFAULT_SIZE));
+        map
+    }
+
+    /// Загружает карту из файла TOML или CSV (по расширению).
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("Cannot open register map '{}': {}", path.display(), e))
+        })?;
+        let result = if path.extension().is_some_and(|ext| ext == "csv") {
+            parse_csv(&text)
+        } else {
+            parse_toml(&text)
+        };
+        result.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn slave(&self, id: u8) -> Option<&SlaveData> {
+        self.slaves.get(&id)
+    }
+
+    pub fn slave_mut(&mut self, id: u8) -> &mut SlaveData {
+        self.slaves.entry(id).or_default()
+    }
+
+    pub fn slave_ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.slaves.keys().copied()
+    }
+}
+
+/// Карта регистров в формате TOML.
+///
+/// ```toml
+/// [[slave]]
+/// id = 1
+/// holding_registers = [{ address = 0, values = [42, 0x1234] }, { address = 100, count = 10 }]
+/// coils = [{ address = 0, values = [true, false, true] }]
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MapFile {
+    #[serde(default, rename = "slave")]
+    slaves: Vec<SlaveSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SlaveSpec {
+    id: u8,
+    #[serde(default)]
+    coils: Vec<BlockSpec>,
+    #[serde(default)]
+    discrete_inputs: Vec<BlockSpec>,
+    #[serde(default)]
+    holding_registers: Vec<BlockSpec>,
+    #[serde(default)]
+    input_registers: Vec<BlockSpec>,
+}
+
+/// Блок последовательных ячеек: явные значения или `count` нулевых ячеек.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockSpec {
+    address: u16,
+    #[serde(default)]
+    values: Vec<ValueSpec>,
+    #[serde(default)]
+    count: u16,
+}
+
+/// Значение ячейки: число или `true`/`false` для дискретных таблиц.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum ValueSpec {
+    Bool(bool),
+    Number(u16),
+}
+
+impl ValueSpec {
+    pub(crate) fn value(self) -> u16 {
+        match self {
+            ValueSpec::Bool(value) => value as u16,
+            ValueSpec::Number(value) => value,
+        }
+    }
+}
+
+fn parse_toml(text: &str) -> Result<RegisterMap, String> {
+    let file: MapFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut map = RegisterMap::default();
+    for slave in &file.slaves {
+        let data = map.slave_mut(slave.id);
+        let tables = [
+            (Table::Coils, &slave.coils),
+            (Table::DiscreteInputs, &slave.discrete_inputs),
+            (Table::HoldingRegisters, &slave.holding_registers),
+            (Table::InputRegisters, &slave.input_registers),
+        ];
+        for (table, blocks) in tables {
+            for block in blocks {
+                let values: Vec<u16> = match block.values.is_empty() {
+                    true => vec![0; block.count as usize],
+                    false => block.values.iter().map(|value| value.value()).collect(),
+                };
+                for (offset, value) in values.into_iter().enumerate() {
+                    let address = u16::try_from(block.address as usize + offset).map_err(|_| {
+                        format!("slave {}: {} block at {} exceeds address 65535", slave.id, table.name(), block.address)
+                    })?;
+                    data.set(table, address, value);
+                }
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Разбирает карту в формате CSV: `slave,table,address,value`. Строки, начинающиеся с `#`,
+/// и заголовок `slave,...` пропускаются; значения допускают префикс `0x`.
+fn parse_csv(text: &str) -> Result<RegisterMap, String> {
+    let mut map = RegisterMap::default();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("slave") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let parsed = match fields.as_slice() {
+            [slave, table, address, value] => parse_csv_number(slave)
+                .and_then(|slave| u8::try_from(slave).map_err(|_| format!("invalid slave id '{}'", slave)))
+                .and_then(|slave| Ok((slave, Table::parse(table)?, parse_csv_number(address)?, parse_csv_number(value)?))),
+            _ => Err("expected slave,table,address,value".to_string()),
+        };
+        let (slave, table, address, value) = parsed.map_err(|e| format!("line {}: {}", index + 1, e))?;
+        map.slave_mut(slave).set(table, address, value);
+    }
+    Ok(map)
+}
+
+fn parse_csv_number(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => match text {
+            "true" => Ok(1),
+            "false" => Ok(0),
+            _ => text.parse(),
+        },
+    };
+    parsed.map_err(|_| format!("invalid number '{}'", text))
+}
+
+/// Ведомый Modbus: выполняет запросы (PDU) над картой регистров независимо от транспорта.
+pub struct ModbusSlave {
+    map: RegisterMap,
+}
+
+impl ModbusSlave {
+    pub fn new(map: RegisterMap) -> Self {
+        ModbusSlave { map }
+    }
+
+    pub fn map(&self) -> &RegisterMap {
+        &self.map
+    }
+
+    /// Обрабатывает PDU (код функции и данные), адресованный ведомому `id`.
+    /// Возвращает PDU ответа или `None`, если отвечать не нужно (чужой адрес, широковещательный запрос).
+    pub fn process(&mut self, id: u8, pdu: &[u8], ctx: &DeviceContext) -> Option<Vec<u8>> {
+        let &function = pdu.first()?;
+        if id == BROADCAST {
+            let ids: Vec<u8> = self.map.slave_ids().collect();
+            for id in ids {
+                let _ = execute(id, self.map.slave_mut(id), pdu, ctx);
+            }
+            return None;
+        }
+        let data = self.map.slaves.get_mut(&id)?;
+        match execute(id, data, pdu, ctx) {
+            Ok(response) => Some(response),
+            Err(code) => {
+                ctx.log(&format!(
+                    "[Modbus] Slave {}: exception {:02X} ({}) for function {:02X}",
+                    id,
+                    code,
+                    exception_name(code),
+                    function
+                ));
+                Some(vec![function | 0x80, code])
+            }
+        }
+    }
+}
+
+/// Название кода исключения Modbus.
+pub(crate) fn exception_name(code: u8) -> &'static str {
+    match code {
+        ILLEGAL_FUNCTION => "illegal function",
+        ILLEGAL_DATA_ADDRESS => "illegal data address",
+        ILLEGAL_DATA_VALUE => "illegal data value",
+        0x04 => "slave device failure",
+        0x05 => "acknowledge",
+        0x06 => "slave device busy",
+        _ => "unknown",
+    }
+}
+
+fn u16_at(data: &[u8], index: usize) -> u16 {
+    u16::from_be_bytes([data[index], data[index + 1]])
+}
+
+/// Проверяет длину данных запроса и количество в допустимых пределах.
+fn check_quantity(count: u16, max: u16) -> Result<(), u8> {
+    if count == 0 || count > max {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    Ok(())
+}
+
+fn write_value(id: u8, data: &mut SlaveData, table: Table, address: u16, value: u16, ctx: &DeviceContext) {
+    let previous = data.get(table, address).unwrap_or_default();
+    data.set(table, address, value);
+    ctx.log(&format!("[Modbus] Slave {}: {} {} = {} (was {})", id, table.name(), address, value, previous));
+}
+
+fn execute(id: u8, data: &mut SlaveData, pdu: &[u8], ctx: &DeviceContext) -> Result<Vec<u8>, u8> {
+    let (&function, body) = pdu.split_first().ok_or(ILLEGAL_FUNCTION)?;
+    let mut response = vec![function];
+    match function {
+        // Read Coils / Read Discrete Inputs
+        0x01 | 0x02 => {
+            if body.len() != 4 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let (start, count) = (u16_at(body, 0), u16_at(body, 2));
+            check_quantity(count, 2000)?;
+            let table = if function == 0x01 { Table::Coils } else { Table::DiscreteInputs };
+            let bits = data.read_bits(table, start, count)?;
+            response.push(bits.len() as u8);
+            response.extend(bits);
+        }
+        // Read Holding Registers / Read Input Registers
+        0x03 | 0x04 => {
+            if body.len() != 4 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let (start, count) = (u16_at(body, 0), u16_at(body, 2));
+            check_quantity(count, 125)?;
+            let table = if function == 0x03 { Table::HoldingRegisters } else { Table::InputRegisters };
+            let registers = data.read_registers(table, start, count)?;
+            response.push(registers.len() as u8);
+            response.extend(registers);
+        }
+        // Write Single Coil
+        0x05 => {
+            if body.len() != 4 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let value = match u16_at(body, 2) {
+                0xFF00 => 1,
+                0x0000 => 0,
+                _ => return Err(ILLEGAL_DATA_VALUE),
+            };
+            let address = u16_at(body, 0);
+            data.check_range(Table::Coils, address, 1)?;
+            write_value(id, data, Table::Coils, address, value, ctx);
+            response.extend_from_slice(body);
+        }
+        // Write Single Register
+        0x06 => {
+            if body.len() != 4 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let address = u16_at(body, 0);
+            data.check_range(Table::HoldingRegisters, address, 1)?;
+            write_value(id, data, Table::HoldingRegisters, address, u16_at(body, 2), ctx);
+            response.extend_from_slice(body);
+        }
+        // Write Multiple Coils
+        0x0F => {
+            if body.len() < 5 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let (start, count, bytes) = (u16_at(body, 0), u16_at(body, 2), body[4] as usize);
+            check_quantity(count, 1968)?;
+            if bytes != (count as usize).div_ceil(8) || body.len() != 5 + bytes {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            data.check_range(Table::Coils, start, count)?;
+            for offset in 0..count {
+                let bit = (body[5 + offset as usize / 8] >> (offset % 8)) & 1;
+                write_value(id, data, Table::Coils, start + offset, bit as u16, ctx);
+            }
+            response.extend_from_slice(&body[..4]);
+        }
+        // Write Multiple Registers
+        0x10 => {
+            if body.len() < 5 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let (start, count, bytes) = (u16_at(body, 0), u16_at(body, 2), body[4] as usize);
+            check_quantity(count, 123)?;
+            if bytes != count as usize * 2 || body.len() != 5 + bytes {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            data.check_range(Table::HoldingRegisters, start, count)?;
+            for offset in 0..count {
+                let value = u16_at(body, 5 + offset as usize * 2);
+                write_value(id, data, Table::HoldingRegisters, start + offset, value, ctx);
+            }
+            response.extend_from_slice(&body[..4]);
+        }
+        // Read/Write Multiple Registers: запись выполняется до чтения
+        0x17 => {
+            if body.len() < 9 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let (read_start, read_count) = (u16_at(body, 0), u16_at(body, 2));
+            let (write_start, write_count, bytes) = (u16_at(body, 4), u16_at(body, 6), body[8] as usize);
+            check_quantity(read_count, 125)?;
+            check_quantity(write_count, 121)?;
+            if bytes != write_count as usize * 2 || body.len() != 9 + bytes {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            data.check_range(Table::HoldingRegisters, read_start, read_count)?;
+            data.check_range(Table::HoldingRegisters, write_start, write_count)?;
+            for offset in 0..write_count {
+                let value = u16_at(body, 9 + offset as usize * 2);
+                write_value(id, data, Table::HoldingRegisters, write_start + offset, value, ctx);
+            }
+            let registers = data.read_registers(Table::HoldingRegisters, read_start, read_count)?;
+            response.push(registers.len() as u8);
+            response.extend(registers);
+        }
+        _ => return Err(ILLEGAL_FUNCTION),
+    }
+    Ok(response)
+}
+
+/// Пауза между кадрами Modbus RTU: 3,5 символа по 11 бит, на высоких скоростях не меньше 2 мс.
+pub(crate) fn rtu_frame_gap(baud_rate: u32) -> Duration {
+    Duration::from_millis(38_500u64.div_ceil(baud_rate.max(1) as u64).max(2))
+}
+
+/// Ведомый Modbus RTU: кадры `адрес, PDU, CRC16` разделяются паузой в 3,5 символа.
+pub struct ModbusRtu {
+    slave: ModbusSlave,
+}
+
+impl ModbusRtu {
+    pub fn new(map: RegisterMap) -> Self {
+        ModbusRtu { slave: ModbusSlave::new(map) }
+    }
+}
+
+impl Device for ModbusRtu {
+    fn name(&self) -> &'static str {
+        "Modbus RTU slave"
+    }
+
+    fn framing(&self, baud_rate: u32) -> FramingSpec {
+        FramingSpec {
+            framing: Framing::Idle(rtu_frame_gap(baud_rate)),
+            escape: None,
+            checksum: Some(Checksum::Crc16Modbus),
+        }
+    }
+
+    fn on_frame(&mut self, frame: &[u8], ctx: &DeviceContext) -> bool {
+        if frame.len() < 4 || !Checksum::Crc16Modbus.verify(frame) {
+            return false;
+        }
+        let id = frame[0];
+        if id != BROADCAST && self.slave.map().slave(id).is_none() {
+            return false;
+        }
+        if let Some(pdu) = self.slave.process(id, &frame[1..frame.len() - 2], ctx) {
+            let mut response = vec![id];
+            response.extend(pdu);
+            Checksum::Crc16Modbus.seal(&mut response);
+            ctx.send(response);
+        }
+        true
+    }
+}
+
+/// Ведомый Modbus ASCII: кадр `:`, адрес и PDU в шестнадцатеричном виде, LRC, `\r\n`.
+pub struct ModbusAscii {
+    slave: ModbusSlave,
+}
+
+impl ModbusAscii {
+    pub fn new(map: RegisterMap) -> Self {
+        ModbusAscii { slave: ModbusSlave::new(map) }
+    }
+}
+
+impl Device for ModbusAscii {
+    fn name(&self) -> &'static str {
+        "Modbus ASCII slave"
+    }
+
+    fn framing(&self, _baud_rate: u32) -> FramingSpec {
+        FramingSpec {
+            framing: Framing::Delimited { start: b':', end: b'\n' },
+            escape: None,
+            checksum: None,
+        }
+    }
+
+    fn on_frame(&mut self, frame: &[u8], ctx: &DeviceContext) -> bool {
+        let Some(body) = frame.strip_prefix(b":").and_then(|body| body.strip_suffix(b"\r\n")) else {
+            return false;
+        };
+        let Ok(message) = std::str::from_utf8(body).map_err(|e| e.to_string()).and_then(parse_hex) else {
+            return false;
+        };
+        if message.len() < 3 {
+            return false;
+        }
+        if !Checksum::Lrc.verify(&message) {
+            ctx.log(&format!("[Modbus] Bad LRC in frame '{}', ignoring it", String::from_utf8_lossy(body)));
+            return true;
+        }
+        let id = message[0];
+        if id != BROADCAST && self.slave.map().slave(id).is_none() {
+            return false;
+        }
+        if let Some(pdu) = self.slave.process(id, &message[1..message.len() - 1], ctx) {
+            let mut response = vec![id];
+            response.extend(pdu);
+            Checksum::Lrc.seal(&mut response);
+            let hex: String = response.iter().map(|b| format!("{:02X}", b)).collect();
+            ctx.send(format!(":{}\r\n", hex).into_bytes());
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use crate::scheduler::start_scheduler;
+
+    /// Передаёт кадр устройству и возвращает результат `on_frame` и записанный в порт ответ.
+    fn exchange(device: &mut dyn Device, name: &str, frame: &[u8]) -> (bool, Vec<u8>) {
+        let path = std::env::temp_dir().join(format!("virtualport-{}-{}", std::process::id(), name));
+        let sink = Arc::new(File::create(&path).unwrap());
+        let (scheduler, handle) = start_scheduler(Arc::new(AtomicBool::new(true)), sink, None);
+        let handled = device.on_frame(frame, &DeviceContext { scheduler: &scheduler, logger: &None });
+        // Планировщик завершается, когда очередь пуста и отправителей не осталось
+        drop(scheduler);
+        handle.join().unwrap();
+        let reply = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        (handled, reply)
+    }
+
+    fn process(slave: &mut ModbusSlave, id: u8, pdu: &[u8]) -> Option<Vec<u8>> {
+        let sink = Arc::new(File::create("/dev/null").unwrap());
+        let (scheduler, _) = start_scheduler(Arc::new(AtomicBool::new(false)), sink, None);
+        slave.process(id, pdu, &DeviceContext { scheduler: &scheduler, logger: &None })
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let mut slave = ModbusSlave::new(RegisterMap::default_map());
+        assert_eq!(process(&mut slave, 1, &[0x06, 0x00, 0x01, 0x12, 0x34]), Some(vec![0x06, 0x00, 0x01, 0x12, 0x34]));
+        let write = [0x10, 0x00, 0x02, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x00, 0x0B];
+        assert_eq!(process(&mut slave, 1, &write), Some(vec![0x10, 0x00, 0x02, 0x00, 0x02]));
+        let read = process(&mut slave, 1, &[0x03, 0x00, 0x00, 0x00, 0x04]);
+        assert_eq!(read, Some(vec![0x03, 0x08, 0x00, 0x00, 0x12, 0x34, 0x00, 0x0A, 0x00, 0x0B]));
+        // Read/Write Multiple Registers: чтение видит только что записанное значение
+        let read_write = [0x17, 0x00, 0x03, 0x00, 0x01, 0x00, 0x03, 0x00, 0x01, 0x02, 0xBE, 0xEF];
+        assert_eq!(process(&mut slave, 1, &read_write), Some(vec![0x17, 0x02, 0xBE, 0xEF]));
+        assert_eq!(process(&mut slave, 1, &[0x04, 0x00, 0x00, 0x00, 0x01]), Some(vec![0x04, 0x02, 0x00, 0x00]));
+    }
+
+    #[test]
+    fn packs_coils_into_bits() {
+        let mut slave = ModbusSlave::new(RegisterMap::default_map());
+        assert_eq!(process(&mut slave, 1, &[0x05, 0x00, 0x00, 0xFF, 0x00]), Some(vec![0x05, 0x00, 0x00, 0xFF, 0x00]));
+        let write = [0x0F, 0x00, 0x07, 0x00, 0x03, 0x01, 0b101];
+        assert_eq!(process(&mut slave, 1, &write), Some(vec![0x0F, 0x00, 0x07, 0x00, 0x03]));
+        // Катушки 0, 7 и 9: младший бит первого байта — первая катушка
+        assert_eq!(process(&mut slave, 1, &[0x01, 0x00, 0x00, 0x00, 0x0A]), Some(vec![0x01, 0x02, 0x81, 0x02]));
+        assert_eq!(process(&mut slave, 1, &[0x05, 0x00, 0x00, 0x12, 0x34]), Some(vec![0x85, ILLEGAL_DATA_VALUE]));
+    }
+
+    #[test]
+    fn exceptions() {
+        let mut slave = ModbusSlave::new(RegisterMap::default_map());
+        assert_eq!(process(&mut slave, 1, &[0x2B, 0x0E, 0x01, 0x00]), Some(vec![0xAB, ILLEGAL_FUNCTION]));
+        let past_end = [0x03, 0x00, (DEFAULT_SIZE - 1) as u8, 0x00, 0x02];
+        assert_eq!(process(&mut slave, 1, &past_end), Some(vec![0x83, ILLEGAL_DATA_ADDRESS]));
+        assert_eq!(process(&mut slave, 1, &[0x03, 0x00, 0x00, 0x00, 0x00]), Some(vec![0x83, ILLEGAL_DATA_VALUE]));
+        assert_eq!(process(&mut slave, 1, &[0x03, 0x00, 0x00]), Some(vec![0x83, ILLEGAL_DATA_VALUE]));
+        assert_eq!(process(&mut slave, 1, &[0x10, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00]), Some(vec![0x90, ILLEGAL_DATA_VALUE]));
+    }
+
+    #[test]
+    fn broadcast_writes_without_reply() {
+        let mut slave = ModbusSlave::new(RegisterMap::default_map());
+        assert_eq!(process(&mut slave, BROADCAST, &[0x06, 0x00, 0x05, 0x00, 0x2A]), None);
+        assert_eq!(slave.map().slave(1).unwrap().get(Table::HoldingRegisters, 5), Some(42));
+        assert_eq!(process(&mut slave, 7, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn rtu_reply_carries_crc() {
+        let mut device = ModbusRtu::new(RegisterMap::default_map());
+        let (handled, reply) = exchange(&mut device, "rtu-read", &[0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A]);
+        assert!(handled);
+        assert_eq!(reply, vec![0x01, 0x03, 0x02, 0x00, 0x00, 0xB8, 0x44]);
+        // Кадр с неверной CRC и кадр чужому ведомому остаются без ответа
+        assert_eq!(exchange(&mut device, "rtu-bad-crc", &[0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0B]), (false, vec![]));
+        let mut other = vec![0x07, 0x03, 0x00, 0x00, 0x00, 0x01];
+        Checksum::Crc16Modbus.seal(&mut other);
+        assert_eq!(exchange(&mut device, "rtu-other", &other), (false, vec![]));
+    }
+
+    #[test]
+    fn ascii_reply_carries_lrc() {
+        let mut device = ModbusAscii::new(RegisterMap::default_map());
+        let (handled, reply) = exchange(&mut device, "ascii-read", b":010300000001FB\r\n");
+        assert!(handled);
+        assert_eq!(reply, b":0103020000FA\r\n");
+        assert_eq!(exchange(&mut device, "ascii-bad-lrc", b":010300000001FC\r\n"), (true, vec![]));
+    }
+}
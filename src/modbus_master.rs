@@ -0,0 +1,576 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use crate::checksum::Checksum;
+use crate::device::{Device, DeviceContext};
+use crate::framer::{Framing, FramingSpec};
+use crate::hex::to_hex;
+use crate::modbus::{exception_name, rtu_frame_gap, Table, ValueSpec};
+
+const DEFAULT_INTERVAL_MS: u64 = 1000;
+const DEFAULT_TIMEOUT_MS: u64 = 500;
+
+/// Функция Modbus в списке опроса.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Function {
+    ReadCoils,
+    ReadDiscreteInputs,
+    ReadHoldingRegisters,
+    ReadInputRegisters,
+    WriteSingleCoil,
+    WriteSingleRegister,
+    WriteMultipleCoils,
+    WriteMultipleRegisters,
+}
+
+impl Function {
+    fn code(self) -> u8 {
+        match self {
+            Function::ReadCoils => 0x01,
+            Function::ReadDiscreteInputs => 0x02,
+            Function::ReadHoldingRegisters => 0x03,
+            Function::ReadInputRegisters => 0x04,
+            Function::WriteSingleCoil => 0x05,
+            Function::WriteSingleRegister => 0x06,
+            Function::WriteMultipleCoils => 0x0F,
+            Function::WriteMultipleRegisters => 0x10,
+        }
+    }
+
+    fn table(self) -> Table {
+        match self {
+            Function::ReadCoils | Function::WriteSingleCoil | Function::WriteMultipleCoils => Table::Coils,
+            Function::ReadDiscreteInputs => Table::DiscreteInputs,
+            Function::ReadHoldingRegisters | Function::WriteSingleRegister | Function::WriteMultipleRegisters => {
+                Table::HoldingRegisters
+            }
+            Function::ReadInputRegisters => Table::InputRegisters,
+        }
+    }
+
+    fn is_read(self) -> bool {
+        matches!(
+            self,
+            Function::ReadCoils | Function::ReadDiscreteInputs | Function::ReadHoldingRegisters | Function::ReadInputRegisters
+        )
+    }
+
+    fn is_bits(self) -> bool {
+        matches!(self.table(), Table::Coils | Table::DiscreteInputs)
+    }
+}
+
+/// Список опроса в формате TOML.
+///
+/// ```toml
+/// interval_ms = 1000
+/// timeout_ms = 500
+/// retries = 2
+///
+/// [[request]]
+/// slave = 1
+/// function = "read_holding_registers"
+/// address = 0
+/// count = 10
+///
+/// [[request]]
+/// slave = 1
+/// function = "write_single_register"
+/// address = 5
+/// value = 1234
+/// once = true
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PollFile {
+    #[serde(default = "default_interval")]
+    interval_ms: u64,
+    #[serde(default = "default_timeout")]
+    timeout_ms: u64,
+    #[serde(default)]
+    retries: u32,
+    #[serde(default, rename = "request")]
+    requests: Vec<RequestSpec>,
+}
+
+fn default_interval() -> u64 {
+    DEFAULT_INTERVAL_MS
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+/// Запрос списка опроса; `interval_ms`, `timeout_ms` и `retries` переопределяют общие значения.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequestSpec {
+    slave: u8,
+    function: Function,
+    address: u16,
+    count: Option<u16>,
+    value: Option<ValueSpec>,
+    #[serde(default)]
+    values: Vec<ValueSpec>,
+    interval_ms: Option<u64>,
+    timeout_ms: Option<u64>,
+    retries: Option<u32>,
+    /// Выполнить запрос один раз, например начальную запись.
+    #[serde(default)]
+    once: bool,
+}
+
+/// Счётчики обмена по одному запросу.
+#[derive(Clone, Debug, Default)]
+pub struct PollStats {
+    pub sent: u64,
+    pub ok: u64,
+    pub timeouts: u64,
+    pub retries: u64,
+    pub crc_errors: u64,
+    pub exceptions: u64,
+    pub invalid: u64,
+    pub failed: u64,
+}
+
+/// Запрос списка опроса вместе с расписанием и статистикой.
+#[derive(Clone, Debug)]
+pub struct PollRequest {
+    pub slave: u8,
+    pub function: Function,
+    pub address: u16,
+    /// Число читаемых или записываемых ячеек.
+    pub count: u16,
+    pub values: Vec<u16>,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub once: bool,
+    pub stats: PollStats,
+    next_due: Instant,
+    done: bool,
+}
+
+impl PollRequest {
+    /// Описание запроса для вывода: `slave 1 read holding registers 0..9`.
+    pub fn describe(&self) -> String {
+        let last = self.address as u32 + self.count.max(1) as u32 - 1;
+        let action = if self.function.is_read() { "read" } else { "write" };
+        match self.count {
+            1 => format!("slave {} {} {} {}", self.slave, action, self.function.table().name(), self.address),
+            _ => format!("slave {} {} {}s {}..{}", self.slave, action, self.function.table().name(), self.address, last),
+        }
+    }
+
+    /// Кадр запроса с CRC.
+    fn frame(&self) -> Vec<u8> {
+        let mut frame = vec![self.slave, self.function.code()];
+        frame.extend(self.address.to_be_bytes());
+        match self.function {
+            Function::WriteSingleCoil => frame.extend(if self.values[0] != 0 { [0xFF, 0x00] } else { [0x00, 0x00] }),
+            Function::WriteSingleRegister => frame.extend(self.values[0].to_be_bytes()),
+            Function::WriteMultipleCoils => {
+                let mut bytes = vec![0u8; self.values.len().div_ceil(8)];
+                for (offset, &value) in self.values.iter().enumerate() {
+                    if value != 0 {
+                        bytes[offset / 8] |= 1 << (offset % 8);
+                    }
+                }
+                frame.extend(self.count.to_be_bytes());
+                frame.push(bytes.len() as u8);
+                frame.extend(bytes);
+            }
+            Function::WriteMultipleRegisters => {
+                frame.extend(self.count.to_be_bytes());
+                frame.push((self.values.len() * 2) as u8);
+                frame.extend(self.values.iter().flat_map(|value| value.to_be_bytes()));
+            }
+            _ => frame.extend(self.count.to_be_bytes()),
+        }
+        Checksum::Crc16Modbus.seal(&mut frame);
+        frame
+    }
+
+    /// Проверяет PDU ответа (без адреса и CRC) и возвращает прочитанные значения.
+    fn decode(&self, pdu: &[u8]) -> Result<Vec<u16>, String> {
+        let body = &pdu[1..];
+        if self.function.is_read() {
+            let expected = match self.function.is_bits() {
+                true => (self.count as usize).div_ceil(8),
+                false => self.count as usize * 2,
+            };
+            if body.first().map(|&n| n as usize) != Some(expected) || body.len() != expected + 1 {
+                return Err(format!("expected {} data byte(s), got {}", expected, to_hex(body)));
+            }
+            let data = &body[1..];
+            let values = match self.function.is_bits() {
+                true => (0..self.count as usize).map(|i| (data[i / 8] >> (i % 8) & 1) as u16).collect(),
+                false => data.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect(),
+            };
+            return Ok(values);
+        }
+        // Ответ на запись повторяет адрес и значение либо адрес и количество
+        let request = self.frame();
+        let echo = &request[2..6];
+        if body != echo {
+            return Err(format!("expected echo {}, got {}", to_hex(echo), to_hex(body)));
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// Список опроса: запросы и их расписание.
+#[derive(Clone, Debug, Default)]
+pub struct PollList {
+    pub requests: Vec<PollRequest>,
+}
+
+impl PollList {
+    /// Загружает список опроса из файла TOML.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("Cannot open poll list '{}': {}", path.display(), e))
+        })?;
+        parse_poll_list(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+    }
+}
+
+fn parse_poll_list(text: &str) -> Result<PollList, String> {
+    let file: PollFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    if file.requests.is_empty() {
+        return Err("the poll list has no [[request]] entries".to_string());
+    }
+    let now = Instant::now();
+    let mut requests = Vec::new();
+    for (index, spec) in file.requests.into_iter().enumerate() {
+        let function = spec.function;
+        let values: Vec<u16> = match spec.value {
+            Some(value) => vec![value.value()],
+            None => spec.values.iter().map(|value| value.value()).collect(),
+        };
+        let (count, max) = match function {
+            Function::ReadCoils | Function::ReadDiscreteInputs => (spec.count.unwrap_or(1), 2000),
+            Function::ReadHoldingRegisters | Function::ReadInputRegisters => (spec.count.unwrap_or(1), 125),
+            Function::WriteSingleCoil | Function::WriteSingleRegister => (1, 1),
+            Function::WriteMultipleCoils => (values.len() as u16, 1968),
+            Function::WriteMultipleRegisters => (values.len() as u16, 123),
+        };
+        let request = index + 1;
+        if !function.is_read() && values.is_empty() {
+            return Err(format!("request {}: a write needs 'value' or 'values'", request));
+        }
+        if matches!(function, Function::WriteSingleCoil | Function::WriteSingleRegister) && values.len() != 1 {
+            return Err(format!("request {}: a single write takes exactly one value", request));
+        }
+        if count == 0 || count > max {
+            return Err(format!("request {}: count must be between 1 and {}, got {}", request, max, count));
+        }
+        if spec.address as u32 + count as u32 > 0x1_0000 {
+            return Err(format!("request {}: block at {} exceeds address 65535", request, spec.address));
+        }
+        requests.push(PollRequest {
+            slave: spec.slave,
+            function,
+            address: spec.address,
+            count,
+            values,
+            interval: Duration::from_millis(spec.interval_ms.unwrap_or(file.interval_ms)),
+            timeout: Duration::from_millis(spec.timeout_ms.unwrap_or(file.timeout_ms)),
+            retries: spec.retries.unwrap_or(file.retries),
+            once: spec.once,
+            stats: PollStats::default(),
+            next_due: now,
+            done: false,
+        });
+    }
+    Ok(PollList { requests })
+}
+
+/// Запрос, на который ожидается ответ.
+struct Pending {
+    index: usize,
+    /// Номер попытки, начиная с 1.
+    attempt: u32,
+    sent_at: Instant,
+}
+
+/// Ведущий Modbus RTU: по расписанию из списка опроса отправляет запросы устройству на стороне
+/// slave, проверяет CRC, длину и содержимое ответов, повторяет запросы по таймауту и выводит
+/// статистику при остановке.
+pub struct ModbusMaster {
+    list: PollList,
+    pending: Option<Pending>,
+}
+
+impl ModbusMaster {
+    pub fn new(list: PollList) -> Self {
+        ModbusMaster { list, pending: None }
+    }
+
+    pub fn list(&self) -> &PollList {
+        &self.list
+    }
+
+    fn send(&mut self, index: usize, attempt: u32, now: Instant, ctx: &DeviceContext) {
+        let request = &mut self.list.requests[index];
+        request.stats.sent += 1;
+        if attempt > 1 {
+            request.stats.retries += 1;
+        }
+        ctx.send_request(request.frame());
+        self.pending = Some(Pending { index, attempt, sent_at: now });
+    }
+
+    /// Неудачная попытка: повторяет запрос, пока не исчерпаны повторы.
+    fn fail(&mut self, now: Instant, reason: &str, ctx: &DeviceContext) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let request = &mut self.list.requests[pending.index];
+        let attempts = request.retries + 1;
+        if pending.attempt < attempts {
+            ctx.log(&format!(
+                "[Poll] {}: {} (attempt {}/{}), retrying",
+                request.describe(),
+                reason,
+                pending.attempt,
+                attempts
+            ));
+            self.send(pending.index, pending.attempt + 1, now, ctx);
+        } else {
+            request.stats.failed += 1;
+            ctx.log(&format!("[Poll] {}: {} (attempt {}/{}), giving up", request.describe(), reason, pending.attempt, attempts));
+            self.complete(pending.index);
+        }
+    }
+
+    fn complete(&mut self, index: usize) {
+        let request = &mut self.list.requests[index];
+        request.done = request.once;
+        self.pending = None;
+    }
+}
+
+impl Device for ModbusMaster {
+    fn name(&self) -> &'static str {
+        "Modbus RTU master"
+    }
+
+    fn framing(&self, baud_rate: u32) -> FramingSpec {
+        // CRC проверяется ведущим, чтобы считать ошибки по каждому запросу
+        FramingSpec { framing: Framing::Idle(rtu_frame_gap(baud_rate)), escape: None, checksum: None }
+    }
+
+    fn on_frame(&mut self, frame: &[u8], ctx: &DeviceContext) -> bool {
+        let now = Instant::now();
+        let Some(pending) = &self.pending else {
+            ctx.log(&format!("[Poll] Unexpected frame {} with no request pending", to_hex(frame)));
+            return true;
+        };
+        let index = pending.index;
+        let elapsed = now - pending.sent_at;
+        let request = &mut self.list.requests[index];
+        if frame.len() < 4 || !Checksum::Crc16Modbus.verify(frame) {
+            request.stats.crc_errors += 1;
+            self.fail(now, &format!("bad CRC in response {}", to_hex(frame)), ctx);
+            return true;
+        }
+        let (id, pdu) = (frame[0], &frame[1..frame.len() - 2]);
+        if id != request.slave || pdu[0] & 0x7F != request.function.code() {
+            // Ответ другого устройства или на другой запрос: ожидание продолжается
+            ctx.log(&format!("[Poll] {}: ignoring unrelated response {}", request.describe(), to_hex(frame)));
+            return true;
+        }
+        if pdu[0] & 0x80 != 0 {
+            let code = pdu.get(1).copied().unwrap_or_default();
+            request.stats.exceptions += 1;
+            ctx.log(&format!("[Poll] {}: exception {:02X} ({})", request.describe(), code, exception_name(code)));
+            self.complete(index);
+            return true;
+        }
+        match request.decode(pdu) {
+            Ok(values) => {
+                request.stats.ok += 1;
+                let msg = match request.function.is_read() {
+                    true => format!("[Poll] {}: {:?} ({} ms)", request.describe(), values, elapsed.as_millis()),
+                    false => format!("[Poll] {}: {:?} ok ({} ms)", request.describe(), request.values, elapsed.as_millis()),
+                };
+                ctx.log(&msg);
+                self.complete(index);
+            }
+            Err(e) => {
+                request.stats.invalid += 1;
+                self.fail(now, &format!("invalid response: {}", e), ctx);
+            }
+        }
+        true
+    }
+
+    fn on_tick(&mut self, now: Instant, ctx: &DeviceContext) {
+        if let Some(pending) = &self.pending {
+            let request = &mut self.list.requests[pending.index];
+            if now - pending.sent_at >= request.timeout {
+                request.stats.timeouts += 1;
+                let reason = format!("no response within {} ms", request.timeout.as_millis());
+                self.fail(now, &reason, ctx);
+            }
+            return;
+        }
+        // Запросы выполняются по одному; из просроченных первым идёт стоящий раньше в списке
+        let due = self.list.requests.iter().position(|request| !request.done && request.next_due <= now);
+        if let Some(index) = due {
+            let request = &mut self.list.requests[index];
+            request.next_due = (request.next_due + request.interval).max(now);
+            self.send(index, 1, now, ctx);
+        }
+    }
+
+    fn finish(&mut self, ctx: &DeviceContext) {
+        ctx.log("[Poll] Statistics:");
+        for request in &self.list.requests {
+            let stats = &request.stats;
+            ctx.log(&format!(
+                "[Poll]   {}: sent {}, ok {}, timeouts {}, retries {}, CRC errors {}, exceptions {}, invalid {}, failed {}",
+                request.describe(),
+                stats.sent,
+                stats.ok,
+                stats.timeouts,
+                stats.retries,
+                stats.crc_errors,
+                stats.exceptions,
+                stats.invalid,
+                stats.failed
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use crate::scheduler::start_scheduler;
+
+    fn request(toml: &str) -> PollRequest {
+        parse_poll_list(&format!("[[request]]\n{}", toml)).unwrap().requests.remove(0)
+    }
+
+    fn sealed(mut frame: Vec<u8>) -> Vec<u8> {
+        Checksum::Crc16Modbus.seal(&mut frame);
+        frame
+    }
+
+    /// Выполняет `f` с контекстом устройства и возвращает байты, записанные в порт.
+    fn capture(name: &str, f: impl FnOnce(&DeviceContext)) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("virtualport-{}-{}", std::process::id(), name));
+        let sink = Arc::new(File::create(&path).unwrap());
+        let (scheduler, handle) = start_scheduler(Arc::new(AtomicBool::new(true)), sink, None);
+        f(&DeviceContext { scheduler: &scheduler, logger: &None });
+        // Планировщик завершается, когда очередь пуста и отправителей не осталось
+        drop(scheduler);
+        handle.join().unwrap();
+        let written = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        written
+    }
+
+    #[test]
+    fn builds_request_frames() {
+        let cases = [
+            ("slave = 17\nfunction = \"read_coils\"\naddress = 19\ncount = 37", "11 01 00 13 00 25 0E 84"),
+            ("slave = 17\nfunction = \"read_holding_registers\"\naddress = 107\ncount = 3", "11 03 00 6B 00 03 76 87"),
+            ("slave = 17\nfunction = \"write_single_coil\"\naddress = 172\nvalue = true", "11 05 00 AC FF 00 4E 8B"),
+            ("slave = 17\nfunction = \"write_single_register\"\naddress = 1\nvalue = 3", "11 06 00 01 00 03 9A 9B"),
+            (
+                "slave = 17\nfunction = \"write_multiple_coils\"\naddress = 19\nvalues = [1, 0, 1, 1, 0, 0, 1, 1, true, false]",
+                "11 0F 00 13 00 0A 02 CD 01 BF 0B",
+            ),
+            (
+                "slave = 17\nfunction = \"write_multiple_registers\"\naddress = 1\nvalues = [10, 258]",
+                "11 10 00 01 00 02 04 00 0A 01 02 C6 F0",
+            ),
+        ];
+        for (toml, expected) in cases {
+            assert_eq!(to_hex(&request(toml).frame()), expected, "{}", toml);
+        }
+    }
+
+    #[test]
+    fn decodes_responses() {
+        let read = request("slave = 1\nfunction = \"read_holding_registers\"\naddress = 0\ncount = 2");
+        assert_eq!(read.decode(&[0x03, 0x04, 0x00, 0x0A, 0x01, 0x02]), Ok(vec![10, 258]));
+        assert!(read.decode(&[0x03, 0x02, 0x00, 0x0A]).is_err());
+        assert!(read.decode(&[0x03, 0x04, 0x00, 0x0A, 0x01]).is_err());
+        let coils = request("slave = 1\nfunction = \"read_coils\"\naddress = 0\ncount = 10");
+        assert_eq!(coils.decode(&[0x01, 0x02, 0xCD, 0x01]), Ok(vec![1, 0, 1, 1, 0, 0, 1, 1, 1, 0]));
+        assert!(coils.decode(&[0x01, 0x01, 0xCD]).is_err());
+        // Ответ на запись должен повторять адрес и значение
+        let write = request("slave = 1\nfunction = \"write_single_register\"\naddress = 1\nvalue = 3");
+        assert_eq!(write.decode(&[0x06, 0x00, 0x01, 0x00, 0x03]), Ok(vec![]));
+        assert!(write.decode(&[0x06, 0x00, 0x01, 0x00, 0x04]).is_err());
+        let multiple = request("slave = 1\nfunction = \"write_multiple_registers\"\naddress = 1\nvalues = [10, 258]");
+        assert_eq!(multiple.decode(&[0x10, 0x00, 0x01, 0x00, 0x02]), Ok(vec![]));
+        assert!(multiple.decode(&[0x10, 0x00, 0x01, 0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn counts_replies_exceptions_and_crc_errors() {
+        let list = parse_poll_list("retries = 1\n[[request]]\nslave = 1\nfunction = \"read_holding_registers\"\naddress = 0\ncount = 1");
+        let mut master = ModbusMaster::new(list.unwrap());
+        let now = Instant::now();
+        let written = capture("master-replies", |ctx| {
+            master.on_tick(now, ctx);
+            // Повреждённый CRC засчитывается и запрос повторяется
+            let mut corrupt = sealed(vec![0x01, 0x03, 0x02, 0x00, 0x2A]);
+            corrupt[5] ^= 0xFF;
+            assert!(master.on_frame(&corrupt, ctx));
+            assert_eq!(master.pending.as_ref().map(|pending| pending.attempt), Some(2));
+            // Ответ другого устройства не завершает ожидание
+            assert!(master.on_frame(&sealed(vec![0x02, 0x03, 0x02, 0x00, 0x2A]), ctx));
+            assert!(master.pending.is_some());
+            assert!(master.on_frame(&sealed(vec![0x01, 0x03, 0x02, 0x00, 0x2A]), ctx));
+            assert!(master.pending.is_none());
+            master.list.requests[0].next_due = now;
+            master.on_tick(now, ctx);
+            assert!(master.on_frame(&sealed(vec![0x01, 0x83, 0x02]), ctx));
+            assert!(master.pending.is_none());
+            // Кадр без запроса только выводится
+            assert!(master.on_frame(&sealed(vec![0x01, 0x83, 0x02]), ctx));
+        });
+        let frame = request("slave = 1\nfunction = \"read_holding_registers\"\naddress = 0\ncount = 1").frame();
+        assert_eq!(written, frame.repeat(3));
+        let stats = &master.list().requests[0].stats;
+        assert_eq!((stats.sent, stats.ok, stats.retries, stats.crc_errors, stats.exceptions), (3, 1, 1, 1, 1));
+        assert_eq!((stats.timeouts, stats.invalid, stats.failed), (0, 0, 0));
+    }
+
+    #[test]
+    fn retries_on_timeout_and_gives_up() {
+        let toml = "timeout_ms = 100\nretries = 1\n\
+                    [[request]]\nslave = 1\nfunction = \"write_single_register\"\naddress = 1\nvalue = 3\nonce = true";
+        let mut master = ModbusMaster::new(parse_poll_list(toml).unwrap());
+        let start = Instant::now();
+        let written = capture("master-timeout", |ctx| {
+            master.on_tick(start, ctx);
+            master.on_tick(start + Duration::from_millis(99), ctx);
+            assert_eq!(master.pending.as_ref().map(|pending| pending.attempt), Some(1));
+            master.on_tick(start + Duration::from_millis(100), ctx);
+            assert_eq!(master.pending.as_ref().map(|pending| pending.attempt), Some(2));
+            // Неверное эхо тоже считается неудачной попыткой
+            assert!(master.on_frame(&sealed(vec![0x01, 0x06, 0x00, 0x01, 0x00, 0x04]), ctx));
+            assert!(master.pending.is_none());
+            // Запрос `once` после завершения больше не отправляется
+            master.on_tick(start + Duration::from_secs(10), ctx);
+            assert!(master.pending.is_none());
+        });
+        assert_eq!(written.len(), 16);
+        let stats = &master.list().requests[0].stats;
+        assert_eq!((stats.sent, stats.timeouts, stats.retries), (2, 1, 1));
+        assert_eq!((stats.invalid, stats.failed, stats.ok), (1, 1, 0));
+    }
+}
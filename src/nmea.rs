@@ -0,0 +1,240 @@
+use std::io;
+use std::time::{Duration, Instant};
+use crate::calendar::civil_from_days;
+use crate::checksum::Checksum;
+use crate::device::{Device, DeviceContext};
+use crate::framer::FramingSpec;
+use crate::track::{Fix, Playback};
+
+/// Спутники синтетического созвездия: номер, угол места и азимут в начале воспроизведения.
+const SATELLITES: [(u8, f64, f64); 10] = [
+    (2, 68.0, 40.0),
+    (5, 45.0, 110.0),
+    (7, 30.0, 200.0),
+    (9, 55.0, 300.0),
+    (13, 20.0, 250.0),
+    (16, 75.0, 160.0),
+    (20, 12.0, 20.0),
+    (26, 38.0, 75.0),
+    (29, 8.0, 330.0),
+    (31, 25.0, 140.0),
+];
+
+/// Сколько спутников из созвездия участвуют в решении (с наибольшим углом места).
+pub(crate) const USED_SATELLITES: usize = 8;
+
+pub(crate) const HDOP: f64 = 0.9;
+pub(crate) const VDOP: f64 = 1.3;
+pub(crate) const PDOP: f64 = 1.6;
+
+/// Высота геоида над эллипсоидом, м.
+pub(crate) const GEOID_SEPARATION: f64 = 14.0;
+
+const KNOTS_PER_MPS: f64 = 3600.0 / 1852.0;
+
+/// Наибольшая частота решений, Гц.
+pub(crate) const MAX_FIX_RATE: f64 = 50.0;
+
+/// Период решений для частоты `rate` в герцах.
+pub(crate) fn fix_interval(rate: f64) -> io::Result<Duration> {
+    if rate.is_nan() || rate <= 0.0 || rate > MAX_FIX_RATE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Fix rate must be between 0 and {} Hz, got {}", MAX_FIX_RATE, rate),
+        ));
+    }
+    Ok(Duration::from_secs_f64(1.0 / rate))
+}
+
+/// Приёмник GPS, выдающий GGA, RMC, GSA, GSV и VTG с заданной частотой по треку.
+///
+/// Предложения отправляются с паузами, соответствующими скорости порта; если все предложения
+/// не помещаются в период выдачи, сначала отбрасываются GSV, затем GSA и VTG.
+pub struct NmeaSource {
+    playback: Playback,
+    interval: Duration,
+    baud_rate: u32,
+    next_fix: Instant,
+    fixes: u64,
+    /// Отброшенные из-за скорости порта предложения, о которых уже выведено предупреждение.
+    dropped: Vec<&'static str>,
+}
+
+impl NmeaSource {
+    /// `rate` — число решений в секунду.
+    pub fn new(playback: Playback, rate: f64, baud_rate: u32) -> io::Result<Self> {
+        Ok(NmeaSource {
+            playback,
+            interval: fix_interval(rate)?,
+            baud_rate,
+            next_fix: Instant::now(),
+            fixes: 0,
+            dropped: Vec::new(),
+        })
+    }
+
+    /// Предложения одного решения в порядке выдачи вместе с их типами.
+    fn sentences(&self, fix: &Fix, elapsed: f64) -> Vec<(&'static str, String)> {
+        let (date, time) = format_utc(fix.time.unwrap_or_default());
+        let (lat, ns) = format_coordinate(fix.lat, 2, 'N', 'S');
+        let (lon, ew) = format_coordinate(fix.lon, 3, 'E', 'W');
+        let knots = fix.speed * KNOTS_PER_MPS;
+        let kmh = fix.speed * 3.6;
+        let satellites = constellation(elapsed);
+        let mut used: Vec<u8> = satellites.iter().take(USED_SATELLITES).map(|sat| sat.0).collect();
+        used.sort_unstable();
+
+        let mut sentences = vec![
+            ("RMC", format!("GPRMC,{},A,{},{},{},{},{:.2},{:.1},{},,,A", time, lat, ns, lon, ew, knots, fix.course, date)),
+            ("VTG", format!("GPVTG,{:.1},T,,M,{:.2},N,{:.2},K,A", fix.course, knots, kmh)),
+            (
+                "GGA",
+                format!(
+                    "GPGGA,{},{},{},{},{},1,{:02},{:.1},{:.1},M,{:.1},M,,",
+                    time,
+                    lat,
+                    ns,
+                    lon,
+                    ew,
+                    used.len(),
+                    HDOP,
+                    fix.alt,
+                    GEOID_SEPARATION
+                ),
+            ),
+        ];
+        let mut ids: Vec<String> = used.iter().map(|prn| format!("{:02}", prn)).collect();
+        ids.resize(12, String::new());
+        sentences.push(("GSA", format!("GPGSA,A,3,{},{:.1},{:.1},{:.1}", ids.join(","), PDOP, HDOP, VDOP)));
+        let total = satellites.len().div_ceil(4);
+        for (index, group) in satellites.chunks(4).enumerate() {
+            let mut sentence = format!("GPGSV,{},{},{:02}", total, index + 1, satellites.len());
+            for (prn, elevation, azimuth, snr) in group {
+                sentence.push_str(&format!(",{:02},{:02},{:03},{:02}", prn, elevation, azimuth, snr));
+            }
+            sentences.push(("GSV", sentence));
+        }
+        sentences
+            .into_iter()
+            .map(|(kind, body)| {
+                let sum = Checksum::Xor.compute(body.as_bytes())[0];
+                (kind, format!("${}*{:02X}\r\n", body, sum))
+            })
+            .collect()
+    }
+
+    /// Отбрасывает предложения, не помещающиеся в период выдачи при текущей скорости порта.
+    fn fit_bandwidth(&mut self, mut sentences: Vec<(&'static str, String)>, ctx: &DeviceContext) -> Vec<String> {
+        // 8N1: десять битов на байт
+        let budget = (self.baud_rate as f64 / 10.0 * self.interval.as_secs_f64()) as usize;
+        let size = |sentences: &[(&str, String)]| sentences.iter().map(|(_, text)| text.len()).sum::<usize>();
+        for kind in ["GSV", "GSA", "VTG"] {
+            if size(&sentences) <= budget {
+                break;
+            }
+            sentences.retain(|(other, _)| *other != kind);
+            if !self.dropped.contains(&kind) {
+                self.dropped.push(kind);
+                ctx.log(&format!(
+                    "[NMEA] {} baud carries {} bytes per fix at {:.1} Hz, dropping {}",
+                    self.baud_rate,
+                    budget,
+                    1.0 / self.interval.as_secs_f64(),
+                    kind
+                ));
+            }
+        }
+        sentences.into_iter().map(|(_, text)| text).collect()
+    }
+}
+
+impl Device for NmeaSource {
+    fn name(&self) -> &'static str {
+        "NMEA 0183 GPS receiver"
+    }
+
+    fn framing(&self, _baud_rate: u32) -> FramingSpec {
+        // Команды приёмнику (например, `$PMTK...`) приходят строками
+        FramingSpec::default()
+    }
+
+    fn on_frame(&mut self, _frame: &[u8], _ctx: &DeviceContext) -> bool {
+        false
+    }
+
+    fn on_tick(&mut self, now: Instant, ctx: &DeviceContext) {
+        if now < self.next_fix {
+            return;
+        }
+        // После задержки потока выдача продолжается с текущего момента, без пачки решений
+        self.next_fix = (self.next_fix + self.interval).max(now);
+        let (fix, elapsed) = self.playback.fix(now);
+        let sentences = self.sentences(&fix, elapsed);
+        let mut offset = 0usize;
+        for sentence in self.fit_bandwidth(sentences, ctx) {
+            // Следующее предложение уходит, когда предыдущее было бы передано по линии
+            let delay = Duration::from_secs_f64(offset as f64 * 10.0 / self.baud_rate.max(1) as f64);
+            offset += sentence.len();
+            ctx.scheduler.send_after(delay, sentence.into_bytes(), "NMEA");
+        }
+        self.fixes += 1;
+    }
+
+    fn finish(&mut self, ctx: &DeviceContext) {
+        ctx.log(&format!("[NMEA] Sent {} fix(es)", self.fixes));
+    }
+}
+
+/// Спутники по убыванию угла места: номер, угол места, азимут и отношение сигнал/шум.
+/// Азимут медленно меняется, как у настоящих спутников.
+pub(crate) fn constellation(elapsed: f64) -> Vec<(u8, u32, u32, u32)> {
+    let mut satellites: Vec<(u8, u32, u32, u32)> = SATELLITES
+        .iter()
+        .map(|&(prn, elevation, azimuth)| {
+            let azimuth = (azimuth + elapsed / 240.0).rem_euclid(360.0);
+            (prn, elevation as u32, azimuth as u32, (20.0 + elevation / 3.0) as u32)
+        })
+        .collect();
+    satellites.sort_by_key(|sat| std::cmp::Reverse(sat.1));
+    satellites
+}
+
+/// Координата в формате NMEA (`ddmm.mmmmm` / `dddmm.mmmmm`) и полушарие.
+fn format_coordinate(value: f64, degree_digits: usize, positive: char, negative: char) -> (String, char) {
+    // Округление до 1e-5 минуты до разделения, чтобы не получить 60 минут
+    let total = (value.abs() * 60.0 * 100_000.0).round() as u64;
+    let degrees = total / 6_000_000;
+    let minutes = (total % 6_000_000) as f64 / 100_000.0;
+    let hemisphere = if value < 0.0 { negative } else { positive };
+    (format!("{:0width$}{:08.5}", degrees, minutes, width = degree_digits), hemisphere)
+}
+
+/// Дата `ddmmyy` и время `hhmmss.ss` UTC.
+fn format_utc(utc: f64) -> (String, String) {
+    let centis = (utc * 100.0).round() as i64;
+    let (days, day_centis) = (centis.div_euclid(8_640_000), centis.rem_euclid(8_640_000));
+    let (year, month, day) = civil_from_days(days);
+    let seconds = day_centis / 100;
+    let date = format!("{:02}{:02}{:02}", day, month, year.rem_euclid(100));
+    let time = format!(
+        "{:02}{:02}{:02}.{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        day_centis % 100
+    );
+    (date, time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fix_interval_rejects_invalid_rates() {
+        assert_eq!(fix_interval(4.0).unwrap(), Duration::from_millis(250));
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY, MAX_FIX_RATE + 1.0] {
+            assert_eq!(fix_interval(rate).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
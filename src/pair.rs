@@ -0,0 +1,86 @@
+use std::fs::File;
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use crate::hex::escape_bytes;
+use crate::intercept::{start_intercept_console, Interceptor};
+use crate::logger::{log_data, open_log_file};
+use crate::port::{VirtualPort, VirtualPortBuilder};
+use crate::pty::write_all;
+
+/// Два виртуальных порта, соединённые нуль-модемным кабелем: всё, что программа пишет в один
+/// порт, читается из другого. Таблица команд, скрипт, устройство и heartbeat каждого порта
+/// работают как обычно; данные, на которые они ответили, дальше не передаются.
+pub struct PortPair {
+    a: VirtualPort,
+    b: VirtualPort,
+    shuttles: Vec<JoinHandle<()>>,
+}
+
+impl PortPair {
+    /// Создаёт оба порта и запускает пересылку. Обмен в каждом направлении записывается
+    /// в `log_file` с метками `A->B` и `B->A`. С `intercept` каждый кадр задерживается
+    /// до решения в консоли (см. `Interceptor`).
+    pub fn start(a: VirtualPortBuilder, b: VirtualPortBuilder, log_file: Option<&str>, intercept: bool) -> io::Result<PortPair> {
+        let logger = match log_file {
+            Some(path) => {
+                println!("[Info] Logging both directions to file: {}", path);
+                Some(open_log_file(path)?)
+            }
+            None => None,
+        };
+        let (to_b, from_a) = mpsc::channel();
+        let (to_a, from_b) = mpsc::channel();
+        let a = a.forward(to_b).build()?;
+        let b = b.forward(to_a).build()?;
+        let interceptor = intercept.then(Interceptor::new);
+        let (from_a, from_b) = match &interceptor {
+            Some(interceptor) => (interceptor.route("A->B", from_a), interceptor.route("B->A", from_b)),
+            None => (from_a, from_b),
+        };
+        let mut shuttles = vec![
+            start_shuttle("A->B", from_a, Arc::clone(b.master()), logger.clone()),
+            start_shuttle("B->A", from_b, Arc::clone(a.master()), logger),
+        ];
+        shuttles.extend(interceptor.map(start_intercept_console));
+        Ok(PortPair { a, b, shuttles })
+    }
+
+    pub fn a(&self) -> &VirtualPort {
+        &self.a
+    }
+
+    pub fn b(&self) -> &VirtualPort {
+        &self.b
+    }
+
+    /// Блокирует до остановки обоих портов.
+    pub fn wait(self) {
+        let PortPair { a, b, shuttles } = self;
+        a.wait();
+        b.wait();
+        // Пересылка и консоль перехвата заканчиваются, когда потоки чтения закрывают каналы
+        for handle in shuttles {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Пересылает данные, принятые одним портом, в master-устройство другого.
+fn start_shuttle(
+    direction: &'static str,
+    rx: Receiver<Vec<u8>>,
+    master: Arc<File>,
+    logger: Option<Arc<Mutex<File>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for data in rx {
+            println!("[{}] {}", direction, escape_bytes(&data));
+            log_data(&logger, direction, &data);
+            if let Err(e) = write_all(&master, &data) {
+                eprintln!("[{}] Dropped {} byte(s): {}", direction, data.len(), e);
+            }
+        }
+    })
+}
@@ -0,0 +1,401 @@
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd};
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Sender,
+    Arc, Mutex,
+};
+use std::thread::JoinHandle;
+use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+
+use crate::cleanup::Cleanup;
+use crate::commands::CommandTable;
+use crate::device::Device;
+use crate::emulator::Emulator;
+use crate::framer::FramingSpec;
+use crate::heartbeat::start_heartbeat;
+use crate::hex::escape_bytes;
+use crate::io_handler::{start_reader, start_writer};
+use crate::scheduler::start_scheduler;
+use crate::script::Script;
+use crate::pty::{create_virtual_serial_port, get_slave_name, set_baud_rate, set_nonblocking, set_parity, speed_to_baud};
+
+/// Построитель виртуального порта. Создаётся через `VirtualPort::builder()`.
+pub struct VirtualPortBuilder {
+    link: Option<String>,
+    verbose: bool,
+    enable_echo: bool,
+    init_msg: Option<Vec<u8>>,
+    log_file: Option<String>,
+    heartbeat: u64,
+    hb_msg: Vec<u8>,
+    baud_rate: u32,
+    parity: String,
+    commands: CommandTable,
+    script: Option<String>,
+    framing: Option<FramingSpec>,
+    device: Option<Box<dyn Device>>,
+    console: bool,
+    running: Option<Arc<AtomicBool>>,
+    forward: Option<Sender<Vec<u8>>>,
+}
+
+impl Default for VirtualPortBuilder {
+    fn default() -> Self {
+        VirtualPortBuilder {
+            link: None,
+            verbose: false,
+            enable_echo: false,
+            init_msg: None,
+            log_file: None,
+            heartbeat: 0,
+            hb_msg: b"HEARTBEAT\n".to_vec(),
+            baud_rate: 9600,
+            parity: "none".to_string(),
+            commands: CommandTable::new(),
+            script: None,
+            framing: None,
+            device: None,
+            console: false,
+            running: None,
+            forward: None,
+        }
+    }
+}
+
+impl VirtualPortBuilder {
+    /// Путь символической ссылки на slave-устройство. Без ссылки порт доступен только по `slave_path()`.
+    pub fn link(mut self, path: impl Into<String>) -> Self {
+        self.link = Some(path.into());
+        self
+    }
+
+    /// Подробный вывод в stdout.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Оставить эхо на slave-устройстве включённым (по умолчанию отключается).
+    pub fn enable_echo(mut self, enable: bool) -> Self {
+        self.enable_echo = enable;
+        self
+    }
+
+    /// Сообщение, отправляемое в порт сразу после запуска.
+    pub fn init_msg(mut self, msg: impl Into<Vec<u8>>) -> Self {
+        self.init_msg = Some(msg.into());
+        self
+    }
+
+    /// Файл для логирования обмена.
+    pub fn log_file(mut self, path: impl Into<String>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Интервал heartbeat-сообщений в секундах (0 — отключено) и их текст.
+    pub fn heartbeat(mut self, interval: u64, msg: impl Into<Vec<u8>>) -> Self {
+        self.heartbeat = interval;
+        self.hb_msg = msg.into();
+        self
+    }
+
+    /// Скорость slave-устройства.
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Паритет slave-устройства: `none`, `even` или `odd`.
+    pub fn parity(mut self, parity: impl Into<String>) -> Self {
+        self.parity = parity.into();
+        self
+    }
+
+    /// Таблица команд и ответов.
+    pub fn commands(mut self, commands: CommandTable) -> Self {
+        self.commands = commands;
+        self
+    }
+
+    /// Скрипт Rhai, обрабатывающий принятые строки раньше таблицы команд.
+    pub fn script(mut self, path: impl Into<String>) -> Self {
+        self.script = Some(path.into());
+        self
+    }
+
+    /// Способ выделения кадров из принятых данных. Без него данные делятся на строки по `\n`.
+    pub fn framing(mut self, framing: FramingSpec) -> Self {
+        self.framing = Some(framing);
+        self
+    }
+
+    /// Встроенная модель устройства, отвечающая на кадры раньше таблицы команд. Если `framing`
+    /// не задан, используется способ выделения кадров, который требует устройство.
+    pub fn device(mut self, device: Box<dyn Device>) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Читать консоль (stdin) и пересылать ввод в порт.
+    pub fn console(mut self, enable: bool) -> Self {
+        self.console = enable;
+        self
+    }
+
+    /// Внешний флаг работы, например из обработчика сигналов. Сброс флага останавливает порт;
+    /// сам порт флаг только читает, и его остановка не затрагивает других владельцев флага.
+    pub fn running(mut self, running: Arc<AtomicBool>) -> Self {
+        self.running = Some(running);
+        self
+    }
+
+    /// Передавать принятые данные, на которые нет ответа, в канал вместо вывода в консоль
+    /// (см. `PortPair`).
+    pub fn forward(mut self, forward: Sender<Vec<u8>>) -> Self {
+        self.forward = Some(forward);
+        self
+    }
+
+    /// Создаёт PTY, символическую ссылку и запускает рабочие потоки.
+    pub fn build(self) -> io::Result<VirtualPort> {
+        let baud_rate = speed_to_baud(self.baud_rate).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported baud rate: {}", self.baud_rate))
+        })?;
+        if !matches!(self.parity.as_str(), "none" | "even" | "odd") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid parity: {}", self.parity)));
+        }
+
+        let running = self.running.unwrap_or_else(|| Arc::new(AtomicBool::new(true)));
+
+        // Создание виртуального последовательного порта
+        let pty_result = create_virtual_serial_port(5)?;
+        let (master, slave) = (pty_result.master, pty_result.slave);
+
+        // Установка неблокирующего режима для master и stdin
+        set_nonblocking(master.as_raw_fd())?;
+        if self.console {
+            set_nonblocking(0)?; // stdin
+        }
+
+        // Получение имени slave-устройства и создание символической ссылки
+        let slave_path = get_slave_name(slave.as_raw_fd());
+        println!("[Info] Virtual serial port created: {}", slave_path);
+        let cleanup = self.link.as_ref().map(|link| {
+            // Автоматическая очистка символической ссылки при завершении работы
+            let cleanup = Cleanup::new(link.clone());
+            let _ = remove_file(link);
+            if let Err(e) = symlink(&slave_path, link) {
+                eprintln!("[Error] Failed to create symbolic link: {}", e);
+            } else {
+                println!("[Info] Symbolic link created: {} <-> {}", link, slave_path);
+            }
+            cleanup
+        });
+
+        // Настройка slave-устройства (отключение эха, если требуется)
+        let slave_file = unsafe { File::from_raw_fd(slave.into_raw_fd()) };
+        let mut termios = tcgetattr(&slave_file)?;
+        if !self.enable_echo {
+            termios.local_flags.remove(LocalFlags::ECHO);
+            if self.verbose {
+                println!("[Info] Disabling local echo on slave device.");
+            }
+        } else if self.verbose {
+            println!("[Info] Echo enabled on slave device.");
+        }
+        tcsetattr(&slave_file, SetArg::TCSANOW, &termios)?;
+
+        // Настройка скорости и паритета
+        set_baud_rate(&slave_file, baud_rate)?;
+        set_parity(&slave_file, &self.parity)?;
+        let slave_file = Arc::new(slave_file);
+
+        // Оборачивание master-устройства в Arc для потокобезопасного доступа
+        let master_file = Arc::new(unsafe { File::from_raw_fd(master.into_raw_fd()) });
+
+        // Отправка начального сообщения, если задано
+        if let Some(msg) = &self.init_msg {
+            if self.verbose {
+                println!("[Info] Sending init message: {}", escape_bytes(msg));
+            }
+            master_file.as_ref().write_all(msg)?;
+        }
+
+        // Инициализация логгера, если задан файл для логирования
+        let logger: Option<Arc<Mutex<File>>> = match &self.log_file {
+            Some(path) => {
+                let file = OpenOptions::new().append(true).create(true).open(path)?;
+                println!("[Info] Logging communications to file: {}", path);
+                Some(Arc::new(Mutex::new(file)))
+            }
+            None => None,
+        };
+
+        // Фоновые потоки останавливаются своим флагом при уничтожении порта: общий флаг `running`
+        // может принадлежать и другим портам (пара, шина), и ошибка этого порта не должна их остановить
+        let background_running = Arc::new(AtomicBool::new(true));
+        let mut background = Vec::new();
+
+        // Запуск heartbeat-потока, если задан интервал
+        if self.heartbeat > 0 {
+            background.push(start_heartbeat(
+                background_running.clone(),
+                self.heartbeat,
+                self.hb_msg.clone(),
+                Arc::clone(&master_file),
+                logger.clone(),
+            ));
+        }
+
+        // Планировщик отложенных ответов
+        let (scheduler, scheduler_handle) = start_scheduler(background_running.clone(), Arc::clone(&master_file), logger.clone());
+        background.push(scheduler_handle);
+
+        let framing = self.framing.clone().or_else(|| self.device.as_ref().map(|device| device.framing(self.baud_rate)));
+        if let Some(device) = &self.device {
+            println!("[Info] Emulating {}", device.name());
+        }
+        if let (Some(framing), true) = (&framing, self.verbose) {
+            println!("[Info] Framing received data as {}", framing);
+        }
+
+        // Эмулятор устройства по таблице команд, общий для потоков чтения и записи
+        let emulator = Emulator::new(self.commands, scheduler, logger.clone(), self.verbose);
+        emulator.start();
+        let script = match &self.script {
+            Some(path) => match Script::load(path, emulator.clone(), logger.clone()) {
+                Ok(script) => Some(script),
+                Err(e) => {
+                    // Останавливаем уже запущенные фоновые потоки этого порта
+                    background_running.store(false, Ordering::SeqCst);
+                    for handle in background {
+                        let _ = handle.join();
+                    }
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+
+        // Потоки чтения и записи работают, пока не сброшен внешний флаг или флаг этого порта.
+        // Внешний флаг порт только читает: остановка порта не должна останавливать других
+        let port_running = Arc::new(AtomicBool::new(true));
+        let active = {
+            let (running, port_running) = (running.clone(), port_running.clone());
+            move || running.load(Ordering::SeqCst) && port_running.load(Ordering::SeqCst)
+        };
+
+        // Запуск потоков для чтения и записи
+        let mut threads = vec![start_reader(
+            active.clone(),
+            Arc::clone(&master_file),
+            logger.clone(),
+            emulator.clone(),
+            script,
+            self.device,
+            framing,
+            self.forward,
+        )];
+        if self.console {
+            threads.push(start_writer(
+                active,
+                Arc::clone(&master_file),
+                Arc::clone(&slave_file),
+                logger.clone(),
+                emulator.clone(),
+            ));
+        }
+
+        Ok(VirtualPort {
+            slave_path,
+            link: self.link,
+            running,
+            port_running,
+            threads,
+            background_running,
+            background,
+            master: master_file,
+            slave: slave_file,
+            _cleanup: cleanup,
+        })
+    }
+}
+
+/// Запущенный виртуальный порт. При уничтожении останавливает потоки и удаляет символическую ссылку.
+pub struct VirtualPort {
+    slave_path: String,
+    link: Option<String>,
+    /// Внешний флаг работы; порт его только читает.
+    running: Arc<AtomicBool>,
+    /// Флаг остановки потоков чтения и записи этого порта.
+    port_running: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    background_running: Arc<AtomicBool>,
+    background: Vec<JoinHandle<()>>,
+    master: Arc<File>,
+    slave: Arc<File>,
+    // Объявлено последним, чтобы ссылка удалялась после остановки потоков
+    _cleanup: Option<Cleanup>,
+}
+
+impl VirtualPort {
+    pub fn builder() -> VirtualPortBuilder {
+        VirtualPortBuilder::default()
+    }
+
+    /// Путь к slave-устройству (например, `/dev/pts/3`).
+    pub fn slave_path(&self) -> &str {
+        &self.slave_path
+    }
+
+    /// Путь символической ссылки, если она была запрошена.
+    pub fn link_path(&self) -> Option<&str> {
+        self.link.as_deref()
+    }
+
+    /// Master-сторона PTY.
+    pub fn master(&self) -> &Arc<File> {
+        &self.master
+    }
+
+    /// Slave-сторона PTY, удерживаемая открытой на время работы порта.
+    pub fn slave(&self) -> &Arc<File> {
+        &self.slave
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst) && self.port_running.load(Ordering::SeqCst)
+    }
+
+    /// Блокирует до завершения потоков чтения и записи (например, по флагу из обработчика сигналов).
+    pub fn wait(mut self) {
+        self.join_threads();
+    }
+
+    /// Останавливает рабочие потоки и освобождает порт.
+    pub fn shutdown(self) {
+        drop(self);
+    }
+
+    fn join_threads(&mut self) {
+        for handle in self.threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for VirtualPort {
+    fn drop(&mut self) {
+        self.port_running.store(false, Ordering::SeqCst);
+        self.join_threads();
+        self.background_running.store(false, Ordering::SeqCst);
+        for handle in self.background.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::commands::{
+    legacy_pattern, load_into, parse_legacy_file, CommandEntry, CommandSet, CommandTable, Delay, LegacyItem,
+    ResponseMessage, ResponsePart, StateTimer,
+};
+use crate::checksum::parse_sealed_hex;
+use crate::hex::HexPattern;
+
+/// Профиль устройства в формате TOML.
+///
+/// ```toml
+/// include = ["base.toml"]
+///
+/// [[command]]
+/// hex = "01 03 ?? ?? 00 02 *"
+/// response = { hex = "01 03 04 00 2A 00 2B {crc16_modbus}" }
+///
+/// [[command]]
+/// match = "AT+CSQ"
+/// response = ["+CSQ: 23,99", "OK", { text = "+CREG: 1", delay_ms = 2000 }]
+/// delay_ms = [100, 400]
+/// chunk_size = 4
+/// chunk_gap_ms = 20
+/// line_ending = "crlf"
+/// flags = ["ignore_case"]
+///
+/// [[state]]
+/// name = "connected"
+///
+/// [[state.command]]
+/// match = "AT+CIPCLOSE"
+/// response = "CLOSE OK"
+/// goto = "idle"
+///
+/// [[state.timer]]
+/// after_ms = 30000
+/// response = "CLOSED"
+/// goto = "idle"
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Подключаемые файлы (профили или файлы старого формата) относительно текущего файла.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Начальное состояние; по умолчанию — первое описанное состояние.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_state: Option<String>,
+    /// Общие команды, действующие в любом состоянии.
+    #[serde(default, rename = "command", skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<CommandSpec>,
+    #[serde(default, rename = "state", skip_serializing_if = "Vec::is_empty")]
+    pub states: Vec<StateSpec>,
+}
+
+/// Именованное состояние с собственными командами и таймерами.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateSpec {
+    pub name: String,
+    #[serde(default, rename = "command", skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<CommandSpec>,
+    #[serde(default, rename = "timer", skip_serializing_if = "Vec::is_empty")]
+    pub timers: Vec<TimerSpec>,
+}
+
+/// Таймер, срабатывающий через `after_ms` после входа в состояние, если состояние не сменилось.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimerSpec {
+    pub after_ms: DelaySpec,
+    #[serde(default, deserialize_with = "one_or_many", skip_serializing_if = "Vec::is_empty")]
+    pub response: Vec<ResponseSpec>,
+    #[serde(default, skip_serializing_if = "LineEnding::is_default")]
+    pub line_ending: LineEnding,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goto: Option<String>,
+}
+
+/// Описание команды в профиле.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandSpec {
+    /// Точное совпадение с командой.
+    #[serde(rename = "match", default, skip_serializing_if = "Option::is_none")]
+    pub exact: Option<String>,
+    /// Регулярное выражение, совпадающее с командой целиком.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Шаблон двоичного кадра (`01 03 ?? ?? *`), который ищется в потоке байтов.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hex: Option<String>,
+    /// Сообщения ответа; пустой список означает, что команда распознаётся без ответа.
+    #[serde(deserialize_with = "one_or_many")]
+    pub response: Vec<ResponseSpec>,
+    /// Задержка перед ответом: число или диапазон `[min, max]` для случайной задержки.
+    #[serde(default, skip_serializing_if = "DelaySpec::is_zero")]
+    pub delay_ms: DelaySpec,
+    /// Отправлять ответ фрагментами указанного размера.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub chunk_size: usize,
+    /// Пауза между фрагментами.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub chunk_gap_ms: u64,
+    /// Окончание, добавляемое к каждой текстовой строке ответа.
+    #[serde(default, skip_serializing_if = "LineEnding::is_default")]
+    pub line_ending: LineEnding,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<Flag>,
+    /// Состояние, в которое эмулятор переходит после команды.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goto: Option<String>,
+}
+
+/// Сообщение ответа: строка текста или таблица `{ text = "...", delay_ms = 2000 }` /
+/// `{ hex = "1A" }`. Задержка сообщения отсчитывается от отправки предыдущего.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResponseSpec {
+    Text(String),
+    Message(MessageSpec),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Сырые байты в шестнадцатеричном виде; `{crc16_modbus}` и другие подстановки
+    /// дописывают контрольную сумму предшествующих байтов.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hex: Option<String>,
+    #[serde(default, skip_serializing_if = "DelaySpec::is_zero")]
+    pub delay_ms: DelaySpec,
+}
+
+/// Задержка в миллисекундах: фиксированная или случайная из диапазона.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DelaySpec {
+    Fixed(u64),
+    Range([u64; 2]),
+}
+
+impl Default for DelaySpec {
+    fn default() -> Self {
+        DelaySpec::Fixed(0)
+    }
+}
+
+impl DelaySpec {
+    fn is_zero(&self) -> bool {
+        *self == DelaySpec::Fixed(0)
+    }
+
+    fn to_delay(self) -> Result<Delay, String> {
+        match self {
+            DelaySpec::Fixed(ms) => Ok(Delay::Fixed(Duration::from_millis(ms))),
+            DelaySpec::Range([min, max]) if min <= max => {
+                Ok(Delay::Range(Duration::from_millis(min), Duration::from_millis(max)))
+            }
+            DelaySpec::Range([min, max]) => Err(format!("invalid delay range [{}, {}]", min, max)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Cr,
+    Crlf,
+    None,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Cr => "\r",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::None => "",
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        *self == LineEnding::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    /// Сравнение команды без учёта регистра.
+    IgnoreCase,
+    /// Не выводить распознанную команду в консоль.
+    Quiet,
+}
+
+fn is_zero<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ResponseSpec>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(ResponseSpec),
+        Many(Vec<ResponseSpec>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(item) => vec![item],
+        OneOrMany::Many(items) => items,
+    })
+}
+
+fn invalid(path: &Path, msg: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), msg))
+}
+
+/// Читает и разбирает профиль без подключения вложенных файлов.
+pub fn parse_profile_file(path: &Path) -> io::Result<Profile> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(e.kind(), format!("Cannot open command file '{}': {}", path.display(), e))
+    })?;
+    toml::from_str(&text).map_err(|e| invalid(path, e))
+}
+
+/// Загружает профиль в таблицу команд вместе с подключёнными файлами.
+pub(crate) fn load_profile_into(path: &Path, commands: &mut CommandTable, stack: &mut Vec<PathBuf>) -> io::Result<()> {
+    let profile = parse_profile_file(path)?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for include in &profile.include {
+        load_into(&base.join(include), commands, stack)?;
+    }
+
+    insert_commands(&profile.commands, commands.global_mut()).map_err(|e| invalid(path, e))?;
+
+    if commands.initial_state().is_none() {
+        if let Some(first) = profile.states.first() {
+            commands.set_initial_state(first.name.clone());
+        }
+    }
+    if let Some(initial) = &profile.initial_state {
+        commands.set_initial_state(initial.clone());
+    }
+    for state in &profile.states {
+        let def = commands.state_mut(&state.name);
+        insert_commands(&state.commands, &mut def.commands)
+            .map_err(|e| invalid(path, format!("state '{}': {}", state.name, e)))?;
+        for (index, timer) in state.timers.iter().enumerate() {
+            let timer = build_timer(timer)
+                .map_err(|e| invalid(path, format!("state '{}': timer #{}: {}", state.name, index + 1, e)))?;
+            def.timers.push(timer);
+        }
+    }
+    Ok(())
+}
+
+fn insert_commands(specs: &[CommandSpec], set: &mut CommandSet) -> Result<(), String> {
+    for (index, spec) in specs.iter().enumerate() {
+        let entry = build_entry(spec).map_err(|e| format!("command #{}: {}", index + 1, e))?;
+        let ignore_case = spec.flags.contains(&Flag::IgnoreCase);
+        let result = match (&spec.exact, &spec.regex, &spec.hex) {
+            (Some(command), None, None) if ignore_case => {
+                set.insert_ignore_case(command, entry);
+                Ok(())
+            }
+            (Some(command), None, None) => {
+                set.insert(command.clone(), entry);
+                Ok(())
+            }
+            (None, Some(pattern), None) => set.insert_pattern(pattern, ignore_case, entry),
+            (None, None, Some(pattern)) => {
+                let pattern = HexPattern::parse(pattern).map_err(|e| format!("command #{}: {}", index + 1, e))?;
+                set.insert_hex(pattern, entry);
+                Ok(())
+            }
+            _ => {
+                return Err(format!(
+                    "command #{}: exactly one of 'match', 'regex' or 'hex' is required",
+                    index + 1
+                ))
+            }
+        };
+        result.map_err(|e| format!("command #{}: invalid regular expression: {}", index + 1, e))?;
+    }
+    Ok(())
+}
+
+fn build_timer(spec: &TimerSpec) -> Result<StateTimer, String> {
+    Ok(StateTimer {
+        after: spec.after_ms.to_delay()?,
+        entry: CommandEntry {
+            responses: build_responses(&spec.response, spec.line_ending)?,
+            goto: spec.goto.clone(),
+            ..CommandEntry::default()
+        },
+    })
+}
+
+fn build_responses(specs: &[ResponseSpec], line_ending: LineEnding) -> Result<Vec<ResponseMessage>, String> {
+    let text_part = |text: &str| ResponsePart::Text(format!("{}{}", text, line_ending.as_str()));
+    specs
+        .iter()
+        .map(|response| match response {
+            ResponseSpec::Text(text) => Ok(ResponseMessage { part: text_part(text), delay: Delay::default() }),
+            ResponseSpec::Message(message) => {
+                let part = match (&message.text, &message.hex) {
+                    (Some(text), None) => text_part(text),
+                    (None, Some(hex)) => ResponsePart::Bytes(parse_sealed_hex(hex)?),
+                    _ => return Err("a response message needs exactly one of 'text' or 'hex'".to_string()),
+                };
+                Ok(ResponseMessage { part, delay: message.delay_ms.to_delay()? })
+            }
+        })
+        .collect()
+}
+
+fn build_entry(spec: &CommandSpec) -> Result<CommandEntry, String> {
+    Ok(CommandEntry {
+        responses: build_responses(&spec.response, spec.line_ending)?,
+        delay: spec.delay_ms.to_delay()?,
+        chunk_size: spec.chunk_size,
+        chunk_gap: Duration::from_millis(spec.chunk_gap_ms),
+        quiet: spec.flags.contains(&Flag::Quiet),
+        goto: spec.goto.clone(),
+    })
+}
+
+/// Преобразует файл команд старого формата в профиль TOML. Подключения (`@include`)
+/// переносятся как есть: файлы старого формата по-прежнему принимаются. Профиль загружает
+/// подключения раньше своих команд, поэтому подключение после команд — ошибка: иначе
+/// подключённый файл перестал бы переопределять эти команды.
+pub fn convert_legacy_file(path: &Path) -> io::Result<String> {
+    let mut profile = Profile::default();
+    for item in parse_legacy_file(path)? {
+        match item {
+            LegacyItem::Include(include) if !profile.commands.is_empty() => {
+                return Err(invalid(
+                    path,
+                    format!(
+                        "@include {} follows commands, which a profile cannot express; \
+                         move the @include lines to the top of the file before converting",
+                        include
+                    ),
+                ))
+            }
+            LegacyItem::Include(include) => profile.include.push(include),
+            LegacyItem::Command { command, response, .. } => {
+                let (exact, regex) = match legacy_pattern(&command) {
+                    Some(pattern) => (None, Some(pattern.to_string())),
+                    None => (Some(command), None),
+                };
+                profile.commands.push(CommandSpec {
+                    exact,
+                    regex,
+                    response: vec![ResponseSpec::Text(response)],
+                    ..CommandSpec::default()
+                });
+            }
+        }
+    }
+    let body = toml::to_string(&profile).map_err(|e| invalid(path, e))?;
+    Ok(format!("# Converted from {}\n\n{}", path.display(), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("virtualport-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn converts_legacy_commands_and_includes() {
+        let path = write_temp("legacy.txt", "@include base.txt\nAT\nOK\n~AT\\+CSQ=(\\d+)\nCSQ $1\n");
+        let profile: Profile = toml::from_str(&convert_legacy_file(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(profile.include, vec!["base.txt"]);
+        assert_eq!(profile.commands[0].exact.as_deref(), Some("AT"));
+        assert_eq!(profile.commands[1].regex.as_deref(), Some("AT\\+CSQ=(\\d+)"));
+    }
+
+    #[test]
+    fn refuses_include_after_commands() {
+        let path = write_temp("late-include.txt", "AT\nOK\n@include base.txt\n");
+        let error = convert_legacy_file(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.to_string().contains("@include base.txt follows commands"), "{}", error);
+    }
+}
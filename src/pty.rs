@@ -8,41 +8,50 @@ use nix::pty::{OpenptyResult};
 #[cfg(unix)]
 use nix::sys::termios::{tcgetattr, tcsetattr, cfsetispeed, cfsetospeed, BaudRate, ControlFlags, SetArg};
 #[cfg(unix)]
+use std::io;
+#[cfg(unix)]
 use std::os::unix::io::RawFd;
 #[cfg(unix)]
 use std::thread;
 #[cfg(unix)]
-use std::time::Duration;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::io::{ErrorKind, Write};
+
+#[cfg(unix)]
+/// Сколько ждать, пока программа на другой стороне освободит буфер PTY, прежде чем
+/// отбросить данные.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[cfg(unix)]
 /// Создаёт виртуальный последовательный порт с указанным количеством попыток.
-pub fn create_virtual_serial_port(retries: usize) -> OpenptyResult {
+pub fn create_virtual_serial_port(retries: usize) -> io::Result<OpenptyResult> {
     #[cfg(not(target_os = "android"))]
     {
         for attempt in 0..retries {
             match openpty(None, None) {
-                Ok(pty) => return pty,
+                Ok(pty) => return Ok(pty),
                 Err(e) => {
                     eprintln!("[Error] Failed to create PTY (attempt {}/{}): {}", attempt + 1, retries, e);
                     thread::sleep(Duration::from_millis(500));
                 }
             }
         }
-        panic!("[Error] Unable to create a virtual serial port after {} attempts", retries);
+        Err(io::Error::other(format!("Unable to create a virtual serial port after {} attempts", retries)))
     }
 
     #[cfg(target_os = "android")]
     {
         for attempt in 0..retries {
             match openpty_android() {
-                Ok(pty) => return pty,
+                Ok(pty) => return Ok(pty),
                 Err(e) => {
                     eprintln!("[Error] Failed to create PTY on Android (attempt {}/{}): {}", attempt + 1, retries, e);
                     thread::sleep(Duration::from_millis(500));
                 }
             }
         }
-        panic!("[Error] Unable to create a virtual serial port after {} attempts", retries);
+        Err(io::Error::other(format!("Unable to create a virtual serial port after {} attempts", retries)))
     }
 }
 
@@ -68,17 +77,18 @@ fn openpty_android() -> nix::Result<OpenptyResult> {
 
 #[cfg(unix)]
 /// Устанавливает заданную скорость (baud rate) для терминала.
-pub fn set_baud_rate(file: &File, baud: BaudRate) {
-    let mut termios = tcgetattr(file).expect("Failed to get terminal attributes");
-    cfsetispeed(&mut termios, baud).expect("Failed to set input speed");
-    cfsetospeed(&mut termios, baud).expect("Failed to set output speed");
-    tcsetattr(file, SetArg::TCSANOW, &termios).expect("Failed to set terminal attributes");
+pub fn set_baud_rate(file: &File, baud: BaudRate) -> io::Result<()> {
+    let mut termios = tcgetattr(file)?;
+    cfsetispeed(&mut termios, baud)?;
+    cfsetospeed(&mut termios, baud)?;
+    tcsetattr(file, SetArg::TCSANOW, &termios)?;
+    Ok(())
 }
 
 #[cfg(unix)]
 /// Устанавливает заданный паритет для терминала.
-pub fn set_parity(file: &File, parity: &str) {
-    let mut termios = tcgetattr(file).expect("Failed to get terminal attributes");
+pub fn set_parity(file: &File, parity: &str) -> io::Result<()> {
+    let mut termios = tcgetattr(file)?;
     match parity {
         "none" => termios.control_flags &= !ControlFlags::PARENB,
         "even" => {
@@ -86,22 +96,40 @@ pub fn set_parity(file: &File, parity: &str) {
             termios.control_flags &= !ControlFlags::PARODD;
         }
         "odd" => termios.control_flags |= ControlFlags::PARENB | ControlFlags::PARODD,
-        _ => {
-            println!("[Error] Invalid parity setting: {}", parity);
-            return;
-        }
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid parity: {}", parity))),
     }
-    tcsetattr(file, SetArg::TCSANOW, &termios).expect("Failed to set terminal attributes");
+    tcsetattr(file, SetArg::TCSANOW, &termios)
+        .map_err(|e| io::Error::new(io::Error::from(e).kind(), format!("Cannot set parity {}: {}", parity, e)))?;
     println!("[Info] Parity set to {}", parity);
+    Ok(())
 }
 
 #[cfg(unix)]
 /// Переводит файловый дескриптор в неблокирующий режим.
-pub fn set_nonblocking(fd: RawFd) {
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
     use nix::fcntl::{fcntl, F_GETFL, F_SETFL, OFlag};
-    let flags = fcntl(fd, F_GETFL).expect("fcntl F_GETFL failed");
+    let flags = fcntl(fd, F_GETFL)?;
     let new_flags = OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK;
-    fcntl(fd, F_SETFL(new_flags)).expect("fcntl F_SETFL O_NONBLOCK failed");
+    fcntl(fd, F_SETFL(new_flags))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+/// Записывает данные в неблокирующее master-устройство, дожидаясь места в буфере.
+pub(crate) fn write_all(master: &File, data: &[u8]) -> io::Result<()> {
+    let started = Instant::now();
+    let mut written = 0;
+    while written < data.len() {
+        match (&*master).write(&data[written..]) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock && started.elapsed() < WRITE_TIMEOUT => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
 }
 
 #[cfg(unix)]
@@ -144,3 +172,35 @@ pub fn speed_to_baud(speed: u32) -> Option<BaudRate> {
         _ => return None,
     })
 }
+
+/// Скорости, поддерживаемые `speed_to_baud`.
+pub const SUPPORTED_SPEEDS: [u32; 18] = [
+    50, 75, 110, 134, 150, 200, 300, 600, 1200, 1800, 2400, 4800, 9600, 19200, 38400, 57600, 115200,
+    230400,
+];
+
+#[cfg(unix)]
+/// Возвращает текущее состояние termios терминала в читаемом виде (скорость и паритет).
+pub fn describe_termios(file: &File) -> String {
+    let termios = match tcgetattr(file) {
+        Ok(termios) => termios,
+        Err(e) => return format!("unavailable ({})", e),
+    };
+    let parity = if !termios.control_flags.contains(ControlFlags::PARENB) {
+        "none"
+    } else if termios.control_flags.contains(ControlFlags::PARODD) {
+        "odd"
+    } else {
+        "even"
+    };
+    // cfgetospeed в nix возвращает разные типы на Linux и BSD, поэтому сравниваем значения speed_t
+    let raw: libc::termios = termios.into();
+    let current = unsafe { libc::cfgetospeed(&raw) };
+    let speed = SUPPORTED_SPEEDS
+        .iter()
+        .copied()
+        .find(|&s| speed_to_baud(s).map(|b| b as libc::speed_t) == Some(current))
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    format!("baud={} parity={}", speed, parity)
+}
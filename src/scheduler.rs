@@ -0,0 +1,126 @@
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    Arc, Mutex,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::logger::log_data;
+use crate::pty::write_all;
+
+/// Максимальное время ожидания, после которого поток проверяет флаг работы.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Отложенное действие: запись в master-устройство или вызов функции.
+enum Action {
+    Write { data: Vec<u8>, tag: &'static str },
+    Call(Box<dyn FnOnce() + Send>),
+}
+
+struct Job {
+    due: Instant,
+    seq: u64,
+    action: Action,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    // Обратный порядок: BinaryHeap отдаёт первой самую раннюю запись
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.due.cmp(&self.due).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Очередь отложенных записей. Клонируется и передаётся в потоки, формирующие ответы,
+/// чтобы задержки не блокировали приём данных.
+#[derive(Clone)]
+pub struct Scheduler {
+    tx: Sender<Job>,
+    seq: Arc<AtomicU64>,
+}
+
+impl Scheduler {
+    /// Ставит запись в очередь; данные будут записаны через `delay`. Записи с одинаковым
+    /// временем отправляются в порядке постановки.
+    pub fn send_after(&self, delay: Duration, data: Vec<u8>, tag: &'static str) {
+        self.push(delay, Action::Write { data, tag });
+    }
+
+    /// Ставит запись в очередь для немедленной отправки.
+    pub fn send_now(&self, data: Vec<u8>, tag: &'static str) {
+        self.send_after(Duration::ZERO, data, tag);
+    }
+
+    /// Вызывает функцию в потоке планировщика через `delay` (например, для таймеров состояний).
+    pub fn call_after(&self, delay: Duration, f: impl FnOnce() + Send + 'static) {
+        self.push(delay, Action::Call(Box::new(f)));
+    }
+
+    fn push(&self, delay: Duration, action: Action) {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        let _ = self.tx.send(Job { due: Instant::now() + delay, seq, action });
+    }
+}
+
+/// Запускает поток, выполняющий отложенные записи в master-устройство.
+pub fn start_scheduler(
+    running: Arc<AtomicBool>,
+    master: Arc<File>,
+    logger: Option<Arc<Mutex<File>>>,
+) -> (Scheduler, thread::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || run(running, master, logger, rx));
+    (Scheduler { tx, seq: Arc::new(AtomicU64::new(0)) }, handle)
+}
+
+fn run(running: Arc<AtomicBool>, master: Arc<File>, logger: Option<Arc<Mutex<File>>>, rx: Receiver<Job>) {
+    let mut queue: BinaryHeap<Job> = BinaryHeap::new();
+    while running.load(Ordering::SeqCst) {
+        let timeout = queue
+            .peek()
+            .map(|job| job.due.saturating_duration_since(Instant::now()).min(IDLE_TIMEOUT))
+            .unwrap_or(IDLE_TIMEOUT);
+        match rx.recv_timeout(timeout) {
+            Ok(job) => queue.push(job),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) if queue.is_empty() => break,
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(timeout),
+        }
+
+        let now = Instant::now();
+        while queue.peek().is_some_and(|job| job.due <= now) {
+            let (data, tag) = match queue.pop().unwrap().action {
+                Action::Write { data, tag } => (data, tag),
+                Action::Call(f) => {
+                    f();
+                    continue;
+                }
+            };
+            // Неблокирующий master: ждём, пока программа освободит буфер PTY
+            if let Err(e) = write_all(&master, &data) {
+                eprintln!("[Scheduler] Dropped {} byte(s) for the port: {}", data.len(), e);
+                continue;
+            }
+            let _ = master.as_ref().flush();
+            log_data(&logger, tag, &data);
+        }
+    }
+    println!("[Scheduler] Thread exiting.");
+}
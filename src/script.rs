@@ -0,0 +1,186 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use rhai::{Blob, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use crate::checksum::Checksum;
+use crate::emulator::Emulator;
+use crate::hex::parse_hex;
+use crate::logger::log_message;
+
+/// Ограничение числа операций за один вызов, чтобы зациклившийся скрипт не останавливал чтение.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Контекст, передаваемый в функции скрипта (`ctx` в Rhai).
+#[derive(Clone)]
+pub struct ScriptContext {
+    emulator: Emulator,
+    store: Arc<Mutex<Map>>,
+    logger: Option<Arc<Mutex<File>>>,
+}
+
+impl ScriptContext {
+    fn send(&mut self, data: Vec<u8>) {
+        self.emulator.scheduler().send_now(data, "Script");
+    }
+
+    fn schedule(&mut self, delay_ms: i64, data: Vec<u8>) {
+        let delay = Duration::from_millis(delay_ms.max(0) as u64);
+        self.emulator.scheduler().send_after(delay, data, "Script");
+    }
+
+    fn send_hex(&mut self, hex: &str) -> Result<(), Box<EvalAltResult>> {
+        let data = parse_hex(hex)?;
+        self.send(data);
+        Ok(())
+    }
+
+    fn get(&mut self, key: &str) -> Dynamic {
+        self.store.lock().unwrap().get(key).cloned().unwrap_or(Dynamic::UNIT)
+    }
+
+    fn set(&mut self, key: &str, value: Dynamic) {
+        self.store.lock().unwrap().insert(key.into(), value);
+    }
+
+    fn log(&mut self, msg: &str) {
+        let msg = format!("[Script] {}", msg);
+        println!("{}", msg);
+        log_message(&self.logger, &msg);
+    }
+
+    fn state(&mut self) -> String {
+        self.emulator.current_state().unwrap_or_default()
+    }
+
+    fn goto(&mut self, name: &str) -> Result<(), Box<EvalAltResult>> {
+        Ok(self.emulator.set_state(name, "script")?)
+    }
+}
+
+struct Loaded {
+    ast: AST,
+    scope: Scope<'static>,
+    modified: Option<SystemTime>,
+}
+
+/// Скрипт Rhai, вычисляющий ответы на принятые строки. Перезагружается при изменении файла.
+///
+/// Скрипт может определить функции `on_load(ctx)` и `on_line(ctx, line)`. Если `on_line`
+/// возвращает строку, она отправляется в порт с переводом строки; `true` означает, что строка
+/// обработана (ответ отправлен через `ctx`); иначе строка передаётся таблице команд.
+pub struct Script {
+    path: PathBuf,
+    engine: Engine,
+    context: ScriptContext,
+    loaded: Mutex<Loaded>,
+}
+
+impl Script {
+    /// Компилирует скрипт и выполняет его верхнеуровневый код и `on_load`.
+    pub fn load(path: impl AsRef<Path>, emulator: Emulator, logger: Option<Arc<Mutex<File>>>) -> io::Result<Script> {
+        let path = path.as_ref().to_path_buf();
+        let context = ScriptContext { emulator, store: Arc::new(Mutex::new(Map::new())), logger };
+        let engine = create_engine();
+        let loaded = compile(&engine, &path, &context)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+        println!("[Script] Loaded {}", path.display());
+        Ok(Script { path, engine, context, loaded: Mutex::new(loaded) })
+    }
+
+    /// Передаёт принятую строку в `on_line`: строкой, если это UTF-8, иначе массивом байтов (blob).
+    /// Возвращает `true`, если скрипт её обработал.
+    pub fn on_line(&self, line: &[u8]) -> bool {
+        let mut loaded = self.loaded.lock().unwrap();
+        self.reload_if_changed(&mut loaded);
+        if !has_function(&loaded.ast, "on_line") {
+            return false;
+        }
+
+        let Loaded { ast, scope, .. } = &mut *loaded;
+        let options = CallFnOptions::new().eval_ast(false);
+        let line = match std::str::from_utf8(line) {
+            Ok(text) => Dynamic::from(text.to_string()),
+            Err(_) => Dynamic::from_blob(line.to_vec()),
+        };
+        let args = (self.context.clone(), line);
+        match self.engine.call_fn_with_options::<Dynamic>(options, scope, ast, "on_line", args) {
+            Ok(result) if result.is_string() => {
+                let mut data = result.into_string().unwrap_or_default().into_bytes();
+                data.push(b'\n');
+                self.context.emulator.scheduler().send_now(data, "Script");
+                true
+            }
+            Ok(result) => result.as_bool().unwrap_or(false),
+            Err(e) => {
+                eprintln!("[Script] Error in on_line: {}", e);
+                false
+            }
+        }
+    }
+
+    fn reload_if_changed(&self, loaded: &mut Loaded) {
+        let modified = modified_time(&self.path);
+        if modified == loaded.modified {
+            return;
+        }
+        match compile(&self.engine, &self.path, &self.context) {
+            Ok(reloaded) => {
+                *loaded = reloaded;
+                println!("[Script] Reloaded {}", self.path.display());
+            }
+            Err(e) => {
+                // Прежняя версия остаётся рабочей; ошибка выводится один раз на изменение файла
+                loaded.modified = modified;
+                eprintln!("[Script] Reload of {} failed, keeping the previous version: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+fn create_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| println!("[Script] {}", text));
+    engine
+        .register_type_with_name::<ScriptContext>("Context")
+        .register_fn("send", |ctx: &mut ScriptContext, text: &str| ctx.send(text.as_bytes().to_vec()))
+        .register_fn("send", |ctx: &mut ScriptContext, data: Blob| ctx.send(data))
+        .register_fn("send_hex", ScriptContext::send_hex)
+        .register_fn("schedule", |ctx: &mut ScriptContext, delay_ms: i64, text: &str| {
+            ctx.schedule(delay_ms, text.as_bytes().to_vec())
+        })
+        .register_fn("schedule", |ctx: &mut ScriptContext, delay_ms: i64, data: Blob| ctx.schedule(delay_ms, data))
+        .register_fn("get", ScriptContext::get)
+        .register_fn("set", ScriptContext::set)
+        .register_fn("log", ScriptContext::log)
+        .register_fn("goto", ScriptContext::goto)
+        .register_get("state", ScriptContext::state);
+    engine.register_fn("checksum", |name: &str, data: Blob| -> Result<Blob, Box<EvalAltResult>> {
+        let checksum: Checksum = name.parse()?;
+        Ok(checksum.compute(&data))
+    });
+    engine
+}
+
+fn compile(engine: &Engine, path: &Path, context: &ScriptContext) -> Result<Loaded, Box<EvalAltResult>> {
+    // Время изменения берётся до чтения, чтобы не пропустить запись во время компиляции
+    let modified = modified_time(path);
+    let ast = engine.compile_file(path.to_path_buf())?;
+    let mut scope = Scope::new();
+    engine.run_ast_with_scope(&mut scope, &ast)?;
+    if has_function(&ast, "on_load") {
+        let options = CallFnOptions::new().eval_ast(false);
+        let _ = engine.call_fn_with_options::<Dynamic>(options, &mut scope, &ast, "on_load", (context.clone(),))?;
+    }
+    Ok(Loaded { ast, scope, modified })
+}
+
+fn has_function(ast: &AST, name: &str) -> bool {
+    ast.iter_functions().any(|f| f.name == name)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
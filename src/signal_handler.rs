@@ -2,6 +2,7 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+#[allow(clippy::single_component_path_imports)]
 use ctrlc;
 
 pub fn setup_signal_handler() -> Arc<AtomicBool> {
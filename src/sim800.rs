@@ -0,0 +1,658 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::at::{AtCommand, AtCommandSet, AtKind, AtModem, AtResponse, ModemState, ResultCode};
+use crate::at_commands::{BasicCommands, GsmCommands};
+use crate::device::DeviceContext;
+
+/// Состояние IP-стека SIM800, как его выводит `AT+CIPSTATUS`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IpState {
+    Initial,
+    Start,
+    GprsAct,
+    Status,
+    /// Есть соединения в режиме `AT+CIPMUX=1`.
+    Processing,
+}
+
+impl IpState {
+    fn name(self) -> &'static str {
+        match self {
+            IpState::Initial => "IP INITIAL",
+            IpState::Start => "IP START",
+            IpState::GprsAct => "IP GPRSACT",
+            IpState::Status => "IP STATUS",
+            IpState::Processing => "IP PROCESSING",
+        }
+    }
+}
+
+/// Соединений в режиме `AT+CIPMUX=1` (номера 0–5).
+const MAX_CONNECTIONS: usize = 6;
+
+/// Наибольший объём данных в одном `AT+CIPSEND` и в одном сообщении о приёме.
+const MAX_SEND: usize = 1460;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Сколько ждать, пока сокет примет данные `AT+CIPSEND`, прежде чем ответить `SEND FAIL`.
+const SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+const CTRL_Z: u8 = 0x1A;
+const ESC: u8 = 0x1B;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "TCP",
+            Protocol::Udp => "UDP",
+        }
+    }
+}
+
+enum Socket {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Socket {
+    /// Открывает сокет; вызывается в отдельном потоке, чтобы разрешение имени и установка
+    /// соединения не задерживали приём команд.
+    fn open(protocol: Protocol, host: &str, port: u16) -> io::Result<Socket> {
+        let address = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("{}: no address", host)))?;
+        let socket = match protocol {
+            Protocol::Tcp => Socket::Tcp(TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?),
+            Protocol::Udp => {
+                let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+                socket.connect(address)?;
+                Socket::Udp(socket)
+            }
+        };
+        match &socket {
+            Socket::Tcp(stream) => stream.set_nonblocking(true)?,
+            Socket::Udp(socket) => socket.set_nonblocking(true)?,
+        }
+        Ok(socket)
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => {
+                // Неблокирующий сокет может принять данные частями
+                let deadline = Instant::now() + SEND_TIMEOUT;
+                let mut sent = 0;
+                while sent < data.len() {
+                    match stream.write(&data[sent..]) {
+                        Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                        Ok(n) => sent += n,
+                        Err(e) if e.kind() == ErrorKind::WouldBlock && Instant::now() < deadline => {
+                            thread::sleep(Duration::from_millis(1))
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                            return Err(io::Error::new(ErrorKind::TimedOut, format!("{} of {} byte(s) sent", sent, data.len())))
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(())
+            }
+            Socket::Udp(socket) => socket.send(data).map(|_| ()),
+        }
+    }
+
+    /// Принятые данные; `Ok(None)`, если данных нет, и ошибка, если соединение закрыто.
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = [0u8; MAX_SEND];
+        let result = match self {
+            Socket::Tcp(stream) => stream.read(&mut buf),
+            Socket::Udp(socket) => socket.recv(&mut buf),
+        };
+        match result {
+            Ok(0) if matches!(self, Socket::Tcp(_)) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => Ok(Some(buf[..n].to_vec())),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+enum Link {
+    Connecting(Receiver<io::Result<Socket>>),
+    Open(Socket),
+}
+
+struct Connection {
+    protocol: Protocol,
+    host: String,
+    port: u16,
+    link: Link,
+}
+
+/// Ожидаемые после `> ` данные: соединение и длина (`None` — до Ctrl-Z).
+struct PendingSend {
+    index: usize,
+    length: Option<usize>,
+}
+
+/// Команды SIM800 поверх 27.007: `+CCID`, `+CBC`, настройка GPRS (`+CSTT`, `+CIICR`, `+CIFSR`,
+/// `+CIPSHUT`) с проверкой порядка вызова, как у модуля, и соединения TCP/UDP через настоящие
+/// сокеты (`+CIPSTART`, `+CIPSEND`, `+CIPCLOSE`, `+CIPMUX`, `+CIPHEAD`, `+CIPSTATUS`).
+pub struct Sim800Commands {
+    state: IpState,
+    apn: String,
+    mux: bool,
+    /// `AT+CIPHEAD=1`: заголовок `+IPD,<len>:` перед принятыми данными в одиночном режиме.
+    head: bool,
+    connections: [Option<Connection>; MAX_CONNECTIONS],
+    /// Состояние одиночного соединения после закрытия (`TCP CLOSED`).
+    closed: Option<Protocol>,
+    sending: Option<PendingSend>,
+    /// Сообщения, выводимые после итогового ответа текущей команды.
+    pending: Vec<String>,
+}
+
+impl Default for Sim800Commands {
+    fn default() -> Self {
+        Sim800Commands {
+            state: IpState::Initial,
+            apn: "CMNET".to_string(),
+            mux: false,
+            head: false,
+            connections: Default::default(),
+            closed: None,
+            sending: None,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Sim800Commands {
+    /// Модем SIM800 со всеми встроенными наборами команд.
+    pub fn modem() -> AtModem {
+        AtModem::new(
+            "SIM800 AT modem",
+            vec![
+                Box::new(Sim800Commands::default()),
+                Box::new(GsmCommands::new("SIMCOM_Ltd", "SIMCOM_SIM800", "Revision:1418B04SIM800L24")),
+                Box::new(BasicCommands::new(&["SIM800 R14.18"])),
+            ],
+        )
+    }
+
+    /// Текст события соединения: `CONNECT OK` в одиночном режиме и `0, CONNECT OK` в многоканальном.
+    fn event(&self, index: usize, text: &str) -> String {
+        match self.mux {
+            true => format!("{}, {}", index, text),
+            false => text.to_string(),
+        }
+    }
+
+    /// Номер соединения из первых аргументов команды: в одиночном режиме всегда 0.
+    fn connection_index(&self, command: &AtCommand) -> Option<usize> {
+        match self.mux {
+            true => command.arg(0)?.parse().ok().filter(|&index| index < MAX_CONNECTIONS),
+            false => Some(0),
+        }
+    }
+
+    /// `AT+CIPSTART=["<n>",]"TCP|UDP","<host>",<port>`.
+    fn start(&mut self, command: &AtCommand, ctx: &DeviceContext) -> AtResponse {
+        let Some(index) = self.connection_index(command) else {
+            return AtResponse::error();
+        };
+        let AtKind::Set(args) = &command.kind else {
+            return AtResponse::error();
+        };
+        let args = &args[self.mux as usize..];
+        let protocol = match args.first().map(|arg| arg.to_ascii_uppercase()).as_deref() {
+            Some("TCP") => Protocol::Tcp,
+            Some("UDP") => Protocol::Udp,
+            _ => return AtResponse::error(),
+        };
+        let (Some(host), Some(Ok(port))) = (args.get(1), args.get(2).map(|port| port.parse::<u16>())) else {
+            return AtResponse::error();
+        };
+        if self.state == IpState::Start || args.len() != 3 {
+            return AtResponse::error();
+        }
+        if self.connections[index].is_some() {
+            self.pending.push(self.event(index, "ALREADY CONNECT"));
+            return AtResponse::error();
+        }
+        let (tx, rx) = mpsc::channel();
+        let target = host.to_string();
+        thread::spawn(move || {
+            let _ = tx.send(Socket::open(protocol, &target, port));
+        });
+        ctx.log(&format!("[SIM800] Connection {}: {} {}:{}", index, protocol.name(), host, port));
+        self.connections[index] = Some(Connection { protocol, host: host.to_string(), port, link: Link::Connecting(rx) });
+        self.closed = None;
+        self.state = if self.mux { IpState::Processing } else { IpState::Status };
+        AtResponse::ok()
+    }
+
+    /// `AT+CIPSEND[=<n>][,<length>]`: приглашение `> ` для открытого соединения.
+    fn send(&mut self, command: &AtCommand) -> AtResponse {
+        let (index, length) = match (&command.kind, self.mux) {
+            (AtKind::Exec, false) => (Some(0), None),
+            (AtKind::Set(args), false) if args.len() == 1 => (Some(0), Some(args[0].as_str())),
+            (AtKind::Set(args), true) if args.len() <= 2 => (self.connection_index(command), args.get(1).map(String::as_str)),
+            (AtKind::Test, _) => return AtResponse::info([format!("+CIPSEND: {}", if self.mux { "(0-5),(1-1460)" } else { "(1-1460)" })]),
+            _ => return AtResponse::error(),
+        };
+        let length = match length.map(str::parse::<usize>) {
+            Some(Ok(length @ 1..=MAX_SEND)) => Some(length),
+            Some(_) => return AtResponse::error(),
+            None => None,
+        };
+        match index {
+            Some(index) if matches!(self.connections[index], Some(Connection { link: Link::Open(_), .. })) => {
+                self.sending = Some(PendingSend { index, length });
+                AtResponse::code(ResultCode::Prompt)
+            }
+            _ => AtResponse::error(),
+        }
+    }
+
+    /// `AT+CIPCLOSE[=<n>]` в многоканальном режиме или `AT+CIPCLOSE[=<0|1>]` в одиночном.
+    fn close(&mut self, command: &AtCommand, ctx: &DeviceContext) -> AtResponse {
+        let index = match self.mux {
+            true if command.arg(0).is_some() => self.connection_index(command),
+            true => None,
+            false => Some(0),
+        };
+        let Some(connection) = index.and_then(|index| self.connections[index].take()) else {
+            return AtResponse::error();
+        };
+        let index = index.unwrap_or_default();
+        ctx.log(&format!("[SIM800] Connection {} closed", index));
+        if !self.mux {
+            self.closed = Some(connection.protocol);
+        }
+        AtResponse::code(ResultCode::Text(self.event(index, "CLOSE OK")))
+    }
+
+    /// Строки `AT+CIPSTATUS` после `OK`.
+    fn status(&self) -> Vec<String> {
+        let state = match (self.mux, &self.connections[0], self.closed) {
+            (false, Some(Connection { protocol, link: Link::Connecting(_), .. }), _) => format!("{} CONNECTING", protocol.name()),
+            (false, Some(_), _) => "CONNECT OK".to_string(),
+            (false, None, Some(protocol)) => format!("{} CLOSED", protocol.name()),
+            _ => self.state.name().to_string(),
+        };
+        let mut lines = vec!["OK".to_string(), String::new(), format!("STATE: {}", state)];
+        if self.mux {
+            for (index, connection) in self.connections.iter().enumerate() {
+                lines.push(match connection {
+                    Some(connection) => format!(
+                        "C: {},0,\"{}\",\"{}\",\"{}\",\"{}\"",
+                        index,
+                        connection.protocol.name(),
+                        connection.host,
+                        connection.port,
+                        match connection.link {
+                            Link::Connecting(_) => "CONNECTING",
+                            Link::Open(_) => "CONNECTED",
+                        }
+                    ),
+                    None => format!("C: {},,\"\",\"\",\"\",\"INITIAL\"", index),
+                });
+            }
+        }
+        lines
+    }
+
+    /// Сообщение о принятых данных: сырые байты, `+IPD,<len>:` или `+RECEIVE,<n>,<len>:`.
+    fn incoming(&self, index: usize, data: &[u8], modem: &ModemState) -> Vec<u8> {
+        let mut out = match (self.mux, self.head) {
+            (true, _) => modem.format_urc(&format!("+RECEIVE,{},{}:", index, data.len())),
+            (false, true) => {
+                let mut header = modem.format_urc(&format!("+IPD,{}:", data.len()));
+                // Данные идут сразу за двоеточием заголовка
+                header.truncate(header.len() - 2);
+                header
+            }
+            (false, false) => Vec::new(),
+        };
+        out.extend_from_slice(data);
+        out
+    }
+}
+
+/// Сообщает, сколько байт из `len` отброшено сверх предела одной отправки.
+fn discard_over_limit(len: usize, ctx: &DeviceContext) {
+    ctx.log(&format!("[SIM800] Discarded {} byte(s) over the {}-byte send limit", len - MAX_SEND, MAX_SEND));
+}
+
+impl AtCommandSet for Sim800Commands {
+    fn execute(&mut self, command: &AtCommand, _modem: &mut ModemState, ctx: &DeviceContext) -> Option<AtResponse> {
+        let any_open = self.connections.iter().any(Option::is_some);
+        let response = match (command.name.as_str(), &command.kind) {
+            ("+CCID", AtKind::Exec) => AtResponse::info(["89701010000000000000"]),
+            ("+CBC", AtKind::Exec) => AtResponse::info(["+CBC: 0,85,4100"]),
+            ("+CSTT", AtKind::Read) => AtResponse::info([format!("+CSTT: \"{}\",\"\",\"\"", self.apn)]),
+            ("+CSTT", AtKind::Exec | AtKind::Set(_)) if self.state == IpState::Initial => {
+                if let Some(apn) = command.arg(0) {
+                    self.apn = apn.to_string();
+                }
+                self.state = IpState::Start;
+                AtResponse::ok()
+            }
+            ("+CIICR", AtKind::Exec) if self.state == IpState::Start => {
+                self.state = IpState::GprsAct;
+                AtResponse::ok()
+            }
+            // Адрес выводится без `OK`
+            ("+CIFSR", AtKind::Exec) if self.state != IpState::Initial && self.state != IpState::Start => {
+                if self.state == IpState::GprsAct {
+                    self.state = IpState::Status;
+                }
+                AtResponse { lines: vec!["192.168.1.100".to_string()], result: ResultCode::None }
+            }
+            // `OK` приходит до строки состояния
+            ("+CIPSTATUS", AtKind::Exec) => AtResponse { lines: self.status(), result: ResultCode::None },
+            ("+CIPSHUT", AtKind::Exec) => {
+                if any_open {
+                    ctx.log("[SIM800] All connections closed");
+                }
+                let (mux, head) = (self.mux, self.head);
+                *self = Sim800Commands { mux, head, ..Sim800Commands::default() };
+                AtResponse::code(ResultCode::Text("SHUT OK".to_string()))
+            }
+            ("+CIPMUX", AtKind::Read) => AtResponse::info([format!("+CIPMUX: {}", self.mux as u8)]),
+            ("+CIPMUX", AtKind::Test) => AtResponse::info(["+CIPMUX: (0,1)"]),
+            ("+CIPMUX", AtKind::Set(_)) if !any_open => match command.arg(0) {
+                Some(value @ ("0" | "1")) => {
+                    self.mux = value == "1";
+                    AtResponse::ok()
+                }
+                _ => AtResponse::error(),
+            },
+            ("+CIPHEAD", AtKind::Read) => AtResponse::info([format!("+CIPHEAD: {}", self.head as u8)]),
+            ("+CIPHEAD", AtKind::Test) => AtResponse::info(["+CIPHEAD: (0,1)"]),
+            ("+CIPHEAD", AtKind::Set(_)) => match command.arg(0) {
+                Some(value @ ("0" | "1")) => {
+                    self.head = value == "1";
+                    AtResponse::ok()
+                }
+                _ => AtResponse::error(),
+            },
+            ("+CIPSTART", AtKind::Set(_)) => self.start(command, ctx),
+            ("+CIPSTART", AtKind::Test) => AtResponse::info(["+CIPSTART: (\"TCP\",\"UDP\"),(\"(0-255).(0-255).(0-255).(0-255)\"),(1-65535)"]),
+            ("+CIPSEND", _) => self.send(command),
+            ("+CIPCLOSE", AtKind::Exec | AtKind::Set(_)) => self.close(command, ctx),
+            ("+CSTT" | "+CIICR" | "+CIFSR" | "+CIPMUX" | "+CIPSTART" | "+CIPCLOSE", _) => AtResponse::error(),
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    fn on_data(&mut self, buffer: &mut Vec<u8>, modem: &mut ModemState, ctx: &DeviceContext) -> bool {
+        let Some(PendingSend { index, length }) = self.sending else {
+            return true;
+        };
+        let data: Vec<u8> = match length {
+            Some(length) if buffer.len() >= length => buffer.drain(..length).collect(),
+            Some(_) => return false,
+            None => match buffer.iter().position(|&byte| byte == CTRL_Z || byte == ESC) {
+                Some(end) if buffer[end] == ESC => {
+                    buffer.drain(..=end);
+                    self.sending = None;
+                    ctx.log(&format!("[SIM800] Send on connection {} cancelled", index));
+                    return true;
+                }
+                Some(end) => {
+                    let data = buffer[..end.min(MAX_SEND)].to_vec();
+                    if end > MAX_SEND {
+                        discard_over_limit(end, ctx);
+                    }
+                    buffer.drain(..=end);
+                    data
+                }
+                None => {
+                    // Данные сверх предела одной отправки отбрасываются до Ctrl-Z
+                    if buffer.len() > MAX_SEND {
+                        discard_over_limit(buffer.len(), ctx);
+                        buffer.truncate(MAX_SEND);
+                    }
+                    return false;
+                }
+            },
+        };
+        self.sending = None;
+        let result = match &mut self.connections[index] {
+            Some(Connection { link: Link::Open(socket), .. }) => socket.send(&data),
+            _ => Err(ErrorKind::NotConnected.into()),
+        };
+        let event = match result {
+            Ok(()) => {
+                ctx.log(&format!("[SIM800] Sent {} byte(s) on connection {}", data.len(), index));
+                "SEND OK"
+            }
+            Err(e) => {
+                ctx.log(&format!("[SIM800] Send on connection {} failed: {}", index, e));
+                "SEND FAIL"
+            }
+        };
+        ctx.send(modem.format_urc(&self.event(index, event)));
+        true
+    }
+
+    fn on_tick(&mut self, modem: &ModemState, ctx: &DeviceContext) {
+        for text in std::mem::take(&mut self.pending) {
+            ctx.send(modem.format_urc(&text));
+        }
+        // Пока модем ждёт данные после `> `, принятое из сети придерживается
+        if self.sending.is_some() {
+            return;
+        }
+        for index in 0..MAX_CONNECTIONS {
+            let Some(connection) = &mut self.connections[index] else {
+                continue;
+            };
+            let event = match &mut connection.link {
+                Link::Connecting(rx) => match rx.try_recv() {
+                    Ok(Ok(socket)) => {
+                        connection.link = Link::Open(socket);
+                        ctx.log(&format!("[SIM800] Connection {} established", index));
+                        Some("CONNECT OK")
+                    }
+                    Ok(Err(e)) => {
+                        ctx.log(&format!("[SIM800] Connection {} failed: {}", index, e));
+                        Some("CONNECT FAIL")
+                    }
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => Some("CONNECT FAIL"),
+                },
+                Link::Open(socket) => match socket.receive() {
+                    Ok(Some(data)) => {
+                        ctx.log(&format!("[SIM800] Received {} byte(s) on connection {}", data.len(), index));
+                        ctx.send(self.incoming(index, &data, modem));
+                        None
+                    }
+                    Ok(None) => None,
+                    Err(e) => {
+                        ctx.log(&format!("[SIM800] Connection {} closed by peer: {}", index, e));
+                        Some("CLOSED")
+                    }
+                },
+            };
+            let Some(event) = event else {
+                continue;
+            };
+            if event != "CONNECT OK" {
+                let connection = self.connections[index].take();
+                if !self.mux {
+                    self.closed = connection.map(|connection| connection.protocol);
+                }
+            }
+            ctx.send(modem.format_urc(&self.event(index, event)));
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Sim800Commands::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use crate::device::Device;
+    use crate::scheduler::{start_scheduler, Scheduler};
+
+    /// Модем SIM800, ответы которого записываются в файл.
+    struct Harness {
+        modem: AtModem,
+        scheduler: Scheduler,
+        path: PathBuf,
+        /// Сколько байт вывода уже проверено.
+        seen: usize,
+    }
+
+    impl Harness {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("virtualport-{}-{}", std::process::id(), name));
+            let sink = Arc::new(File::create(&path).unwrap());
+            let (scheduler, _) = start_scheduler(Arc::new(AtomicBool::new(true)), sink, None);
+            let mut harness = Harness { modem: Sim800Commands::modem(), scheduler, path, seen: 0 };
+            harness.command("ATE0", "ATE0\r\r\nOK\r\n");
+            harness
+        }
+
+        fn input(&mut self, data: &[u8]) {
+            self.modem.on_frame(data, &DeviceContext { scheduler: &self.scheduler, logger: &None });
+        }
+
+        /// Вызывает `on_tick`, пока вывод не достигнет длины `expected`, и сравнивает его целиком.
+        fn expect(&mut self, expected: &str) {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            let mut output = Vec::new();
+            while output.len() < expected.len() && Instant::now() < deadline {
+                self.modem.on_tick(Instant::now(), &DeviceContext { scheduler: &self.scheduler, logger: &None });
+                thread::sleep(Duration::from_millis(5));
+                output = fs::read(&self.path).unwrap()[self.seen..].to_vec();
+            }
+            self.seen += output.len();
+            assert_eq!(String::from_utf8_lossy(&output), expected);
+        }
+
+        fn command(&mut self, line: &str, expected: &str) {
+            self.input(format!("{}\r", line).as_bytes());
+            self.expect(expected);
+        }
+    }
+
+    impl Drop for Harness {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn read(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut data = vec![0; len];
+        stream.read_exact(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn tcp_connection_in_single_mode() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut modem = Harness::new("sim800-tcp");
+        modem.command(&format!("AT+CIPSTART=\"TCP\",\"127.0.0.1\",{}", port), "\r\nOK\r\n\r\nCONNECT OK\r\n");
+        let (mut peer, _) = listener.accept().unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        modem.command("AT+CIPSTART=\"TCP\",\"127.0.0.1\",1", "\r\nERROR\r\n\r\nALREADY CONNECT\r\n");
+
+        // Данные до Ctrl-Z, данные заданной длины и отмена по ESC
+        modem.command("AT+CIPSEND", "\r\n> ");
+        modem.input(b"hello\x1A");
+        modem.expect("\r\nSEND OK\r\n");
+        assert_eq!(read(&mut peer, 5), b"hello");
+        modem.command("AT+CIPSEND=3", "\r\n> ");
+        modem.input(b"abc");
+        modem.expect("\r\nSEND OK\r\n");
+        modem.command("AT+CIPSEND", "\r\n> ");
+        modem.input(b"dropped\x1B");
+        modem.command("AT", "\r\nOK\r\n");
+        assert_eq!(read(&mut peer, 3), b"abc");
+
+        // Не больше 1460 байт за одну отправку, в одной порции данных или в нескольких
+        modem.command("AT+CIPSEND=1461", "\r\nERROR\r\n");
+        modem.command("AT+CIPSEND", "\r\n> ");
+        let mut data = vec![b'x'; 1500];
+        data.push(CTRL_Z);
+        modem.input(&data);
+        modem.expect("\r\nSEND OK\r\n");
+        modem.command("AT+CIPSEND", "\r\n> ");
+        modem.input(&[b'y'; 1000]);
+        modem.input(&[b'y'; 1000]);
+        modem.input(&[CTRL_Z]);
+        modem.expect("\r\nSEND OK\r\n");
+        modem.command("AT+CIPSEND=3", "\r\n> ");
+        modem.input(b"end");
+        modem.expect("\r\nSEND OK\r\n");
+        assert_eq!(read(&mut peer, 1460), vec![b'x'; 1460]);
+        assert_eq!(read(&mut peer, 1460), vec![b'y'; 1460]);
+        assert_eq!(read(&mut peer, 3), b"end");
+
+        // Принятые данные выводятся как есть, а после `AT+CIPHEAD=1` с заголовком `+IPD`
+        peer.write_all(b"pong").unwrap();
+        modem.expect("pong");
+        modem.command("AT+CIPHEAD=1", "\r\nOK\r\n");
+        peer.write_all(b"ping").unwrap();
+        modem.expect("\r\n+IPD,4:ping");
+
+        drop(peer);
+        modem.expect("\r\nCLOSED\r\n");
+        modem.command("AT+CIPSTATUS", "\r\nOK\r\n\r\nSTATE: TCP CLOSED\r\n");
+        modem.command("AT+CIPSEND", "\r\nERROR\r\n");
+    }
+
+    #[test]
+    fn udp_connection_in_multiplexed_mode() {
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let port = peer.local_addr().unwrap().port();
+        let mut modem = Harness::new("sim800-udp");
+        modem.command("AT+CIPMUX=1", "\r\nOK\r\n");
+        modem.command(&format!("AT+CIPSTART=2,\"UDP\",\"127.0.0.1\",{}", port), "\r\nOK\r\n\r\n2, CONNECT OK\r\n");
+        modem.command("AT+CIPSEND=3", "\r\nERROR\r\n");
+
+        modem.command("AT+CIPSEND=2,4", "\r\n> ");
+        modem.input(b"data");
+        modem.expect("\r\n2, SEND OK\r\n");
+        modem.command("AT+CIPSEND=2", "\r\n> ");
+        modem.input(b"cancelled\x1B");
+        modem.command("AT+CIPSEND=2", "\r\n> ");
+        modem.input(b"more\x1A");
+        modem.expect("\r\n2, SEND OK\r\n");
+        let mut buf = [0u8; 64];
+        let (len, address) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"data");
+        let (len, _) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"more");
+
+        peer.send_to(b"resp", address).unwrap();
+        modem.expect("\r\n+RECEIVE,2,4:\r\nresp");
+        modem.command("AT+CIPCLOSE=2", "\r\n2, CLOSE OK\r\n");
+        modem.command("AT+CIPSEND=2", "\r\nERROR\r\n");
+    }
+}
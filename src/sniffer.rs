@@ -0,0 +1,203 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, ControlFlags, SetArg, Termios};
+use crate::hex::escape_bytes;
+use crate::intercept::{start_intercept_console, Interceptor};
+use crate::logger::{log_message, open_log_file, timestamp};
+use crate::port::{VirtualPort, VirtualPortBuilder};
+use crate::pty::{describe_termios, write_all};
+
+/// Как часто настройки линии виртуального порта переносятся на устройство.
+const TERMIOS_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Прослушивание обмена между программой и настоящим устройством: программа открывает
+/// виртуальный порт, данные в обе стороны пересылаются без изменений и записываются в лог
+/// с направлением и временем. Скорость и стоповые биты, которые программа устанавливает
+/// на виртуальном порту, переносятся на устройство; размер символа и паритет тоже, кроме Linux,
+/// где PTY их не сохраняет. С перехватом каждый кадр задерживается до решения в консоли.
+pub struct Sniffer {
+    port: VirtualPort,
+    threads: Vec<JoinHandle<()>>,
+    /// Флаг работы порта и чтения устройства; внешний флаг `running` только читается.
+    sniff_running: Arc<AtomicBool>,
+}
+
+impl Sniffer {
+    /// Открывает устройство (любой tty, в том числе другой PTY) и виртуальный порт.
+    /// Начальная скорость устройства берётся из виртуального порта, паритет — `parity`.
+    pub fn start(
+        builder: VirtualPortBuilder,
+        device_path: &str,
+        parity: &str,
+        running: Arc<AtomicBool>,
+        log_file: Option<&str>,
+        intercept: bool,
+    ) -> io::Result<Sniffer> {
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(device_path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Cannot open device '{}': {}", device_path, e)))?;
+        let mut termios = tcgetattr(&device)?;
+        cfmakeraw(&mut termios);
+        termios.control_flags |= ControlFlags::CLOCAL | ControlFlags::CREAD;
+        termios.control_flags.remove(ControlFlags::PARENB | ControlFlags::PARODD);
+        match parity {
+            "even" => termios.control_flags |= ControlFlags::PARENB,
+            "odd" => termios.control_flags |= ControlFlags::PARENB | ControlFlags::PARODD,
+            _ => {}
+        }
+        tcsetattr(&device, SetArg::TCSANOW, &termios).map_err(|e| {
+            io::Error::new(ErrorKind::InvalidInput, format!("Cannot configure '{}' (parity {}): {}", device_path, parity, e))
+        })?;
+        let device = Arc::new(device);
+        let logger = match log_file {
+            Some(path) => {
+                println!("[Info] Logging sniffed traffic to file: {}", path);
+                Some(open_log_file(path)?)
+            }
+            None => None,
+        };
+
+        let (to_device, from_app) = mpsc::channel();
+        let (to_app, from_device) = mpsc::channel();
+        let sniff_running = Arc::new(AtomicBool::new(true));
+        let port = builder.running(Arc::clone(&sniff_running)).forward(to_device).build()?;
+        sync_termios(port.slave(), &device)?;
+        println!("[Info] Sniffing {} ({})", device_path, describe_termios(&device));
+
+        let interceptor = intercept.then(Interceptor::new);
+        let (from_app, from_device) = match &interceptor {
+            Some(interceptor) => (interceptor.route("APP->DEV", from_app), interceptor.route("DEV->APP", from_device)),
+            None => (from_app, from_device),
+        };
+        let mut threads = vec![
+            // Программа могла сменить скорость прямо перед записью
+            start_delivery("APP->DEV", from_app, Arc::clone(&device), Some(Arc::clone(port.slave())), logger.clone()),
+            start_delivery("DEV->APP", from_device, Arc::clone(port.master()), None, logger),
+            start_device_reader(device, Arc::clone(port.slave()), to_app, running, Arc::clone(&sniff_running)),
+        ];
+        threads.extend(interceptor.map(start_intercept_console));
+        Ok(Sniffer { port, threads, sniff_running })
+    }
+
+    pub fn port(&self) -> &VirtualPort {
+        &self.port
+    }
+
+    /// Блокирует до остановки по внешнему флагу или до потери устройства.
+    pub fn wait(mut self) {
+        // Поток чтения устройства останавливает порт, остальные потоки завершаются вслед за ним
+        for handle in self.threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Sniffer {
+    fn drop(&mut self) {
+        self.sniff_running.store(false, Ordering::SeqCst);
+        for handle in self.threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Выводит и записывает в лог данные одного направления с временем.
+fn record(logger: &Option<Arc<Mutex<File>>>, direction: &str, data: &[u8]) {
+    let line = format!("[{}] {} {}", direction, timestamp(), escape_bytes(data));
+    println!("{}", line);
+    log_message(logger, &line);
+}
+
+/// Записывает данные одного направления в `target`. Если задан `slave`, перед записью на
+/// устройство переносятся настройки линии виртуального порта.
+fn start_delivery(
+    direction: &'static str,
+    rx: Receiver<Vec<u8>>,
+    target: Arc<File>,
+    slave: Option<Arc<File>>,
+    logger: Option<Arc<Mutex<File>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for data in rx {
+            if let Some(slave) = &slave {
+                let _ = sync_termios(slave, &target);
+            }
+            record(&logger, direction, &data);
+            if let Err(e) = write_all(&target, &data) {
+                eprintln!("[Sniff] Dropped {} byte(s) {}: {}", data.len(), direction, e);
+            }
+        }
+    })
+}
+
+/// Читает устройство и передаёт данные для программы в `to_app`, пока не сброшен внешний
+/// флаг `running` или флаг прослушивания `sniff_running`.
+fn start_device_reader(
+    device: Arc<File>,
+    slave: Arc<File>,
+    to_app: Sender<Vec<u8>>,
+    running: Arc<AtomicBool>,
+    sniff_running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0u8; 1024];
+        let mut last_check = Instant::now();
+        while running.load(Ordering::SeqCst) && sniff_running.load(Ordering::SeqCst) {
+            if last_check.elapsed() >= TERMIOS_CHECK_INTERVAL {
+                last_check = Instant::now();
+                if let Err(e) = sync_termios(&slave, &device) {
+                    eprintln!("[Sniff] Cannot apply line settings to the device: {}", e);
+                }
+            }
+            match device.as_ref().read(&mut buf) {
+                Ok(0) => {
+                    eprintln!("[Sniff] Device closed.");
+                    break;
+                }
+                Ok(n) => {
+                    let _ = to_app.send(buf[..n].to_vec());
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                Err(e) => {
+                    eprintln!("[Sniff] Error reading from the device: {}", e);
+                    break;
+                }
+            }
+        }
+        // Без устройства прослушивание теряет смысл: останавливаем и виртуальный порт
+        sniff_running.store(false, Ordering::SeqCst);
+    })
+}
+
+/// Переносит настройки линии виртуального порта на устройство. Возвращает `true`, если
+/// настройки устройства изменились.
+fn sync_termios(slave: &File, device: &File) -> io::Result<bool> {
+    let source: libc::termios = tcgetattr(slave)?.into();
+    let mut target: libc::termios = tcgetattr(device)?.into();
+    // PTY в Linux всегда сбрасывает CSIZE и PARENB, поэтому там паритет задаётся только `--parity`
+    let mask = match cfg!(target_os = "linux") {
+        true => libc::CSTOPB,
+        false => libc::CSIZE | libc::CSTOPB | libc::PARENB | libc::PARODD,
+    };
+    let speed = unsafe { libc::cfgetospeed(&source) };
+    if source.c_cflag & mask == target.c_cflag & mask && unsafe { libc::cfgetospeed(&target) } == speed {
+        return Ok(false);
+    }
+    target.c_cflag = (target.c_cflag & !mask) | (source.c_cflag & mask);
+    unsafe {
+        libc::cfsetispeed(&mut target, speed);
+        libc::cfsetospeed(&mut target, speed);
+    }
+    tcsetattr(device, SetArg::TCSANOW, &Termios::from(target))?;
+    println!("[Sniff] Device line settings: {}", describe_termios(device));
+    Ok(true)
+}
@@ -0,0 +1,444 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::hex::escape_bytes;
+use crate::logger::{log_data, log_message, open_log_file};
+use crate::port::{VirtualPort, VirtualPortBuilder};
+use crate::pty::write_all;
+
+/// Как часто потоки проверяют флаг работы, ожидая подключений и данных клиентов.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Сколько ждать, пока клиент примет данные, прежде чем отключить его.
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Что делать с новым клиентом, когда уже подключён другой.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClientPolicy {
+    /// Отклонять новых клиентов, пока подключён первый.
+    #[default]
+    Reject,
+    /// Обслуживать всех: данные порта получают все клиенты, данные любого клиента идут в порт.
+    Share,
+    /// Отключать прежнего клиента и обслуживать нового.
+    LastWins,
+}
+
+impl FromStr for ClientPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "reject" => Ok(ClientPolicy::Reject),
+            "share" => Ok(ClientPolicy::Share),
+            "last-wins" => Ok(ClientPolicy::LastWins),
+            _ => Err(format!("Invalid client policy '{}' (expected reject, share or last-wins)", text)),
+        }
+    }
+}
+
+impl fmt::Display for ClientPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ClientPolicy::Reject => "reject",
+            ClientPolicy::Share => "share",
+            ClientPolicy::LastWins => "last-wins",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Подключённый клиент; поток чтения держит свою копию сокета.
+struct Client {
+    addr: SocketAddr,
+    stream: TcpStream,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+/// Сырой TCP-сервер перед виртуальным портом, как ser2net в режиме raw: байты клиентов
+/// записываются в master-сторону порта, а данные, которые программа пишет в порт, получают
+/// клиенты. Таблица команд, скрипт, устройство и консоль порта работают как обычно;
+/// данные, на которые они ответили, клиентам не передаются.
+pub struct TcpBridge {
+    port: VirtualPort,
+    threads: Vec<JoinHandle<()>>,
+    /// Флаг работы порта и сервера; внешний флаг `running` только читается.
+    bridge_running: Arc<AtomicBool>,
+}
+
+impl TcpBridge {
+    /// Открывает сокет на `addr` и создаёт порт. Подключения и данные каждого клиента
+    /// записываются в `log_file` с адресом клиента.
+    pub fn start(
+        builder: VirtualPortBuilder,
+        addr: &str,
+        policy: ClientPolicy,
+        running: Arc<AtomicBool>,
+        log_file: Option<&str>,
+    ) -> io::Result<TcpBridge> {
+        let listener = TcpListener::bind(addr)
+            .map_err(|e| io::Error::new(e.kind(), format!("Cannot listen on '{}': {}", addr, e)))?;
+        listener.set_nonblocking(true)?;
+        let logger = match log_file {
+            Some(path) => Some(open_log_file(path)?),
+            None => None,
+        };
+        let (tx, rx) = mpsc::channel();
+        let bridge_running = Arc::new(AtomicBool::new(true));
+        let port = builder.running(Arc::clone(&bridge_running)).forward(tx).build()?;
+        println!("[TCP] Listening on {} (policy: {})", listener.local_addr()?, policy);
+
+        let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+        let threads = vec![
+            start_acceptor(
+                listener,
+                policy,
+                Arc::clone(&clients),
+                Arc::clone(port.master()),
+                running,
+                Arc::clone(&bridge_running),
+                logger.clone(),
+            ),
+            start_broadcast(rx, clients, logger),
+        ];
+        Ok(TcpBridge { port, threads, bridge_running })
+    }
+
+    pub fn port(&self) -> &VirtualPort {
+        &self.port
+    }
+
+    /// Блокирует до остановки по внешнему флагу.
+    pub fn wait(mut self) {
+        // Сервер останавливает порт, а рассылка заканчивается, когда поток чтения порта закрывает канал
+        for handle in self.threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for TcpBridge {
+    fn drop(&mut self) {
+        self.bridge_running.store(false, Ordering::SeqCst);
+        for handle in self.threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Выводит и записывает в лог событие сервера.
+fn report(logger: &Option<Arc<Mutex<File>>>, msg: String) {
+    println!("{}", msg);
+    log_message(logger, &msg);
+}
+
+/// Принимает подключения по политике и запускает поток чтения для каждого клиента, пока
+/// не сброшен внешний флаг `running` или флаг сервера `bridge_running`. Остановившись,
+/// сбрасывает `bridge_running`, чтобы остановить порт и потоки клиентов.
+fn start_acceptor(
+    listener: TcpListener,
+    policy: ClientPolicy,
+    clients: Clients,
+    master: Arc<File>,
+    running: Arc<AtomicBool>,
+    bridge_running: Arc<AtomicBool>,
+    logger: Option<Arc<Mutex<File>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut readers: Vec<JoinHandle<()>> = Vec::new();
+        while running.load(Ordering::SeqCst) && bridge_running.load(Ordering::SeqCst) {
+            let (stream, addr) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("[TCP] Error accepting a client: {}", e);
+                    continue;
+                }
+            };
+            let mut connected = clients.lock().unwrap();
+            match policy {
+                ClientPolicy::Reject if !connected.is_empty() => {
+                    report(&logger, format!("[TCP] Rejected client {}: {} is connected", addr, connected[0].addr));
+                    let _ = stream.shutdown(Shutdown::Both);
+                    continue;
+                }
+                ClientPolicy::LastWins => {
+                    for old in connected.drain(..) {
+                        report(&logger, format!("[TCP] Client {} replaced by {}", old.addr, addr));
+                        let _ = old.stream.shutdown(Shutdown::Both);
+                    }
+                }
+                _ => {}
+            }
+            let reader = match configure(&stream).and_then(|_| stream.try_clone()) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    eprintln!("[TCP] Cannot set up client {}: {}", addr, e);
+                    continue;
+                }
+            };
+            // Потоки отключившихся клиентов уже завершились
+            readers.retain(|handle| !handle.is_finished());
+            connected.push(Client { addr, stream });
+            report(&logger, format!("[TCP] Client {} connected", addr));
+            readers.push(start_client_reader(
+                reader,
+                addr,
+                Arc::clone(&clients),
+                Arc::clone(&master),
+                Arc::clone(&bridge_running),
+                logger.clone(),
+            ));
+        }
+        bridge_running.store(false, Ordering::SeqCst);
+        for handle in readers {
+            let _ = handle.join();
+        }
+    })
+}
+
+fn configure(stream: &TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))
+}
+
+/// Читает данные клиента и записывает их в master-сторону порта.
+fn start_client_reader(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    clients: Clients,
+    master: Arc<File>,
+    running: Arc<AtomicBool>,
+    logger: Option<Arc<Mutex<File>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let tag = format!("{}->PTY", addr);
+        let mut buf = [0u8; 1024];
+        while running.load(Ordering::SeqCst) {
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    println!("[{}] {}", tag, escape_bytes(&buf[..n]));
+                    log_data(&logger, &tag, &buf[..n]);
+                    if let Err(e) = write_all(&master, &buf[..n]) {
+                        eprintln!("[TCP] Dropped {} byte(s) from {}: {}", n, addr, e);
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => {}
+                Err(_) => break,
+            }
+        }
+        // Вытесненный клиент уже удалён из списка и записан в лог
+        let mut connected = clients.lock().unwrap();
+        if let Some(index) = connected.iter().position(|client| client.addr == addr) {
+            connected.remove(index);
+            report(&logger, format!("[TCP] Client {} disconnected", addr));
+        }
+    })
+}
+
+/// Передаёт данные, записанные программой в порт, всем подключённым клиентам.
+fn start_broadcast(rx: Receiver<Vec<u8>>, clients: Clients, logger: Option<Arc<Mutex<File>>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for data in rx {
+            let mut connected = clients.lock().unwrap();
+            let note = if connected.is_empty() { " (no client connected)" } else { "" };
+            println!("[PTY->TCP] {}{}", escape_bytes(&data), note);
+            log_data(&logger, "PTY->TCP", &data);
+            connected.retain_mut(|client| match client.stream.write_all(&data) {
+                Ok(()) => true,
+                Err(e) => {
+                    report(&logger, format!("[TCP] Client {} dropped: {}", client.addr, e));
+                    let _ = client.stream.shutdown(Shutdown::Both);
+                    false
+                }
+            });
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::mpsc::Sender;
+    use std::time::Instant;
+
+    /// Сервер на 127.0.0.1 без порта: данные клиентов записываются в файл вместо master-стороны.
+    struct Server {
+        addr: SocketAddr,
+        clients: Clients,
+        to_clients: Option<Sender<Vec<u8>>>,
+        path: PathBuf,
+        bridge_running: Arc<AtomicBool>,
+        threads: Vec<JoinHandle<()>>,
+    }
+
+    impl Server {
+        fn start(name: &str, policy: ClientPolicy) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.set_nonblocking(true).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let path = std::env::temp_dir().join(format!("virtualport-{}-{}", std::process::id(), name));
+            let master = Arc::new(File::create(&path).unwrap());
+            let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+            let bridge_running = Arc::new(AtomicBool::new(true));
+            let (tx, rx) = mpsc::channel();
+            let threads = vec![
+                start_acceptor(
+                    listener,
+                    policy,
+                    Arc::clone(&clients),
+                    master,
+                    Arc::new(AtomicBool::new(true)),
+                    Arc::clone(&bridge_running),
+                    None,
+                ),
+                start_broadcast(rx, Arc::clone(&clients), None),
+            ];
+            Server { addr, clients, to_clients: Some(tx), path, bridge_running, threads }
+        }
+
+        /// Подключает клиента и ждёт, пока сервер обработает подключение.
+        fn connect(&self, expected_clients: usize) -> TcpStream {
+            let stream = TcpStream::connect(self.addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+            let local = stream.local_addr().unwrap();
+            wait_until(|| {
+                let connected = self.clients.lock().unwrap();
+                connected.len() == expected_clients && connected.iter().any(|client| client.addr == local)
+            });
+            stream
+        }
+
+        fn connected(&self) -> Vec<SocketAddr> {
+            self.clients.lock().unwrap().iter().map(|client| client.addr).collect()
+        }
+
+        fn broadcast(&self, data: &[u8]) {
+            self.to_clients.as_ref().unwrap().send(data.to_vec()).unwrap();
+        }
+
+        /// Ждёт, пока в порт будет записано `len` байт, и возвращает их.
+        fn written(&self, len: usize) -> Vec<u8> {
+            wait_until(|| fs::read(&self.path).unwrap().len() >= len);
+            fs::read(&self.path).unwrap()
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            self.bridge_running.store(false, Ordering::SeqCst);
+            self.to_clients = None;
+            for handle in self.threads.drain(..) {
+                let _ = handle.join();
+            }
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn read(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut data = vec![0; len];
+        stream.read_exact(&mut data).unwrap();
+        data
+    }
+
+    /// Сервер закрыл соединение клиента.
+    fn is_closed(stream: &mut TcpStream) -> bool {
+        !matches!(stream.read(&mut [0u8; 16]), Ok(1..))
+    }
+
+    #[test]
+    fn reject_keeps_the_first_client() {
+        let server = Server::start("tcp-reject", ClientPolicy::Reject);
+        let mut first = server.connect(1);
+        let mut second = TcpStream::connect(server.addr).unwrap();
+        second.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        assert!(is_closed(&mut second));
+        assert_eq!(server.connected(), vec![first.local_addr().unwrap()]);
+        first.write_all(b"hello").unwrap();
+        assert_eq!(server.written(5), b"hello");
+        server.broadcast(b"reply");
+        assert_eq!(read(&mut first, 5), b"reply");
+        // После отключения первого клиента подключение снова принимается
+        drop(first);
+        wait_until(|| server.connected().is_empty());
+        let mut third = server.connect(1);
+        server.broadcast(b"again");
+        assert_eq!(read(&mut third, 5), b"again");
+    }
+
+    #[test]
+    fn share_serves_every_client() {
+        let server = Server::start("tcp-share", ClientPolicy::Share);
+        let mut first = server.connect(1);
+        let mut second = server.connect(2);
+        server.broadcast(b"to all");
+        assert_eq!(read(&mut first, 6), b"to all");
+        assert_eq!(read(&mut second, 6), b"to all");
+        first.write_all(b"a").unwrap();
+        server.written(1);
+        second.write_all(b"b").unwrap();
+        assert_eq!(server.written(2), b"ab");
+        drop(first);
+        wait_until(|| server.connected().len() == 1);
+        server.broadcast(b"rest");
+        assert_eq!(read(&mut second, 4), b"rest");
+    }
+
+    #[test]
+    fn last_wins_replaces_the_previous_client() {
+        let server = Server::start("tcp-last-wins", ClientPolicy::LastWins);
+        let mut first = server.connect(1);
+        let mut second = server.connect(1);
+        assert!(is_closed(&mut first));
+        assert_eq!(server.connected(), vec![second.local_addr().unwrap()]);
+        server.broadcast(b"new");
+        assert_eq!(read(&mut second, 3), b"new");
+        second.write_all(b"ok").unwrap();
+        assert_eq!(server.written(2), b"ok");
+    }
+
+    #[test]
+    fn broadcast_drops_clients_that_cannot_receive() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut receiving = TcpStream::connect(addr).unwrap();
+        let (stream, receiving_addr) = listener.accept().unwrap();
+        let _closed = TcpStream::connect(addr).unwrap();
+        let (closed, closed_addr) = listener.accept().unwrap();
+        closed.shutdown(Shutdown::Write).unwrap();
+        let clients: Clients = Arc::new(Mutex::new(vec![
+            Client { addr: receiving_addr, stream },
+            Client { addr: closed_addr, stream: closed },
+        ]));
+        let (tx, rx) = mpsc::channel();
+        tx.send(b"data".to_vec()).unwrap();
+        drop(tx);
+        start_broadcast(rx, Arc::clone(&clients), None).join().unwrap();
+        assert_eq!(read(&mut receiving, 4), b"data");
+        let connected: Vec<SocketAddr> = clients.lock().unwrap().iter().map(|client| client.addr).collect();
+        assert_eq!(connected, vec![receiving_addr]);
+    }
+}
@@ -0,0 +1,394 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::calendar::days_from_civil;
+
+/// Средний радиус Земли в метрах.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Источник координат: неподвижная точка, синтетическое движение или файл трека.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrackSource {
+    /// `static:<lat>,<lon>[,<alt>]`
+    Static { lat: f64, lon: f64, alt: f64 },
+    /// Движение по прямой с постоянной скоростью: `line:<lat>,<lon>,<km/h>,<course>`.
+    Line { lat: f64, lon: f64, speed_kmh: f64, course: f64 },
+    /// Движение по окружности по часовой стрелке: `circle:<lat>,<lon>,<radius m>,<km/h>`.
+    Circle { lat: f64, lon: f64, radius: f64, speed_kmh: f64 },
+    /// Файл GPX или CSV (`time,lat,lon[,alt]`).
+    File(PathBuf),
+}
+
+impl FromStr for TrackSource {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let Some((kind, args)) = text.split_once(':') else {
+            return Ok(TrackSource::File(PathBuf::from(text)));
+        };
+        let numbers = || -> Result<Vec<f64>, String> {
+            args.split(',')
+                .map(|arg| arg.trim().parse().map_err(|_| format!("Invalid number '{}' in track '{}'", arg, text)))
+                .collect()
+        };
+        let source = match kind {
+            "static" => match numbers()?[..] {
+                [lat, lon] => TrackSource::Static { lat, lon, alt: 0.0 },
+                [lat, lon, alt] => TrackSource::Static { lat, lon, alt },
+                _ => return Err(format!("Expected static:<lat>,<lon>[,<alt>], got '{}'", text)),
+            },
+            "line" => match numbers()?[..] {
+                [lat, lon, speed_kmh, course] => TrackSource::Line { lat, lon, speed_kmh, course },
+                _ => return Err(format!("Expected line:<lat>,<lon>,<km/h>,<course>, got '{}'", text)),
+            },
+            "circle" => match numbers()?[..] {
+                [lat, lon, radius, speed_kmh] if radius > 0.0 => TrackSource::Circle { lat, lon, radius, speed_kmh },
+                _ => return Err(format!("Expected circle:<lat>,<lon>,<radius m>,<km/h> with a positive radius, got '{}'", text)),
+            },
+            // Например, путь с двоеточием: считается файлом
+            _ => return Ok(TrackSource::File(PathBuf::from(text))),
+        };
+        match source {
+            TrackSource::Static { lat, lon, .. }
+            | TrackSource::Line { lat, lon, .. }
+            | TrackSource::Circle { lat, lon, .. }
+                if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) =>
+            {
+                Err(format!("Coordinates out of range in track '{}'", text))
+            }
+            source => Ok(source),
+        }
+    }
+}
+
+impl fmt::Display for TrackSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackSource::Static { lat, lon, alt } => write!(f, "static:{},{},{}", lat, lon, alt),
+            TrackSource::Line { lat, lon, speed_kmh, course } => write!(f, "line:{},{},{},{}", lat, lon, speed_kmh, course),
+            TrackSource::Circle { lat, lon, radius, speed_kmh } => write!(f, "circle:{},{},{},{}", lat, lon, radius, speed_kmh),
+            TrackSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Положение в момент времени.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Fix {
+    pub lat: f64,
+    pub lon: f64,
+    /// Высота над уровнем моря, м.
+    pub alt: f64,
+    /// Скорость, м/с.
+    pub speed: f64,
+    /// Курс, градусы от севера по часовой стрелке.
+    pub course: f64,
+    /// Время UTC (секунды Unix), если оно задано треком.
+    pub time: Option<f64>,
+}
+
+/// Точка трека; `offset` — секунды от начала трека.
+#[derive(Clone, Copy, Debug)]
+struct TrackPoint {
+    offset: f64,
+    lat: f64,
+    lon: f64,
+    alt: f64,
+}
+
+/// Трек, из которого берутся положения по времени от начала воспроизведения.
+#[derive(Clone, Debug)]
+pub struct Track {
+    kind: TrackKind,
+}
+
+#[derive(Clone, Debug)]
+enum TrackKind {
+    Synthetic(TrackSource),
+    /// Точки из файла; `start` — время UTC первой точки, если в файле есть время.
+    Recorded { points: Vec<TrackPoint>, start: Option<f64> },
+}
+
+impl Track {
+    /// Создаёт трек, загружая файл GPX или CSV (по расширению).
+    pub fn load(source: &TrackSource) -> io::Result<Track> {
+        let TrackSource::File(path) = source else {
+            return Ok(Track { kind: TrackKind::Synthetic(source.clone()) });
+        };
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Cannot open track '{}': {}", path.display(), e)))?;
+        let result = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gpx")) {
+            parse_gpx(&text)
+        } else {
+            parse_csv(&text)
+        };
+        let (points, start) =
+            result.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+        Ok(Track { kind: TrackKind::Recorded { points, start } })
+    }
+
+    /// Описание трека для вывода.
+    pub fn summary(&self) -> String {
+        match &self.kind {
+            TrackKind::Synthetic(source) => source.to_string(),
+            TrackKind::Recorded { points, .. } => {
+                format!("{} point(s) over {:.0} s", points.len(), points.last().map_or(0.0, |point| point.offset))
+            }
+        }
+    }
+
+    /// Положение через `elapsed` секунд от начала. Трек из файла повторяется по кругу,
+    /// а время UTC при повторе продолжает идти вперёд.
+    pub fn fix_at(&self, elapsed: f64) -> Fix {
+        match &self.kind {
+            TrackKind::Synthetic(TrackSource::Static { lat, lon, alt }) => Fix { lat: *lat, lon: *lon, alt: *alt, ..Fix::default() },
+            TrackKind::Synthetic(TrackSource::Line { lat, lon, speed_kmh, course }) => {
+                let speed = speed_kmh / 3.6;
+                let (lat, lon) = destination(*lat, *lon, *course, speed * elapsed);
+                Fix { lat, lon, speed, course: course.rem_euclid(360.0), ..Fix::default() }
+            }
+            TrackKind::Synthetic(TrackSource::Circle { lat, lon, radius, speed_kmh }) => {
+                let speed = speed_kmh / 3.6;
+                let bearing = (speed * elapsed / radius).to_degrees();
+                let (lat, lon) = destination(*lat, *lon, bearing, *radius);
+                Fix { lat, lon, speed, course: (bearing + 90.0).rem_euclid(360.0), ..Fix::default() }
+            }
+            // Файл при загрузке превращается в `Recorded`
+            TrackKind::Synthetic(TrackSource::File(_)) => Fix::default(),
+            TrackKind::Recorded { points, start } => {
+                let duration = points.last().map_or(0.0, |point| point.offset);
+                let offset = if duration > 0.0 { elapsed.rem_euclid(duration) } else { 0.0 };
+                // Отрезок, на котором находится момент `offset`
+                let index = points.partition_point(|point| point.offset <= offset).clamp(1, points.len().max(2) - 1);
+                let time = start.map(|start| start + elapsed);
+                let Some((a, b)) = points.get(index - 1).zip(points.get(index)) else {
+                    let point = points[0];
+                    return Fix { lat: point.lat, lon: point.lon, alt: point.alt, time, ..Fix::default() };
+                };
+                let span = b.offset - a.offset;
+                let k = if span > 0.0 { ((offset - a.offset) / span).clamp(0.0, 1.0) } else { 1.0 };
+                let distance = haversine(a.lat, a.lon, b.lat, b.lon);
+                Fix {
+                    lat: a.lat + (b.lat - a.lat) * k,
+                    lon: a.lon + (b.lon - a.lon) * k,
+                    alt: a.alt + (b.alt - a.alt) * k,
+                    speed: if span > 0.0 { distance / span } else { 0.0 },
+                    course: bearing(a.lat, a.lon, b.lat, b.lon),
+                    time,
+                }
+            }
+        }
+    }
+}
+
+/// Воспроизведение трека в реальном или ускоренном времени, начиная с первого запроса положения.
+#[derive(Clone, Debug)]
+pub struct Playback {
+    track: Track,
+    time_scale: f64,
+    /// Момент начала и время UTC в этот момент.
+    started: Option<(Instant, f64)>,
+}
+
+impl Playback {
+    pub fn new(track: Track, time_scale: f64) -> Self {
+        Playback { track, time_scale, started: None }
+    }
+
+    pub fn track(&self) -> &Track {
+        &self.track
+    }
+
+    /// Положение в момент `now` и время трека в секундах от начала. Время UTC в `Fix::time`
+    /// задано всегда: из трека или от текущего времени на момент начала.
+    pub fn fix(&mut self, now: Instant) -> (Fix, f64) {
+        let (start, start_utc) = *self.started.get_or_insert_with(|| (now, unix_now()));
+        let elapsed = (now - start).as_secs_f64() * self.time_scale;
+        let mut fix = self.track.fix_at(elapsed);
+        fix.time = Some(fix.time.unwrap_or(start_utc + elapsed));
+        (fix, elapsed)
+    }
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+/// Точка на расстоянии `distance` метров от исходной по курсу `bearing`.
+fn destination(lat: f64, lon: f64, bearing: f64, distance: f64) -> (f64, f64) {
+    let (lat1, lon1, theta) = (lat.to_radians(), lon.to_radians(), bearing.to_radians());
+    let delta = distance / EARTH_RADIUS;
+    let lat2 = (lat1.sin() * delta.cos() + lat1.cos() * delta.sin() * theta.cos()).asin();
+    let lon2 = lon1 + (theta.sin() * delta.sin() * lat1.cos()).atan2(delta.cos() - lat1.sin() * lat2.sin());
+    (lat2.to_degrees(), (lon2.to_degrees() + 540.0).rem_euclid(360.0) - 180.0)
+}
+
+fn haversine(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let (dphi, dlambda) = ((lat2 - lat1).to_radians(), (lon2 - lon1).to_radians());
+    let a = (dphi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (dlambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+fn bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let dlambda = (lon2 - lon1).to_radians();
+    let y = dlambda.sin() * phi2.cos();
+    let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dlambda.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Точки трека с временем UTC (если есть) до приведения к смещениям от начала.
+type RawPoint = (Option<f64>, f64, f64, f64);
+
+/// Приводит время точек к смещениям от первой точки. Если времени нет ни у одной точки,
+/// точки идут с интервалом в секунду.
+fn into_points(raw: Vec<RawPoint>) -> Result<(Vec<TrackPoint>, Option<f64>), String> {
+    if raw.is_empty() {
+        return Err("the track has no points".to_string());
+    }
+    let timed = raw.iter().filter(|point| point.0.is_some()).count();
+    if timed != 0 && timed != raw.len() {
+        return Err("either all track points or none must have a time".to_string());
+    }
+    let start = raw[0].0;
+    let mut points = Vec::with_capacity(raw.len());
+    for (index, (time, lat, lon, alt)) in raw.into_iter().enumerate() {
+        let offset = match (time, start) {
+            (Some(time), Some(start)) => time - start,
+            _ => index as f64,
+        };
+        if points.last().is_some_and(|prev: &TrackPoint| offset < prev.offset) {
+            return Err(format!("point {} is earlier than the previous one", index + 1));
+        }
+        points.push(TrackPoint { offset, lat, lon, alt });
+    }
+    Ok((points, start))
+}
+
+/// Разбирает точки `<trkpt>` и `<rtept>` файла GPX с необязательными `<ele>` и `<time>`.
+fn parse_gpx(text: &str) -> Result<(Vec<TrackPoint>, Option<f64>), String> {
+    let mut raw = Vec::new();
+    let mut rest = text;
+    while let Some(open) = ["<trkpt", "<rtept"].iter().filter_map(|tag| rest.find(tag)).min() {
+        rest = &rest[open..];
+        let head_end = rest.find('>').ok_or("unterminated track point")?;
+        let head = &rest[..head_end];
+        // Точка без вложенных элементов: `<trkpt lat=".." lon=".."/>`
+        let body_end = match head.ends_with('/') {
+            true => head_end,
+            false => rest.find("</trkpt>").into_iter().chain(rest.find("</rtept>")).min().unwrap_or(head_end),
+        };
+        let body = &rest[head_end..body_end];
+        let lat = attribute(head, "lat").ok_or("track point without 'lat'")?;
+        let lon = attribute(head, "lon").ok_or("track point without 'lon'")?;
+        let alt = element(body, "ele").map(|ele| ele.parse().map_err(|_| format!("invalid elevation '{}'", ele)));
+        let time = element(body, "time").map(|time| parse_time(time).ok_or(format!("invalid time '{}'", time)));
+        raw.push((time.transpose()?, parse_coordinate(lat)?, parse_coordinate(lon)?, alt.transpose()?.unwrap_or(0.0)));
+        rest = &rest[body_end.max(1)..];
+    }
+    into_points(raw)
+}
+
+fn attribute<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    let start = head.find(&format!(" {}=", name))? + name.len() + 2;
+    let quote = head[start..].chars().next()?;
+    let value = &head[start + 1..];
+    Some(&value[..value.find(quote)?])
+}
+
+fn element<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = body[start..].find("</")?;
+    Some(body[start..start + end].trim())
+}
+
+fn parse_coordinate(text: &str) -> Result<f64, String> {
+    text.trim().parse().map_err(|_| format!("invalid coordinate '{}'", text))
+}
+
+/// Разбирает трек CSV: `time,lat,lon[,alt]`, где время — секунды от начала или UTC в ISO 8601.
+/// Пустые строки, комментарии `#` и заголовок `time,...` пропускаются.
+fn parse_csv(text: &str) -> Result<(Vec<TrackPoint>, Option<f64>), String> {
+    let mut raw = Vec::new();
+    let mut relative = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("time") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let point = match fields.as_slice() {
+            [time, lat, lon, alt @ ..] if alt.len() <= 1 => {
+                // Числовое время — смещение в секундах; время UTC точки тогда неизвестно
+                let (time, is_relative) = match time.parse::<f64>() {
+                    Ok(seconds) => (seconds, true),
+                    Err(_) => (parse_time(time).ok_or(format!("invalid time '{}'", time))?, false),
+                };
+                if *relative.get_or_insert(is_relative) != is_relative {
+                    Err("mixed relative and absolute times".to_string())
+                } else {
+                    let alt = match alt.first() {
+                        Some(alt) => alt.parse().map_err(|_| format!("invalid altitude '{}'", alt))?,
+                        None => 0.0,
+                    };
+                    Ok((Some(time), parse_coordinate(lat)?, parse_coordinate(lon)?, alt))
+                }
+            }
+            _ => Err("expected time,lat,lon[,alt]".to_string()),
+        };
+        raw.push(point.map_err(|e| format!("line {}: {}", index + 1, e))?);
+    }
+    let (points, start) = into_points(raw)?;
+    Ok((points, start.filter(|_| relative == Some(false))))
+}
+
+/// Разбирает время UTC вида `2024-05-01T12:30:00Z` (дробные секунды и смещение пояса
+/// допускаются) в секунды Unix.
+pub fn parse_time(text: &str) -> Option<f64> {
+    let (date, time) = text.trim().split_once(['T', ' '])?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    // Смещение пояса: `Z`, `+03:00` или `-05:00`
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(index) => {
+            let zone = &time[index..];
+            let offset = match zone {
+                "Z" => 0,
+                _ => {
+                    let sign = if zone.starts_with('-') { -1 } else { 1 };
+                    let (hours, minutes) = zone[1..].split_once(':').unwrap_or((&zone[1..], "0"));
+                    sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60)
+                }
+            };
+            (&time[..index], offset)
+        }
+        None => (time, 0),
+    };
+    let mut parts = time.splitn(3, ':');
+    let (hour, minute) = (parts.next()?.parse::<i64>().ok()?, parts.next()?.parse::<i64>().ok()?);
+    let second: f64 = parts.next().unwrap_or("0").parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second >= 61.0 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some((days * 86_400 + hour * 3600 + minute * 60 - offset) as f64 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_keeps_going_when_track_loops() {
+        let (points, start) = parse_csv("2024-01-01T00:00:00Z,55.0,37.0\n2024-01-01T00:00:10Z,55.001,37.0\n").unwrap();
+        let track = Track { kind: TrackKind::Recorded { points, start } };
+        let first = track.fix_at(5.0);
+        let looped = track.fix_at(15.0);
+        assert_eq!(looped.lat, first.lat);
+        assert_eq!(looped.time.unwrap() - first.time.unwrap(), 10.0);
+    }
+}
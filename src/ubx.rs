@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+use crate::calendar::civil_from_days;
+use crate::checksum::Checksum;
+use crate::device::{Device, DeviceContext};
+use crate::framer::{Framing, FramingSpec};
+use crate::hex::to_hex;
+use crate::nmea::{constellation, fix_interval, GEOID_SEPARATION, PDOP, USED_SATELLITES};
+use crate::track::{Fix, Playback};
+
+/// Синхробайты в начале каждого сообщения UBX.
+pub const SYNC: [u8; 2] = [0xB5, 0x62];
+
+const CLASS_NAV: u8 = 0x01;
+const CLASS_ACK: u8 = 0x05;
+const CLASS_CFG: u8 = 0x06;
+const CLASS_MON: u8 = 0x0A;
+
+const NAV_PVT: (u8, u8) = (CLASS_NAV, 0x07);
+const NAV_SAT: (u8, u8) = (CLASS_NAV, 0x35);
+const ACK_NAK: (u8, u8) = (CLASS_ACK, 0x00);
+const ACK_ACK: (u8, u8) = (CLASS_ACK, 0x01);
+const CFG_PRT: (u8, u8) = (CLASS_CFG, 0x00);
+const CFG_MSG: (u8, u8) = (CLASS_CFG, 0x01);
+const CFG_RST: (u8, u8) = (CLASS_CFG, 0x04);
+const CFG_RATE: (u8, u8) = (CLASS_CFG, 0x08);
+const MON_VER: (u8, u8) = (CLASS_MON, 0x04);
+
+/// Сообщения длиннее этого считаются ошибкой синхронизации.
+const MAX_PAYLOAD: usize = 4096;
+
+/// Порт UART1 в таблице частот CFG-MSG (DDC, UART1, UART2, USB, SPI, резерв).
+const UART1: usize = 1;
+
+/// Разница между шкалами GPS и UTC, с.
+const LEAP_SECONDS: i64 = 18;
+
+/// Начало шкалы GPS (1980-01-06) в секундах Unix.
+const GPS_EPOCH: i64 = 315_964_800;
+
+/// Собирает сообщение UBX: синхробайты, класс, идентификатор, длина, данные и контрольная сумма.
+pub fn encode(class: u8, id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = SYNC.to_vec();
+    frame.extend([class, id]);
+    frame.extend((payload.len() as u16).to_le_bytes());
+    frame.extend_from_slice(payload);
+    // Контрольная сумма не включает синхробайты
+    let sum = Checksum::Fletcher8.compute(&frame[2..]);
+    frame.extend(sum);
+    frame
+}
+
+/// Название сообщения для вывода: `CFG-RATE` или `06-42` для неизвестных.
+fn message_name((class, id): (u8, u8)) -> String {
+    let name = match (class, id) {
+        NAV_PVT => "NAV-PVT",
+        NAV_SAT => "NAV-SAT",
+        ACK_NAK => "ACK-NAK",
+        ACK_ACK => "ACK-ACK",
+        CFG_PRT => "CFG-PRT",
+        CFG_MSG => "CFG-MSG",
+        CFG_RST => "CFG-RST",
+        CFG_RATE => "CFG-RATE",
+        (CLASS_CFG, 0x09) => "CFG-CFG",
+        (CLASS_CFG, 0x24) => "CFG-NAV5",
+        (CLASS_CFG, 0x3E) => "CFG-GNSS",
+        (CLASS_CFG, 0x8A) => "CFG-VALSET",
+        MON_VER => "MON-VER",
+        _ => return format!("{:02X}-{:02X}", class, id),
+    };
+    name.to_string()
+}
+
+/// Приёмник u-blox, принимающий сообщения UBX: подтверждает настройку (`CFG-*`), отвечает
+/// на запросы и выдаёт NAV-PVT и NAV-SAT по треку с частотой из CFG-RATE и CFG-MSG.
+pub struct UbxReceiver {
+    playback: Playback,
+    /// Период решений по умолчанию, до CFG-RATE и после CFG-RST.
+    default_interval: Duration,
+    interval: Duration,
+    next_fix: Instant,
+    fixes: u64,
+    /// Частота периодических сообщений: раз в столько решений, 0 — выключено.
+    rates: HashMap<(u8, u8), u8>,
+    /// Последние данные принятых сообщений CFG, которые возвращаются на запрос.
+    config: HashMap<(u8, u8), Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl UbxReceiver {
+    /// `rate` — число решений в секунду до настройки через CFG-RATE.
+    pub fn new(playback: Playback, rate: f64) -> io::Result<Self> {
+        let interval = fix_interval(rate)?;
+        let mut receiver = UbxReceiver {
+            playback,
+            default_interval: interval,
+            interval,
+            next_fix: Instant::now(),
+            fixes: 0,
+            rates: HashMap::new(),
+            config: HashMap::new(),
+            buffer: Vec::new(),
+        };
+        receiver.reset();
+        Ok(receiver)
+    }
+
+    /// Настройки по умолчанию: NAV-PVT и NAV-SAT выдаются с каждым решением.
+    fn reset(&mut self) {
+        self.interval = self.default_interval;
+        self.rates = HashMap::from([(NAV_PVT, 1), (NAV_SAT, 1)]);
+        self.config.clear();
+    }
+
+    fn send(&self, message: (u8, u8), payload: &[u8], ctx: &DeviceContext) {
+        ctx.scheduler.send_now(encode(message.0, message.1, payload), "UBX");
+    }
+
+    fn acknowledge(&self, message: (u8, u8), ok: bool, ctx: &DeviceContext) {
+        let reply = if ok { ACK_ACK } else { ACK_NAK };
+        ctx.log(&format!("[UBX] {} -> {}", message_name(message), message_name(reply)));
+        self.send(reply, &[message.0, message.1], ctx);
+    }
+
+    fn handle_message(&mut self, message: (u8, u8), payload: &[u8], now: Instant, ctx: &DeviceContext) {
+        match (message, payload.len()) {
+            (CFG_MSG, 2) => {
+                // Запрос частоты сообщения: ответ с частотой на всех портах
+                let target = (payload[0], payload[1]);
+                let rate = self.rates.get(&target).copied().unwrap_or(0);
+                let mut reply = payload.to_vec();
+                reply.extend([rate; 6]);
+                self.send(CFG_MSG, &reply, ctx);
+                self.acknowledge(message, true, ctx);
+            }
+            (CFG_MSG, 3 | 8) => {
+                let target = (payload[0], payload[1]);
+                let rate = if payload.len() == 3 { payload[2] } else { payload[2 + UART1] };
+                self.rates.insert(target, rate);
+                ctx.log(&format!("[UBX] {} output rate set to {}", message_name(target), rate));
+                self.acknowledge(message, true, ctx);
+            }
+            (CFG_RATE, 6) => {
+                let meas_rate = u16::from_le_bytes([payload[0], payload[1]]);
+                let nav_rate = u16::from_le_bytes([payload[2], payload[3]]);
+                let ok = meas_rate >= 25 && nav_rate >= 1;
+                if ok {
+                    self.interval = Duration::from_millis(meas_rate as u64 * nav_rate as u64);
+                    self.next_fix = now;
+                    self.config.insert(message, payload.to_vec());
+                    ctx.log(&format!("[UBX] Navigation rate set to {} ms", self.interval.as_millis()));
+                }
+                self.acknowledge(message, ok, ctx);
+            }
+            (CFG_RATE, 0) => {
+                let default = self.default_interval.as_millis().clamp(25, u16::MAX as u128) as u16;
+                let reply = self.config.get(&message).cloned().unwrap_or_else(|| {
+                    let mut reply = default.to_le_bytes().to_vec();
+                    reply.extend([1, 0, 1, 0]);
+                    reply
+                });
+                self.send(message, &reply, ctx);
+                self.acknowledge(message, true, ctx);
+            }
+            // CFG-RST перезапускает приёмник и не подтверждается
+            (CFG_RST, _) => {
+                self.reset();
+                self.next_fix = now;
+                ctx.log("[UBX] CFG-RST: configuration reset to defaults");
+            }
+            ((CLASS_CFG, _), 0) => match self.config.get(&message).cloned() {
+                Some(reply) => {
+                    self.send(message, &reply, ctx);
+                    self.acknowledge(message, true, ctx);
+                }
+                None => self.acknowledge(message, false, ctx),
+            },
+            ((CLASS_CFG, _), _) => {
+                self.config.insert(message, payload.to_vec());
+                self.acknowledge(message, true, ctx);
+            }
+            (NAV_PVT, 0) => {
+                let (fix, _) = self.playback.fix(now);
+                self.send(NAV_PVT, &nav_pvt(&fix), ctx);
+            }
+            (NAV_SAT, 0) => {
+                let (fix, elapsed) = self.playback.fix(now);
+                self.send(NAV_SAT, &nav_sat(&fix, elapsed), ctx);
+            }
+            (MON_VER, 0) => self.send(MON_VER, &mon_ver(), ctx),
+            _ => ctx.log(&format!("[UBX] Ignoring unsupported {} ({} byte(s))", message_name(message), payload.len())),
+        }
+    }
+}
+
+impl Device for UbxReceiver {
+    fn name(&self) -> &'static str {
+        "u-blox UBX receiver"
+    }
+
+    fn framing(&self, _baud_rate: u32) -> FramingSpec {
+        // Сообщения выделяются самим приёмником по длине; пауза только отделяет порции данных
+        FramingSpec { framing: Framing::Idle(Duration::from_millis(2)), escape: None, checksum: None }
+    }
+
+    fn on_frame(&mut self, frame: &[u8], ctx: &DeviceContext) -> bool {
+        self.buffer.extend_from_slice(frame);
+        let now = Instant::now();
+        let mut handled = false;
+        loop {
+            let Some(start) = self.buffer.windows(2).position(|pair| pair == SYNC) else {
+                // Данные без сообщений UBX (например, команды NMEA) передаются таблице команд;
+                // последний байт может оказаться началом синхропоследовательности
+                let keep = self.buffer.last() == Some(&SYNC[0]);
+                self.buffer.drain(..self.buffer.len() - keep as usize);
+                return handled || keep;
+            };
+            self.buffer.drain(..start);
+            handled = true;
+            if self.buffer.len() < 6 {
+                return true;
+            }
+            let len = u16::from_le_bytes([self.buffer[4], self.buffer[5]]) as usize;
+            if len > MAX_PAYLOAD {
+                self.buffer.drain(..SYNC.len());
+                continue;
+            }
+            if self.buffer.len() < 8 + len {
+                return true;
+            }
+            let frame: Vec<u8> = self.buffer[..8 + len].to_vec();
+            if !Checksum::Fletcher8.verify(&frame[2..]) {
+                // Сдвиг только на синхробайты: внутри испорченного кадра может начинаться следующий
+                ctx.log(&format!("[UBX] Bad checksum in {}, ignoring it", to_hex(&frame)));
+                self.buffer.drain(..SYNC.len());
+                continue;
+            }
+            self.buffer.drain(..frame.len());
+            let message = (frame[2], frame[3]);
+            let payload = &frame[6..6 + len];
+            ctx.log(&format!("[UBX] Received {} ({} byte(s))", message_name(message), len));
+            self.handle_message(message, payload, now, ctx);
+        }
+    }
+
+    fn on_tick(&mut self, now: Instant, ctx: &DeviceContext) {
+        if now < self.next_fix {
+            return;
+        }
+        self.next_fix = (self.next_fix + self.interval).max(now);
+        let (fix, elapsed) = self.playback.fix(now);
+        for message in [NAV_PVT, NAV_SAT] {
+            let rate = self.rates.get(&message).copied().unwrap_or(0) as u64;
+            if rate == 0 || !self.fixes.is_multiple_of(rate) {
+                continue;
+            }
+            let payload = if message == NAV_PVT { nav_pvt(&fix) } else { nav_sat(&fix, elapsed) };
+            self.send(message, &payload, ctx);
+        }
+        self.fixes += 1;
+    }
+
+    fn finish(&mut self, ctx: &DeviceContext) {
+        ctx.log(&format!("[UBX] Sent {} navigation solution(s)", self.fixes));
+    }
+}
+
+/// Время недели GPS в миллисекундах.
+fn itow(utc: f64) -> u32 {
+    let gps = (utc * 1000.0).round() as i64 + (LEAP_SECONDS - GPS_EPOCH) * 1000;
+    gps.rem_euclid(7 * 86_400_000) as u32
+}
+
+/// NAV-PVT (92 байта): трёхмерное решение с датой, положением и скоростью.
+fn nav_pvt(fix: &Fix) -> Vec<u8> {
+    let utc = fix.time.unwrap_or_default();
+    let seconds = utc.floor();
+    let nanos = ((utc - seconds) * 1e9) as i32;
+    let seconds = seconds as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let day_seconds = seconds.rem_euclid(86_400);
+    let course = fix.course.to_radians();
+    let mm = |meters: f64| (meters * 1000.0).round() as i32;
+
+    let mut p = Vec::with_capacity(92);
+    p.extend(itow(utc).to_le_bytes());
+    p.extend((year as u16).to_le_bytes());
+    p.extend([month as u8, day as u8]);
+    p.extend([(day_seconds / 3600) as u8, (day_seconds / 60 % 60) as u8, (day_seconds % 60) as u8]);
+    p.push(0x07); // validDate, validTime, fullyResolved
+    p.extend(30u32.to_le_bytes()); // tAcc, нс
+    p.extend(nanos.to_le_bytes());
+    p.push(3); // fixType: 3D
+    p.push(0x01); // flags: gnssFixOK
+    p.push(0x00); // flags2
+    p.push(USED_SATELLITES as u8);
+    p.extend(((fix.lon * 1e7).round() as i32).to_le_bytes());
+    p.extend(((fix.lat * 1e7).round() as i32).to_le_bytes());
+    p.extend(mm(fix.alt + GEOID_SEPARATION).to_le_bytes()); // над эллипсоидом
+    p.extend(mm(fix.alt).to_le_bytes()); // над уровнем моря
+    p.extend(1500u32.to_le_bytes()); // hAcc, мм
+    p.extend(2500u32.to_le_bytes()); // vAcc, мм
+    p.extend(mm(fix.speed * course.cos()).to_le_bytes()); // velN
+    p.extend(mm(fix.speed * course.sin()).to_le_bytes()); // velE
+    p.extend(0i32.to_le_bytes()); // velD
+    p.extend(mm(fix.speed).to_le_bytes()); // gSpeed
+    p.extend(((fix.course * 1e5).round() as i32).to_le_bytes()); // headMot
+    p.extend(300u32.to_le_bytes()); // sAcc, мм/с
+    p.extend(50_000u32.to_le_bytes()); // headAcc, 1e-5°
+    p.extend(((PDOP * 100.0) as u16).to_le_bytes());
+    p.extend([0u8; 6]); // flags3, резерв
+    p.extend(((fix.course * 1e5).round() as i32).to_le_bytes()); // headVeh
+    p.extend(0i16.to_le_bytes()); // magDec
+    p.extend(0u16.to_le_bytes()); // magAcc
+    p
+}
+
+/// NAV-SAT: спутники синтетического созвездия, использованные в решении отмечены флагом.
+fn nav_sat(fix: &Fix, elapsed: f64) -> Vec<u8> {
+    let satellites = constellation(elapsed);
+    let mut p = Vec::with_capacity(8 + 12 * satellites.len());
+    p.extend(itow(fix.time.unwrap_or_default()).to_le_bytes());
+    p.push(1); // version
+    p.push(satellites.len() as u8);
+    p.extend([0u8; 2]);
+    for (index, &(prn, elevation, azimuth, snr)) in satellites.iter().enumerate() {
+        // qualityInd 7 (код и фаза), health 1 (исправен), svUsed для использованных в решении
+        let used = if index < USED_SATELLITES { 0x08 } else { 0x00 };
+        let flags: u32 = 0x07 | used | 0x10;
+        p.extend([0, prn, snr as u8, elevation as u8]); // gnssId GPS, svId, cno, elev
+        p.extend((azimuth as i16).to_le_bytes());
+        p.extend(0i16.to_le_bytes()); // prRes
+        p.extend(flags.to_le_bytes());
+    }
+    p
+}
+
+/// MON-VER: версии ПО и оборудования и расширения по 30 байт, дополненные нулями.
+fn mon_ver() -> Vec<u8> {
+    let field = |text: &str, len: usize| {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(len, 0);
+        bytes
+    };
+    let mut p = field("ROM CORE 3.01 (107888)", 30);
+    p.extend(field("00080000", 10));
+    for extension in ["FWVER=SPG 3.01", "PROTVER=18.00", "GPS;GLO;GAL;BDS", "virtualport"] {
+        p.extend(field(extension, 30));
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use crate::scheduler::start_scheduler;
+    use crate::track::{Track, TrackSource};
+
+    /// Отправленное приёмником сообщение: класс и идентификатор, данные.
+    type Message = ((u8, u8), Vec<u8>);
+
+    fn receiver() -> UbxReceiver {
+        let track = Track::load(&TrackSource::Static { lat: 55.75, lon: 37.62, alt: 150.0 }).unwrap();
+        UbxReceiver::new(Playback::new(track, 1.0), 1.0).unwrap()
+    }
+
+    /// Передаёт данные приёмнику и возвращает результат `on_frame` и отправленные сообщения.
+    fn exchange(receiver: &mut UbxReceiver, name: &str, data: &[u8]) -> (bool, Vec<Message>) {
+        let path = std::env::temp_dir().join(format!("virtualport-{}-{}", std::process::id(), name));
+        let sink = Arc::new(File::create(&path).unwrap());
+        let (scheduler, handle) = start_scheduler(Arc::new(AtomicBool::new(true)), sink, None);
+        let handled = receiver.on_frame(data, &DeviceContext { scheduler: &scheduler, logger: &None });
+        drop(scheduler);
+        handle.join().unwrap();
+        let mut written = &fs::read(&path).unwrap()[..];
+        let _ = fs::remove_file(&path);
+        let mut messages = Vec::new();
+        while !written.is_empty() {
+            let len = u16::from_le_bytes([written[4], written[5]]) as usize;
+            assert_eq!(written[..8 + len], encode(written[2], written[3], &written[6..6 + len]));
+            messages.push(((written[2], written[3]), written[6..6 + len].to_vec()));
+            written = &written[8 + len..];
+        }
+        (handled, messages)
+    }
+
+    fn ack(message: (u8, u8)) -> Message {
+        (ACK_ACK, vec![message.0, message.1])
+    }
+
+    fn nak(message: (u8, u8)) -> Message {
+        (ACK_NAK, vec![message.0, message.1])
+    }
+
+    #[test]
+    fn resynchronizes_after_garbage_and_bad_checksum() {
+        let mut receiver = receiver();
+        let set_rate = encode(CFG_MSG.0, CFG_MSG.1, &[0x01, 0x07, 0x00]);
+        let mut data = vec![0x00, 0xB5, 0x00, 0x62];
+        data.extend(&set_rate);
+        assert_eq!(exchange(&mut receiver, "ubx-garbage", &data), (true, vec![ack(CFG_MSG)]));
+        assert_eq!(receiver.rates[&NAV_PVT], 0);
+        // Внутри кадра с испорченной суммой находится целое сообщение, которое не должно потеряться
+        let set_rate = encode(CFG_MSG.0, CFG_MSG.1, &[0x01, 0x07, 0x02]);
+        let mut corrupt = encode(CFG_PRT.0, CFG_PRT.1, &set_rate);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        assert_eq!(exchange(&mut receiver, "ubx-checksum", &corrupt), (true, vec![ack(CFG_MSG)]));
+        assert_eq!(receiver.rates[&NAV_PVT], 2);
+        assert!(receiver.buffer.is_empty());
+        // Сообщение, разделённое между порциями данных, собирается целиком
+        let poll = encode(MON_VER.0, MON_VER.1, &[]);
+        assert_eq!(exchange(&mut receiver, "ubx-split-1", &poll[..3]), (true, vec![]));
+        let (handled, messages) = exchange(&mut receiver, "ubx-split-2", &poll[3..]);
+        assert!(handled);
+        assert_eq!(messages, vec![(MON_VER, mon_ver())]);
+        // Данные без сообщений UBX передаются таблице команд
+        assert_eq!(exchange(&mut receiver, "ubx-nmea", b"$PUBX,00*33\r\n"), (false, vec![]));
+    }
+
+    #[test]
+    fn acknowledges_configuration() {
+        let mut receiver = receiver();
+        const CFG_NAV5: (u8, u8) = (CLASS_CFG, 0x24);
+        const CFG_GNSS: (u8, u8) = (CLASS_CFG, 0x3E);
+        let nav5 = vec![0xFF, 0xFF, 0x04, 0x03];
+        let (_, messages) = exchange(&mut receiver, "ubx-nav5", &encode(CFG_NAV5.0, CFG_NAV5.1, &nav5));
+        assert_eq!(messages, vec![ack(CFG_NAV5)]);
+        // Запрос возвращает последние принятые данные, запрос незаданной настройки отклоняется
+        let (_, messages) = exchange(&mut receiver, "ubx-nav5-poll", &encode(CFG_NAV5.0, CFG_NAV5.1, &[]));
+        assert_eq!(messages, vec![(CFG_NAV5, nav5), ack(CFG_NAV5)]);
+        let (_, messages) = exchange(&mut receiver, "ubx-gnss-poll", &encode(CFG_GNSS.0, CFG_GNSS.1, &[]));
+        assert_eq!(messages, vec![nak(CFG_GNSS)]);
+        let (_, messages) = exchange(&mut receiver, "ubx-msg-poll", &encode(CFG_MSG.0, CFG_MSG.1, &[0x01, 0x35]));
+        assert_eq!(messages, vec![(CFG_MSG, vec![0x01, 0x35, 1, 1, 1, 1, 1, 1]), ack(CFG_MSG)]);
+        // CFG-RST сбрасывает настройки без подтверждения
+        let (_, messages) = exchange(&mut receiver, "ubx-rst", &encode(CFG_RST.0, CFG_RST.1, &[0, 0, 1, 0]));
+        assert_eq!(messages, vec![]);
+        let (_, messages) = exchange(&mut receiver, "ubx-nav5-reset", &encode(CFG_NAV5.0, CFG_NAV5.1, &[]));
+        assert_eq!(messages, vec![nak(CFG_NAV5)]);
+    }
+
+    #[test]
+    fn sets_and_polls_navigation_rate() {
+        let mut receiver = receiver();
+        let poll = encode(CFG_RATE.0, CFG_RATE.1, &[]);
+        let (_, messages) = exchange(&mut receiver, "ubx-rate-default", &poll);
+        assert_eq!(messages, vec![(CFG_RATE, vec![0xE8, 0x03, 0x01, 0x00, 0x01, 0x00]), ack(CFG_RATE)]);
+        let rate = [0xC8, 0x00, 0x02, 0x00, 0x01, 0x00];
+        let (_, messages) = exchange(&mut receiver, "ubx-rate-set", &encode(CFG_RATE.0, CFG_RATE.1, &rate));
+        assert_eq!(messages, vec![ack(CFG_RATE)]);
+        assert_eq!(receiver.interval, Duration::from_millis(400));
+        // measRate меньше 25 мс и navRate 0 отклоняются без изменения периода
+        for invalid in [[0x0A, 0x00, 0x01, 0x00, 0x01, 0x00], [0xC8, 0x00, 0x00, 0x00, 0x01, 0x00]] {
+            let (_, messages) = exchange(&mut receiver, "ubx-rate-invalid", &encode(CFG_RATE.0, CFG_RATE.1, &invalid));
+            assert_eq!(messages, vec![nak(CFG_RATE)]);
+        }
+        assert_eq!(receiver.interval, Duration::from_millis(400));
+        let (_, messages) = exchange(&mut receiver, "ubx-rate-poll", &poll);
+        assert_eq!(messages, vec![(CFG_RATE, rate.to_vec()), ack(CFG_RATE)]);
+    }
+
+    #[test]
+    fn nav_pvt_is_92_bytes() {
+        let mut receiver = receiver();
+        let (_, messages) = exchange(&mut receiver, "ubx-pvt", &encode(NAV_PVT.0, NAV_PVT.1, &[]));
+        assert_eq!(messages.len(), 1);
+        let (message, payload) = &messages[0];
+        assert_eq!((*message, payload.len()), (NAV_PVT, 92));
+        // fixType 3D и широта в единицах 1e-7°
+        assert_eq!(payload[20], 3);
+        assert_eq!(i32::from_le_bytes(payload[28..32].try_into().unwrap()), 557_500_000);
+        let fix = Fix { time: Some(1.7e9), speed: 10.0, course: 90.0, ..Fix::default() };
+        assert_eq!(nav_pvt(&fix).len(), 92);
+    }
+}